@@ -0,0 +1,280 @@
+//! Offline command line interface for rolling dice and rendering clocks without a Discord token.
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    db::{ClockStorage, ClockTheme, DATABASE_PATH, DB, DiceMacro, ProgressClock},
+    dice::{handle_dice_string, stats::roll_statistics},
+    svg::{parse_color, render_progress_clock},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Number of rolls used to estimate odds when none is given.
+const DEFAULT_SAMPLES: usize = 100_000;
+
+static REPL_HELP: &str = "\
+<dice string>          roll dice, e.g. `hit: 1d20 + 5, damage: 1d8 + 3`
+<macro name>           roll a saved macro
+:odds <dice string>    estimate the odds of a dice string
+:history               list previous rolls
+:again [n]             roll the last (or n-th) entry in the history again
+:macros                list the saved macros in this namespace
+:save <name> <dice>    save a dice string as a macro
+:help                  show this message
+:quit                  leave the session";
+
+/// Troller runs the Discord bot when started without a subcommand.
+#[derive(Parser)]
+#[command(name = "troller", version, about = "A Discord bot to help run D&D.")]
+pub struct Cli {
+    /// Namespace to read macros and clocks from, e.g. `guild:<server id>` or `user:<user id>`.
+    #[arg(short, long, global = true, default_value = "local")]
+    pub namespace: String,
+
+    /// SQLite database the bot and these commands keep their data in.
+    #[arg(short, long, global = true, default_value = DATABASE_PATH)]
+    pub database: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Roll a dice string or a saved macro once.
+    Roll {
+        /// Dice string or macro name, e.g. `1d20 + 5`.
+        #[arg(required = true, num_args = 1..)]
+        dice_string: Vec<String>,
+    },
+    /// Estimate the odds of a dice string by rolling it many times.
+    Odds {
+        /// Dice string or macro name, e.g. `4d6h3`.
+        #[arg(required = true, num_args = 1..)]
+        dice_string: Vec<String>,
+        /// How many times to roll.
+        #[arg(short, long, default_value_t = DEFAULT_SAMPLES)]
+        samples: usize,
+    },
+    /// Render a progress clock to a PNG file.
+    Clock {
+        /// Name of a saved clock to render.
+        #[arg(long, conflicts_with = "segments")]
+        name: Option<String>,
+        /// Segments in an unsaved clock.
+        #[arg(long, required_unless_present = "name")]
+        segments: Option<u8>,
+        /// Filled segments in an unsaved clock.
+        #[arg(long, default_value_t = 0)]
+        filled: u8,
+        /// Colour of an unsaved clock (html name or hex code).
+        #[arg(long)]
+        color: Option<String>,
+        /// Where to write the PNG.
+        #[arg(short, long, default_value = "clock.png")]
+        output: PathBuf,
+    },
+    /// List the saved dice macros.
+    Macros,
+    /// Save a dice string as a macro.
+    SaveMacro {
+        /// Name of the macro.
+        name: String,
+        /// Dice string to save.
+        #[arg(required = true, num_args = 1..)]
+        dice_string: Vec<String>,
+    },
+    /// Start an interactive dice session.
+    Repl,
+}
+
+/// Returns the dice string of the macro called `input`, or `input` itself if there is none.
+/// Database errors other than a missing macro are passed on.
+fn resolve_dice_string(db: &DB, namespace: &String, input: &str) -> Result<String, Error> {
+    match db.get_macro(namespace, &input.trim().to_owned()) {
+        Ok(dice_macro) => Ok(dice_macro.dice_string),
+        Err(err) => match err.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::QueryReturnedNoRows) => Ok(input.to_owned()),
+            _ => Err(err),
+        },
+    }
+}
+
+fn print_roll(dice_string: String) -> Result<(), Error> {
+    let roll_results = handle_dice_string(dice_string).map_err(|err| err.to_string())?;
+    for result in roll_results {
+        println!("{}: {}", result.name, result.value);
+    }
+    Ok(())
+}
+
+fn print_odds(dice_string: &str, samples: usize) -> Result<(), Error> {
+    if samples == 0 {
+        return Err("need at least one sample to estimate odds.".into());
+    }
+    let statistics = roll_statistics(dice_string, samples).map_err(|err| err.to_string())?;
+    for item in statistics {
+        print!("{item}");
+    }
+    Ok(())
+}
+
+fn print_macros(db: &DB, namespace: &String) -> Result<(), Error> {
+    let macros = db.get_available_macros(namespace, "")?;
+    if macros.is_empty() {
+        println!("no macros saved in namespace `{namespace}`.");
+    }
+    for dice_macro in macros {
+        println!("{}: {}", dice_macro.name, dice_macro.dice_string);
+    }
+    Ok(())
+}
+
+fn save_macro(db: &DB, namespace: &str, name: &str, dice_string: &str) -> Result<(), Error> {
+    handle_dice_string(dice_string.to_owned()).map_err(|err| err.to_string())?;
+    db.save_macro(&DiceMacro {
+        namespace: namespace.to_owned(),
+        name: name.to_owned(),
+        dice_string: dice_string.to_owned(),
+    })?;
+    println!("saved macro {name}.");
+    Ok(())
+}
+
+/// Runs a single CLI subcommand.
+pub fn run(command: Command, namespace: &String, database: &Path) -> Result<(), Error> {
+    match command {
+        Command::Roll { dice_string } => {
+            let db = DB::open(database)?;
+            print_roll(resolve_dice_string(&db, namespace, &dice_string.join(" "))?)
+        }
+        Command::Odds {
+            dice_string,
+            samples,
+        } => {
+            let db = DB::open(database)?;
+            let dice_string = resolve_dice_string(&db, namespace, &dice_string.join(" "))?;
+            print_odds(&dice_string, samples)
+        }
+        Command::Clock {
+            name,
+            segments,
+            filled,
+            color,
+            output,
+        } => {
+            let (progress_clock, theme) = match name {
+                Some(name) => {
+                    let db = DB::open(database)?;
+                    let progress_clock = db.get_clock(namespace, &name).map_err(|_| {
+                        format!("no clock named `{name}` in namespace `{namespace}`.")
+                    })?;
+                    (progress_clock, db.get_clock_theme(namespace)?)
+                }
+                None => (
+                    ProgressClock {
+                        namespace: namespace.clone(),
+                        name: String::new(),
+                        segments: segments.ok_or("the number of segments is required.")?,
+                        segments_filled: filled,
+                        ephemeral: false,
+                        color: color.as_deref().map(parse_color).transpose()?,
+                    },
+                    ClockTheme::default(),
+                ),
+            };
+            std::fs::write(&output, render_progress_clock(&progress_clock, &theme)?)?;
+            println!("wrote progress clock to {}.", output.display());
+            Ok(())
+        }
+        Command::Macros => print_macros(&DB::open(database)?, namespace),
+        Command::SaveMacro { name, dice_string } => save_macro(
+            &DB::open(database)?,
+            namespace,
+            &name,
+            &dice_string.join(" "),
+        ),
+        Command::Repl => repl(namespace, database),
+    }
+}
+
+/// Runs an interactive session that rolls every line typed into it.
+fn repl(namespace: &String, database: &Path) -> Result<(), Error> {
+    let db = DB::open(database)?;
+    let mut history: Vec<String> = Vec::new();
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    println!("troller dice session for namespace `{namespace}`. Type :help for commands.");
+
+    loop {
+        print!("troller> ");
+        std::io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let result = match command {
+            "" => Ok(()),
+            ":quit" | ":q" => break,
+            ":help" => {
+                println!("{REPL_HELP}");
+                Ok(())
+            }
+            ":history" => {
+                for (idx, entry) in history.iter().enumerate() {
+                    println!("{:>4}  {}", idx + 1, entry);
+                }
+                Ok(())
+            }
+            ":again" => {
+                let entry = match argument.parse::<usize>() {
+                    Ok(n) => n.checked_sub(1).and_then(|idx| history.get(idx)),
+                    Err(_) => history.last(),
+                };
+                match entry.cloned() {
+                    Some(entry) => {
+                        println!("{entry}");
+                        let result =
+                            resolve_dice_string(&db, namespace, &entry).and_then(print_roll);
+                        history.push(entry);
+                        result
+                    }
+                    None => Err("no such entry in the history.".into()),
+                }
+            }
+            ":odds" => resolve_dice_string(&db, namespace, argument)
+                .and_then(|dice_string| print_odds(&dice_string, DEFAULT_SAMPLES)),
+            ":macros" => print_macros(&db, namespace),
+            ":save" => match argument.split_once(' ') {
+                Some((name, dice_string)) => save_macro(&db, namespace, name, dice_string.trim()),
+                None => Err("USAGE: :save <name> <dice string>".into()),
+            },
+            _ if command.starts_with(':') => Err(format!("unknown command {command}.").into()),
+            _ => {
+                let result = resolve_dice_string(&db, namespace, line).and_then(print_roll);
+                if result.is_ok() {
+                    history.push(line.to_owned());
+                }
+                result
+            }
+        };
+
+        if let Err(err) = result {
+            println!("error: {err}");
+        }
+    }
+
+    Ok(())
+}
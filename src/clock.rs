@@ -0,0 +1,582 @@
+//! Rules for creating and changing progress clocks, and for describing their history.
+
+use crate::db::{
+    ClockAccess, ClockCategory, ClockEvent, ClockExpiry, ClockLabels, ClockLinkKind, ClockOrder,
+    ClockStorage, ClockStyle, ClockTemplate, ClockTheme, ExportFormat, ImportConflict,
+    ProgressClock,
+};
+use crate::svg::parse_color;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// What a resized clock keeps of its progress.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum FillMode {
+    /// A half-full clock stays half full.
+    #[name = "Keep the fill ratio"]
+    Ratio,
+    /// The same number of segments stay filled, as far as they fit.
+    #[name = "Keep the filled segments"]
+    Absolute,
+}
+
+/// How long an ephemeral clock lasts.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Lifetime {
+    #[name = "Hours"]
+    Hours,
+    #[name = "Days"]
+    Days,
+    /// Until `/clock end_session`.
+    #[name = "Until the session ends"]
+    Session,
+}
+
+/// Clocks last at most ten years, which keeps their expiry well within the dates SQLite can work with.
+const MAX_LIFETIME_HOURS: u32 = 10 * 365 * 24;
+
+impl Lifetime {
+    /// When a clock made now expires, if it lasts `amount` hours or days.
+    pub fn expiry(self, amount: u32) -> ClockExpiry {
+        match self {
+            Lifetime::Hours => ClockExpiry::Hours(amount.min(MAX_LIFETIME_HOURS)),
+            Lifetime::Days => ClockExpiry::Hours(amount.saturating_mul(24).min(MAX_LIFETIME_HOURS)),
+            Lifetime::Session => ClockExpiry::SessionEnd,
+        }
+    }
+}
+
+/// How one clock is linked to another.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum LinkKind {
+    /// The two race to fill first.
+    #[name = "Racing"]
+    Race,
+    /// The clock ticks the other when it fills.
+    #[name = "Child of"]
+    Child,
+    /// The two fill one clock from opposite sides.
+    #[name = "Tug of war"]
+    TugOfWar,
+}
+
+impl LinkKind {
+    pub fn kind(self) -> ClockLinkKind {
+        match self {
+            LinkKind::Race => ClockLinkKind::Race,
+            LinkKind::Child => ClockLinkKind::Child,
+            LinkKind::TugOfWar => ClockLinkKind::TugOfWar,
+        }
+    }
+}
+
+/// Who may see and change a clock, besides the GM.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Access {
+    #[name = "Everyone"]
+    Everyone,
+    /// Players can show the clock, but not change it.
+    #[name = "Players can only view"]
+    ViewOnly,
+    /// Players can't see the clock at all.
+    #[name = "GM only"]
+    GmOnly,
+}
+
+impl Access {
+    pub fn access(self) -> ClockAccess {
+        match self {
+            Access::Everyone => ClockAccess::Everyone,
+            Access::ViewOnly => ClockAccess::ViewOnly,
+            Access::GmOnly => ClockAccess::GmOnly,
+        }
+    }
+}
+
+/// What kind of thing a clock tracks.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Category {
+    #[name = "Faction"]
+    Faction,
+    #[name = "Project"]
+    Project,
+    #[name = "Threat"]
+    Threat,
+    #[name = "Long-term"]
+    LongTerm,
+}
+
+impl Category {
+    pub fn category(self) -> ClockCategory {
+        match self {
+            Category::Faction => ClockCategory::Faction,
+            Category::Project => ClockCategory::Project,
+            Category::Threat => ClockCategory::Threat,
+            Category::LongTerm => ClockCategory::LongTerm,
+        }
+    }
+}
+
+/// The order clocks are listed in.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Sort {
+    #[name = "Name"]
+    Name,
+    #[name = "Most complete first"]
+    MostComplete,
+    #[name = "Least complete first"]
+    LeastComplete,
+    #[name = "Newest first"]
+    Newest,
+}
+
+impl Sort {
+    pub fn order(self) -> ClockOrder {
+        match self {
+            Sort::Name => ClockOrder::Name,
+            Sort::MostComplete => ClockOrder::MostComplete,
+            Sort::LeastComplete => ClockOrder::LeastComplete,
+            Sort::Newest => ClockOrder::Newest,
+        }
+    }
+}
+
+/// Which kind of file clocks are exported to.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum FileFormat {
+    #[name = "JSON"]
+    Json,
+    #[name = "YAML"]
+    Yaml,
+}
+
+impl FileFormat {
+    pub fn format(self) -> ExportFormat {
+        match self {
+            FileFormat::Json => ExportFormat::Json,
+            FileFormat::Yaml => ExportFormat::Yaml,
+        }
+    }
+}
+
+/// What an import does with a clock whose name is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Conflict {
+    #[name = "Keep the clock that's here"]
+    Skip,
+    #[name = "Replace it with the imported clock"]
+    Overwrite,
+    #[name = "Import it under a new name"]
+    Rename,
+}
+
+impl Conflict {
+    pub fn conflict(self) -> ImportConflict {
+        match self {
+            Conflict::Skip => ImportConflict::Skip,
+            Conflict::Overwrite => ImportConflict::Overwrite,
+            Conflict::Rename => ImportConflict::Rename,
+        }
+    }
+}
+
+/// Changes to make to a clock. Properties left as `None` stay as they are.
+pub struct ClockEdit {
+    pub name: Option<String>,
+    pub segments: Option<u8>,
+    pub fill_mode: FillMode,
+    pub color: Option<String>,
+    pub ephemeral: Option<bool>,
+}
+
+/// The shape clocks are drawn as.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Style {
+    #[name = "Pie"]
+    Pie,
+    #[name = "Segmented ring"]
+    Ring,
+    #[name = "Bar"]
+    Bar,
+}
+
+impl Style {
+    pub fn style(self) -> ClockStyle {
+        match self {
+            Style::Pie => ClockStyle::Pie,
+            Style::Ring => ClockStyle::Ring,
+            Style::Bar => ClockStyle::Bar,
+        }
+    }
+}
+
+/// A look for every clock in a server.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum ThemePreset {
+    /// White spokes, with green clocks.
+    #[name = "Classic"]
+    Classic,
+    /// Pale spokes on near-black, with the colours of blood, gold and cold steel.
+    #[name = "Duskvol"]
+    Duskvol,
+    /// Ink on old paper.
+    #[name = "Parchment"]
+    Parchment,
+    /// Bright colours on a deep purple night.
+    #[name = "Neon"]
+    Neon,
+    /// Greys on black, for servers that would rather not have colour.
+    #[name = "Monochrome"]
+    Monochrome,
+    /// Dark spokes and strong colours, for Discord's light theme.
+    #[name = "Daylight"]
+    Daylight,
+    /// Big, bold rings with nothing behind them, to lay over a stream.
+    #[name = "Stream overlay"]
+    Overlay,
+}
+
+impl ThemePreset {
+    pub fn theme(self) -> ClockTheme {
+        let (palette, spoke_color, background): (&[&str], &str, Option<&str>) = match self {
+            ThemePreset::Classic => return ClockTheme::default(),
+            ThemePreset::Duskvol => (
+                &["#b3202a", "#c9a227", "#4f7c99", "#6b8e4e"],
+                "#e8e0cc",
+                Some("#16161a"),
+            ),
+            ThemePreset::Parchment => (
+                &["#8b2e16", "#2f4f2f", "#28476b", "#6b4226"],
+                "#3b2f24",
+                Some("#f1e3c6"),
+            ),
+            ThemePreset::Neon => (
+                &["#ff2bd6", "#00e5ff", "#76ff03", "#ffea00"],
+                "#f5f5ff",
+                Some("#120026"),
+            ),
+            ThemePreset::Monochrome => (&["#9e9e9e", "#6e6e6e"], "#f0f0f0", Some("#000")),
+            ThemePreset::Daylight => {
+                return ClockTheme {
+                    palette: vec![
+                        String::from("#1b7f3a"),
+                        String::from("#c62828"),
+                        String::from("#1565c0"),
+                    ],
+                    spoke_color: String::from("#2b2b2b"),
+                    shade_opacity: 0.7,
+                    ..ClockTheme::default()
+                };
+            }
+            ThemePreset::Overlay => {
+                return ClockTheme {
+                    palette: vec![String::from("#ff4d4d"), String::from("#4dd2ff")],
+                    style: ClockStyle::Ring,
+                    size: 400,
+                    shade_opacity: 0.85,
+                    ..ClockTheme::default()
+                };
+            }
+        };
+        ClockTheme {
+            palette: palette.iter().map(|color| (*color).to_owned()).collect(),
+            spoke_color: spoke_color.to_owned(),
+            background: background.map(str::to_owned),
+            ..ClockTheme::default()
+        }
+    }
+}
+
+/// Templates every server has, for the clocks Blades in the Dark uses most.
+/// A server's own template with the same name is used in place of a built-in one.
+pub fn built_in_templates() -> Vec<ClockTemplate> {
+    [
+        ("Faction Clock (4)", 4, None, false, ClockCategory::Faction),
+        ("Faction Clock (6)", 6, None, false, ClockCategory::Faction),
+        ("Faction Clock (8)", 8, None, false, ClockCategory::Faction),
+        ("Long-Term Project", 8, None, false, ClockCategory::Project),
+        ("Healing", 4, None, false, ClockCategory::LongTerm),
+        ("Heist Alarm", 4, Some("red"), true, ClockCategory::Threat),
+    ]
+    .into_iter()
+    .map(
+        |(name, segments, color, ephemeral, category)| ClockTemplate {
+            namespace: String::new(),
+            name: name.to_owned(),
+            segments,
+            color: color.map(str::to_owned),
+            ephemeral,
+            labels: ClockLabels {
+                category: Some(category),
+                tags: vec![],
+            },
+        },
+    )
+    .collect()
+}
+
+/// Checks that `progress_clock` is a sensible new clock, then adds it to `storage`.
+pub fn create_clock<S: ClockStorage + ?Sized>(
+    storage: &S,
+    progress_clock: &ProgressClock,
+    actor: &str,
+) -> Result<(), Error> {
+    check_new_clock(progress_clock)?;
+    let mut progress_clock = progress_clock.clone();
+    progress_clock.color = progress_clock
+        .color
+        .as_deref()
+        .map(parse_color)
+        .transpose()?;
+    storage.save_clock(&progress_clock, actor)
+}
+
+/// Checks that `progress_clock` is a sensible clock to add, whether it's made here or imported.
+pub fn check_new_clock(progress_clock: &ProgressClock) -> Result<(), Error> {
+    if progress_clock.name.trim().is_empty() {
+        return Err("A clock's name can't be empty.".into());
+    }
+    if progress_clock.segments == 0 {
+        return Err("A clock needs at least one segment.".into());
+    }
+    if progress_clock.segments_filled > progress_clock.segments {
+        return Err(format!(
+            "A clock with {} segments can't have {} filled.",
+            progress_clock.segments, progress_clock.segments_filled
+        )
+        .into());
+    }
+    if let Some(color) = &progress_clock.color {
+        parse_color(color)?;
+    }
+    Ok(())
+}
+
+/// Fills `count` more segments of a clock in `storage`, and returns the clock afterwards.
+pub fn bump_clock<S: ClockStorage + ?Sized>(
+    storage: &S,
+    namespace: &str,
+    name: &str,
+    count: u8,
+    actor: &str,
+) -> Result<ProgressClock, Error> {
+    storage.bump_clock(namespace, name, count, actor)?;
+    storage.get_clock(namespace, name)
+}
+
+impl ProgressClock {
+    /// Changes the number of segments, carrying the clock's progress over according to `fill_mode`.
+    pub fn resize(&mut self, segments: u8, fill_mode: FillMode) -> Result<(), String> {
+        if segments == 0 {
+            return Err(String::from("A clock needs at least one segment."));
+        }
+
+        self.segments_filled = match fill_mode {
+            FillMode::Ratio => (f64::from(self.segments_filled) * f64::from(segments)
+                / f64::from(self.segments.max(1)))
+            .round() as u8,
+            FillMode::Absolute => self.segments_filled,
+        }
+        .min(segments);
+        self.segments = segments;
+        Ok(())
+    }
+
+    /// Applies every change in `edit`, or none of them if any is invalid.
+    pub fn edit(&mut self, edit: ClockEdit) -> Result<(), String> {
+        let mut edited = self.clone();
+        edited.ephemeral = edit.ephemeral.unwrap_or(self.ephemeral);
+
+        if let Some(name) = edit.name {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(String::from("A clock's name can't be empty."));
+            }
+            edited.name = name.to_owned();
+        }
+
+        if let Some(segments) = edit.segments {
+            edited.resize(segments, edit.fill_mode)?;
+        }
+
+        if let Some(color) = edit.color {
+            edited.color = Some(parse_color(&color)?);
+        }
+
+        *self = edited;
+        Ok(())
+    }
+}
+
+impl ClockEvent {
+    /// Describes what the change did, such as `2/6 → 3/6`.
+    pub fn describe(&self) -> String {
+        let (before, after) = match (&self.before, &self.after) {
+            (Some(before), Some(after)) => (before, after),
+            (None, Some(after)) => {
+                return format!("created with {}/{}", after.segments_filled, after.segments);
+            }
+            _ => return String::from("removed"),
+        };
+
+        let mut changes = vec![];
+        if before.name != after.name {
+            changes.push(format!("renamed from {}", before.name));
+        }
+        if (before.segments_filled, before.segments) != (after.segments_filled, after.segments) {
+            changes.push(format!(
+                "{}/{} → {}/{}",
+                before.segments_filled, before.segments, after.segments_filled, after.segments
+            ));
+        }
+        if before.color != after.color {
+            changes.push(format!(
+                "colour {} → {}",
+                before.color.as_deref().unwrap_or("theme"),
+                after.color.as_deref().unwrap_or("theme")
+            ));
+        }
+        if before.ephemeral != after.ephemeral {
+            changes.push(String::from(if after.ephemeral {
+                "now expires"
+            } else {
+                "no longer expires"
+            }));
+        }
+
+        if changes.is_empty() {
+            String::from("no change")
+        } else {
+            changes.join(", ")
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn test_clock(segments: u8, segments_filled: u8) -> ProgressClock {
+    ProgressClock {
+        namespace: String::from("guild"),
+        name: String::from("ritual"),
+        segments,
+        segments_filled,
+        ephemeral: false,
+        color: None,
+    }
+}
+
+#[test]
+fn test_create_and_bump_clock() -> Result<(), Error> {
+    use crate::db::MemoryStorage;
+
+    let storage = MemoryStorage::new();
+    let (namespace, name) = ("guild", "ritual");
+
+    create_clock(&storage, &test_clock(4, 2), "gm")?;
+    assert!(create_clock(&storage, &test_clock(4, 2), "gm").is_err());
+    assert!(create_clock(&storage, &test_clock(0, 0), "gm").is_err());
+    assert!(create_clock(&storage, &test_clock(4, 5), "gm").is_err());
+
+    let clock = bump_clock(&storage, namespace, name, 1, "gm")?;
+    assert_eq!((clock.segments, clock.segments_filled), (4, 3));
+    let clock = bump_clock(&storage, namespace, name, 5, "gm")?;
+    assert_eq!((clock.segments, clock.segments_filled), (4, 4));
+    assert!(bump_clock(&storage, namespace, "missing", 1, "gm").is_err());
+
+    assert_eq!(storage.get_available_clocks(namespace, "RIT")?.len(), 1);
+    storage.remove_clock(namespace, name, "gm")?;
+    assert!(storage.get_clock(namespace, name).is_err());
+    assert!(storage.get_available_clocks(namespace, "")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_clock_resize() -> Result<(), String> {
+    let mut clock = test_clock(4, 2);
+    clock.resize(8, FillMode::Ratio)?;
+    assert_eq!((clock.segments, clock.segments_filled), (8, 4));
+    clock.resize(6, FillMode::Absolute)?;
+    assert_eq!((clock.segments, clock.segments_filled), (6, 4));
+    clock.resize(3, FillMode::Absolute)?;
+    assert_eq!((clock.segments, clock.segments_filled), (3, 3));
+    assert!(clock.resize(0, FillMode::Ratio).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_clock_edit() -> Result<(), String> {
+    let mut clock = test_clock(6, 3);
+    clock.edit(ClockEdit {
+        name: Some(String::from(" Dark Ritual ")),
+        segments: Some(4),
+        fill_mode: FillMode::Ratio,
+        color: Some(String::from("#a0f")),
+        ephemeral: None,
+    })?;
+    assert_eq!(clock.name, "Dark Ritual");
+    assert_eq!((clock.segments, clock.segments_filled), (4, 2));
+    assert_eq!(clock.color.as_deref(), Some("#a0f"));
+
+    // an invalid change leaves the clock untouched.
+    let invalid = clock.edit(ClockEdit {
+        name: Some(String::from("Renamed")),
+        segments: None,
+        fill_mode: FillMode::Ratio,
+        color: Some(String::from("#12345")),
+        ephemeral: Some(true),
+    });
+    assert!(invalid.is_err());
+    assert_eq!(clock.name, "Dark Ritual");
+    assert!(!clock.ephemeral);
+    Ok(())
+}
+
+#[test]
+fn test_lifetime_expiry() {
+    assert_eq!(Lifetime::Days.expiry(2), ClockExpiry::Hours(48));
+    assert_eq!(
+        Lifetime::Days.expiry(u32::MAX),
+        ClockExpiry::Hours(MAX_LIFETIME_HOURS)
+    );
+}
+
+#[test]
+fn test_clock_event_describe() {
+    let mut event = ClockEvent {
+        id: 1,
+        action: String::from("edit"),
+        actor: String::from("gm"),
+        time: 0,
+        undone: false,
+        before: Some(test_clock(4, 2)),
+        after: None,
+    };
+    assert_eq!(event.describe(), "removed");
+
+    let mut after = test_clock(8, 4);
+    after.name = String::from("dark ritual");
+    event.after = Some(after);
+    assert_eq!(event.describe(), "renamed from ritual, 2/4 → 4/8");
+
+    event.before = None;
+    assert_eq!(event.describe(), "created with 4/8");
+}
+
+#[test]
+fn test_built_in_templates_make_valid_clocks() -> Result<(), Error> {
+    let templates = built_in_templates();
+    for template in &templates {
+        check_new_clock(&ProgressClock {
+            namespace: String::from("guild"),
+            name: template.name.clone(),
+            segments: template.segments,
+            segments_filled: 0,
+            ephemeral: template.ephemeral,
+            color: template.color.clone(),
+        })?;
+        let same_name = templates
+            .iter()
+            .filter(|other| other.name.eq_ignore_ascii_case(&template.name));
+        assert_eq!(same_name.count(), 1);
+    }
+    Ok(())
+}
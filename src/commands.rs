@@ -1,30 +1,45 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+pub mod clock;
+pub mod clocks;
+pub mod gm_role;
+pub mod groupcheck;
+pub mod initiative;
+pub mod inline_rolls;
+pub mod pins;
+pub mod roll_buttons;
+pub mod scope;
+pub mod tables;
+pub mod templates;
+
+use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
-    db::{DB, ProgressClock},
-    dice::handle_dice_string,
+    clock::{Access, Category, Lifetime, bump_clock, create_clock},
+    commands::templates::{clock_template_autocomplete, find_clock_template},
+    db::{
+        ClockAccess, ClockLabels, ClockStorage, ClockTemplate, ClockTheme, DbPool, PinnedMessage,
+        ProgressClock, RollMessage,
+    },
+    dice::{RollResult, handle_dice_string},
+    events::fire_clock_completions,
     svg::render_progress_clock,
 };
-use futures::lock::Mutex;
 use poise::serenity_prelude::futures::{self, Stream};
-use poise::serenity_prelude::{CreateAttachment, CreateEmbed};
+use poise::serenity_prelude::{CreateAttachment, CreateEmbed, RoleId};
 
 pub struct Data {
-    pub db: Mutex<DB>,
+    pub db: DbPool,
     pub music_dir: PathBuf,
-    pub track_list: Arc<Mutex<Vec<String>>>,
 }
-type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
-static EMBED_OK_TUPLE: &'static (u8, u8, u8) = &(118, 164, 93);
-static EMBED_ERR_TUPLE: &'static (u8, u8, u8) = &(159, 7, 18);
+static EMBED_OK_TUPLE: &(u8, u8, u8) = &(118, 164, 93);
+static EMBED_ERR_TUPLE: &(u8, u8, u8) = &(159, 7, 18);
 
 /// Breaks up a string and capitalizes every word.
 fn capitalize_string(input: &str) -> String {
     let words: Vec<String> = input
         .split_whitespace()
-        .into_iter()
         .map(|word| {
             let mut w = word.to_lowercase();
             w.replace_range(0..1, &w[0..1].to_uppercase());
@@ -42,13 +57,200 @@ fn create_error_embed<'a>(title: &'a str, message: &'a str) -> CreateEmbed {
         .field("", message, false)
 }
 
-fn create_quick_success_embed<'a>(title: &'a str, message: &'a str) -> CreateEmbed {
+pub fn create_quick_success_embed<'a>(title: &'a str, message: &'a str) -> CreateEmbed {
     CreateEmbed::new()
         .color(*EMBED_OK_TUPLE)
         .title(capitalize_string(title))
         .field("", message, false)
 }
 
+/// The most characters Discord shows in an embed field, as messages are put in embeds.
+pub const EMBED_FIELD_LIMIT: usize = 1024;
+
+/// Cuts `text` down to `max_chars` characters, ending it with `…` if anything was cut.
+pub fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+    let mut shortened: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    shortened.push('…');
+    shortened
+}
+
+/// Joins `lines` one to a line, keeping to `EMBED_FIELD_LIMIT`.
+/// Lines that don't fit are left off, and counted at the end instead.
+pub fn join_lines_to_fit(lines: &[String]) -> String {
+    let joined = lines.join("\n");
+    if joined.chars().count() <= EMBED_FIELD_LIMIT {
+        return joined;
+    }
+
+    // leaves room to say how many were left off.
+    let room = EMBED_FIELD_LIMIT - 32;
+    let mut kept = Vec::new();
+    let mut length = 0;
+    for line in lines {
+        length += line.chars().count() + 1;
+        if length > room {
+            break;
+        }
+        kept.push(line.as_str());
+    }
+    let more = format!("…and {} more", lines.len() - kept.len());
+    kept.push(&more);
+    kept.join("\n")
+}
+
+/// Returns the namespace clocks, macros and tables are stored under: the guild's ID, or the author's ID in DMs,
+/// narrowed to a channel or campaign if one was picked with `/scope`.
+async fn get_namespace(ctx: Context<'_>) -> Result<String, Error> {
+    let namespace = match ctx.guild_id() {
+        Some(guild_id) => format!("guild:{guild_id}"),
+        None => format!("user:{}", ctx.author().id),
+    };
+
+    let channel_id = ctx.channel_id().get();
+    match ctx
+        .data()
+        .db
+        .read(move |db| db.get_namespace_scope(channel_id))
+        .await
+    {
+        Ok(Some(scope)) => Ok(format!("{namespace}/{scope}")),
+        Ok(None) => Ok(namespace),
+        // falling back to the whole server would quietly change clocks in the wrong campaign.
+        Err(e) => {
+            println!("{}", e);
+            Err("Could not look up which campaign this channel belongs to.".into())
+        }
+    }
+}
+
+/// Whether the author is a GM here: they have the server's GM role, or can manage the server.
+/// Everyone is the GM of their own clocks, in DMs.
+pub async fn is_gm(ctx: Context<'_>) -> bool {
+    let Some(guild_id) = ctx.guild_id() else {
+        return true;
+    };
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    if member
+        .permissions
+        .is_some_and(|permissions| permissions.manage_guild())
+    {
+        return true;
+    }
+
+    match ctx
+        .data()
+        .db
+        .read(move |db| db.get_gm_role(guild_id.get()))
+        .await
+    {
+        Ok(Some(role_id)) => member.roles.contains(&RoleId::new(role_id)),
+        Ok(None) => false,
+        Err(e) => {
+            println!("{}", e);
+            false
+        }
+    }
+}
+
+/// Checks that the author may see the clock called `name`, or change it if `change` is set.
+/// If they may not, replies saying so and returns false. GM-only clocks are treated as though they don't exist.
+async fn may_access_clock(
+    ctx: Context<'_>,
+    namespace: &str,
+    name: &str,
+    change: bool,
+) -> Result<bool, Error> {
+    let (access_namespace, access_name) = (namespace.to_owned(), name.to_owned());
+    // a missing clock is left for the command to report.
+    let Ok(access) = ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_access(&access_namespace, &access_name))
+        .await
+    else {
+        return Ok(true);
+    };
+
+    let refusal = match access {
+        ClockAccess::Everyone => return Ok(true),
+        ClockAccess::ViewOnly if !change => return Ok(true),
+        ClockAccess::ViewOnly => format!("Only the GM can change {name}."),
+        ClockAccess::GmOnly => format!("There's no clock named {name}."),
+    };
+    if is_gm(ctx).await {
+        return Ok(true);
+    }
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![create_error_embed("clock error", &refusal)],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+    Ok(false)
+}
+
+/// Moves the author's clocks, macros and tables from before namespaces used IDs over to their ID.
+/// Guilds are moved over as the bot connects to them, in `events::event_handler`.
+pub async fn migrate_user_namespace(ctx: Context<'_>) {
+    if ctx.guild_id().is_some() {
+        return;
+    }
+
+    let legacy_name = ctx.author().name.clone();
+    let namespace = format!("user:{}", ctx.author().id);
+    let owner_since = ctx.author().id.created_at().unix_timestamp();
+    let checked_name = legacy_name.clone();
+    match ctx
+        .data()
+        .db
+        .read(move |db| db.is_legacy_namespace(&checked_name, owner_since))
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    }
+    if let Err(e) = ctx
+        .data()
+        .db
+        .write(move |db| db.migrate_legacy_namespace(&legacy_name, &namespace, owner_since))
+        .await
+    {
+        println!("{}", e);
+    }
+}
+
+/// Rolls `dice_string` and returns the result of its first expression.
+fn roll_first(dice_string: String) -> Result<RollResult, String> {
+    handle_dice_string(dice_string)
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .next()
+        .ok_or(String::from("The dice string did not contain a roll."))
+}
+
+/// Returns a `CreateEmbed` listing each roll result as its own field.
+fn create_roll_embed(title: &str, roll_results: &[RollResult]) -> CreateEmbed {
+    CreateEmbed::new()
+        .color(*EMBED_OK_TUPLE)
+        .title(title)
+        .fields(
+            roll_results
+                .iter()
+                .map(|result| (result.name.clone(), result.value.clone(), false)),
+        )
+}
+
 pub async fn help_command_autocomplete<'a>(
     ctx: Context<'_>,
     partial: &'a str,
@@ -87,7 +289,7 @@ pub async fn help(
 
     let embed = match cmd_descriptions.get(&command_name) {
         Some(help_text) => {
-            create_quick_success_embed(&format!("Help for `/{}`", command_name.clone()), &help_text)
+            create_quick_success_embed(&format!("Help for `/{}`", command_name.clone()), help_text)
         }
         None => create_error_embed(
             "Invalid command name",
@@ -117,66 +319,292 @@ pub async fn help(
 /// `/roll 4d6h3`: Rolls 4 d6s and takes the highest three.
 /// `/roll 2d20l1`: Rolls 2 d20s and takes the lowest one.
 /// `/roll 5 * 3d6`: Multiplies 5 to the result of the 3d6 roll. It *does not* roll 15 sets of dice.
+///
+/// The reply has buttons to reroll, roll again with advantage, or add a +2 bardic inspiration.
 #[poise::command(slash_command)]
 pub async fn roll(
     ctx: Context<'_>,
     #[description = "Dice string to roll."] dice_string: String,
     #[description = "Keep roll private?"] keep_private: Option<bool>,
 ) -> Result<(), Error> {
-    let response = match handle_dice_string(dice_string) {
-        Ok(valid_response) => CreateEmbed::new()
-            .color(*EMBED_OK_TUPLE)
-            .title("Roll Result")
-            .fields(
-                valid_response
-                    .iter()
-                    .map(|result| (result.name.clone(), result.value.clone(), false)),
+    let (response, components) = match handle_dice_string(dice_string.clone()) {
+        Ok(valid_response) => (
+            create_roll_embed("Roll Result", &valid_response),
+            Some(vec![roll_buttons::create_roll_buttons()]),
+        ),
+        Err(_) => (
+            create_error_embed(
+                "Roll Error",
+                "The entered dice text was not valid. Take a look at the /help command for a guide on how to use the bot!",
             ),
-        Err(_) => create_error_embed(
-            "Roll Error",
-            "The entered dice text was not valid. Take a look at the /help command for a guide on how to use the bot!",
+            None,
         ),
     };
+    let has_buttons = components.is_some();
+    let reply_handle = ctx
+        .send(poise::CreateReply {
+            embeds: vec![response],
+            components,
+            ephemeral: keep_private,
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+
+    if has_buttons {
+        let message = reply_handle.message().await?;
+        let roll_message = RollMessage {
+            message_id: message.id.get(),
+            user_id: ctx.author().id.get(),
+            dice_string,
+        };
+        ctx.data()
+            .db
+            .write(move |db| db.save_roll_message(&roll_message))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Renders a clock into an embed, with the image it shows.
+fn clock_message(
+    progress_clock: &ProgressClock,
+    theme: &ClockTheme,
+) -> Result<(CreateEmbed, CreateAttachment), Error> {
+    let png_data = render_progress_clock(progress_clock, theme)?;
+    let embed = CreateEmbed::new()
+        .title(capitalize_string(&progress_clock.name))
+        .image("attachment://clock.png")
+        .color(*EMBED_OK_TUPLE);
+    Ok((embed, CreateAttachment::bytes(png_data, "clock.png")))
+}
+
+/// Renders a clock and posts it publicly in the channel. GM-only clocks are only shown to the author.
+async fn send_clock(ctx: Context<'_>, progress_clock: &ProgressClock) -> Result<(), Error> {
+    let (namespace, name) = (
+        progress_clock.namespace.clone(),
+        progress_clock.name.clone(),
+    );
+    let (access, theme) = ctx
+        .data()
+        .db
+        .read(move |db| {
+            Ok((
+                db.get_clock_access(&namespace, &name)?,
+                db.get_clock_theme(&namespace)?,
+            ))
+        })
+        .await?;
+    let hidden = access == ClockAccess::GmOnly;
+    let (embed, attachment) = clock_message(progress_clock, &theme)?;
+
     ctx.send(poise::CreateReply {
-        embeds: vec![response],
-        ephemeral: keep_private,
+        embeds: vec![embed],
+        attachments: vec![attachment],
+        ephemeral: Some(hidden),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Posts a clock that was just changed, unless one of the `pinned` messages already shows it in this channel.
+/// `pinned` are the messages that were updated for the change.
+async fn send_changed_clock(
+    ctx: Context<'_>,
+    progress_clock: &ProgressClock,
+    pinned: &[PinnedMessage],
+) -> Result<(), Error> {
+    let pinned_here = pinned.iter().any(|pinned_message| {
+        pinned_message.channel_id == ctx.channel_id().get()
+            && pinned_message
+                .clock_name
+                .as_ref()
+                .is_none_or(|name| *name == progress_clock.name)
+    });
+    if !pinned_here {
+        return send_clock(ctx, progress_clock).await;
+    }
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![create_quick_success_embed(
+            &format!("updated {}", progress_clock.name),
+            "The clock pinned in this channel is up to date.",
+        )],
+        ephemeral: Some(true),
         reply: true,
         ..Default::default()
     })
     .await?;
+
     Ok(())
 }
 
+/// Acts on the clocks in this namespace that filled up, announcing them in this channel unless their trigger
+/// names another. Commands that change clocks call this once they've replied, so announcements follow the change.
+pub async fn fire_completions_here(ctx: Context<'_>) {
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return;
+    };
+    if let Err(e) = fire_clock_completions(
+        ctx.serenity_context(),
+        &ctx.data().db,
+        &ctx.data().music_dir,
+        Some((&namespace, ctx.channel_id())),
+    )
+    .await
+    {
+        println!("{}", e);
+    }
+}
+
 /// Create a new progress clock to track goals or countdowns.
 ///
 /// **Example Usage:**
 /// `/add_progress_clock segments:6 name:Escape Plan` - Create a 6-segment clock named "Escape Plan"
 /// `/add_progress_clock segments:8 name:Ritual segments_filled:3 color:#FF5733 display_now:true` - Create an 8-segment clock with 3 segments already filled, custom color, and display immediately
+/// `/add_progress_clock segments:4 name:Guards Alerted lifetime:Hours lasts:3 announce_expiry:true` - Create a clock that expires in 3 hours, with a message here when it does
+/// `/add_progress_clock segments:6 name:Collapse lifetime:Until the session ends` - Create a clock that expires on `/clock end_session`
+/// `/add_progress_clock segments:8 name:Cult's Plan access:GM only` - Create a clock the players can't see
+/// `/add_progress_clock segments:8 name:Lampblacks category:Faction tags:crows, docks` - Create a clock filed under factions, tagged "crows" and "docks"
+/// `/add_progress_clock name:Bluecoat Patrol template:Heist Alarm` - Create a clock from a template, here a red, ephemeral 4-segment one
+///
+/// Either `segments` or a `template` is needed. Templates are saved with `/clock_template save`.
 #[poise::command(slash_command)]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_progress_clock(
     ctx: Context<'_>,
-    #[description = "How many segments does the clock have?"] segments: u8,
+    #[description = "What's the name of the clock?"] name: String,
+    #[description = "Start from a template (options given here override it)"]
+    #[autocomplete = "clock_template_autocomplete"]
+    template: Option<String>,
+    #[description = "How many segments does the clock have?"] segments: Option<u8>,
     #[description = "How many segments are already filled?"] segments_filled: Option<u8>,
     #[description = "Delete clock after a day?"] ephemeral: Option<bool>,
-    #[description = "What's the name of the clock?"] name: String,
     #[description = "What's the colour of the clock (html name or hex code)"] color: Option<String>,
     #[description = "Display now?"] display_now: Option<bool>,
+    #[description = "How long until the clock expires? (default: a day, if ephemeral)"]
+    lifetime: Option<Lifetime>,
+    #[description = "How many hours or days the clock lasts (default: 1)"]
+    #[min = 1]
+    #[max = 3650]
+    lasts: Option<u32>,
+    #[description = "Post a message in this channel when the clock expires?"]
+    announce_expiry: Option<bool>,
+    #[description = "Who can see and change the clock? (default: everyone)"] access: Option<Access>,
+    #[description = "What kind of thing does the clock track?"] category: Option<Category>,
+    #[description = "Tags to find the clock by, separated by commas"]
+    #[autocomplete = "clock_tag_autocomplete"]
+    tags: Option<String>,
 ) -> Result<(), Error> {
+    let access = access.map_or(ClockAccess::Everyone, Access::access);
+    if access != ClockAccess::Everyone && !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "clock error",
+                "Only the GM can make clocks hidden from players.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let namespace = get_namespace(ctx).await?;
+    let template = match template {
+        Some(template_name) => match find_clock_template(ctx, &namespace, &template_name).await {
+            Some(template) => Some(template),
+            None => {
+                ctx.send(poise::CreateReply {
+                    embeds: vec![create_error_embed(
+                        "clock error",
+                        &format!("There's no template named {template_name}."),
+                    )],
+                    ephemeral: Some(true),
+                    reply: true,
+                    ..Default::default()
+                })
+                .await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let Some(segments) = segments.or(template.as_ref().map(|template| template.segments)) else {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "clock error",
+                "Say how many segments the clock has, or pick a template.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    };
+    let template = template.unwrap_or(ClockTemplate {
+        namespace: namespace.clone(),
+        name: String::new(),
+        segments,
+        color: None,
+        ephemeral: false,
+        labels: ClockLabels::default(),
+    });
+
     let progress_clock = ProgressClock {
-        namespace: ctx
-            .guild()
-            .map(|guild| guild.name.clone())
-            .unwrap_or(ctx.author().name.clone()),
+        namespace,
         name: name.clone(),
-        segments: segments,
+        segments,
         segments_filled: segments_filled.unwrap_or(0),
-        ephemeral: ephemeral.unwrap_or(false),
-        color: color,
+        ephemeral: ephemeral.unwrap_or(template.ephemeral) || lifetime.is_some(),
+        color: color.or(template.color),
     };
 
-    let db = ctx.data().db.lock().await;
-
-    match db.save_clock(&progress_clock) {
+    let expiry = lifetime.map(|lifetime| lifetime.expiry(lasts.unwrap_or(1)));
+    let announce_channel_id = announce_expiry
+        .unwrap_or(false)
+        .then_some(ctx.channel_id().get());
+
+    let saved_clock = progress_clock.clone();
+    let actor = ctx.author().display_name().to_owned();
+    match ctx
+        .data()
+        .db
+        .write(move |db| {
+            create_clock(db, &saved_clock, &actor)?;
+            if let Some(expiry) = expiry {
+                db.set_clock_expiry(&saved_clock.namespace, &saved_clock.name, expiry)?;
+            }
+            if saved_clock.ephemeral && announce_channel_id.is_some() {
+                db.set_clock_announcement(
+                    &saved_clock.namespace,
+                    &saved_clock.name,
+                    announce_channel_id,
+                )?;
+            }
+            if access != ClockAccess::Everyone {
+                db.set_clock_access(&saved_clock.namespace, &saved_clock.name, access)?;
+            }
+            let labels = ClockLabels {
+                category: category
+                    .map(Category::category)
+                    .or(template.labels.category),
+                tags: tags
+                    .as_deref()
+                    .map(ClockLabels::parse_tags)
+                    .unwrap_or(template.labels.tags),
+            };
+            if labels != ClockLabels::default() {
+                db.set_clock_labels(&saved_clock.namespace, &saved_clock.name, &labels)?;
+            }
+            Ok(())
+        })
+        .await
+    {
         Ok(_) => {
             let reply_embed = CreateEmbed::new()
                 .color(*EMBED_OK_TUPLE)
@@ -190,27 +618,20 @@ pub async fn add_progress_clock(
             })
             .await?;
 
-            match display_now {
-                Some(true) => {
-                    let png_data = render_progress_clock(&progress_clock)?;
-                    ctx.send(poise::CreateReply {
-                        embeds: vec![
-                            CreateEmbed::new()
-                                .title(capitalize_string(&progress_clock.name))
-                                .image("attachment://clock.png")
-                                .color(*EMBED_OK_TUPLE),
-                        ],
-                        attachments: vec![CreateAttachment::bytes(png_data, "clock.png")],
-                        ephemeral: Some(false),
-                        ..Default::default()
-                    })
-                    .await?;
-                }
-                _ => {}
+            pins::refresh_pinned_messages(
+                ctx.http(),
+                &ctx.data().db,
+                &progress_clock.namespace,
+                Some(&progress_clock.name),
+            )
+            .await;
+
+            if let Some(true) = display_now {
+                send_clock(ctx, &progress_clock).await?;
             }
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("{}", e);
             ctx.send(poise::CreateReply {
                 embeds: vec![create_error_embed(
                     "internal error",
@@ -237,17 +658,30 @@ pub async fn remove_progress_clock(
     #[autocomplete = "display_clock_name_autocomplete"]
     name: String,
 ) -> Result<(), Error> {
-    let db = ctx.data().db.lock().await;
-    match db.remove_clock(
-        &ctx.guild()
-            .map(|guild| guild.name.clone())
-            .unwrap_or(ctx.author().name.clone()),
-        &name,
-    ) {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let actor = ctx.author().display_name().to_owned();
+    let (removed_namespace, removed_name) = (namespace.clone(), name.clone());
+    match ctx
+        .data()
+        .db
+        .write(move |db| db.remove_clock(&removed_namespace, &removed_name, &actor))
+        .await
+    {
         Ok(_) => {
+            pins::refresh_pinned_messages(ctx.http(), &ctx.data().db, &namespace, Some(&name))
+                .await;
+
             let reply_embed = CreateEmbed::new()
                 .color(*EMBED_OK_TUPLE)
-                .title("Removed clock.");
+                .title("Removed clock.")
+                .field(
+                    "",
+                    "Use `/clock undo` within a day to bring it back.",
+                    false,
+                );
 
             ctx.send(poise::CreateReply {
                 content: None,
@@ -258,7 +692,7 @@ pub async fn remove_progress_clock(
             .await?;
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("{}", e);
             ctx.send(poise::CreateReply {
                 embeds: vec![create_error_embed(
                     "internal error",
@@ -289,39 +723,31 @@ pub async fn bump_progress_clock(
     name: String,
     #[description = "Bump by how much?"] count: Option<u8>,
 ) -> Result<(), Error> {
-    let db = ctx.data().db.lock().await;
-    match db.bump_clock(
-        &ctx.guild()
-            .map(|guild| guild.name.clone())
-            .unwrap_or(ctx.author().name.clone()),
-        &name,
-        count.unwrap_or(1),
-    ) {
-        Ok(_) => {
-            let progress_clock = db.get_clock(
-                &ctx.guild()
-                    .map(|guild| guild.name.clone())
-                    .unwrap_or(ctx.author().name.clone()),
-                &name,
-            )?;
-
-            let png_data = render_progress_clock(&progress_clock)?;
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let actor = ctx.author().display_name().to_owned();
+    let bumped = ctx
+        .data()
+        .db
+        .write(move |db| bump_clock(db, &namespace, &name, count.unwrap_or(1), &actor))
+        .await;
 
-            ctx.send(poise::CreateReply {
-                embeds: vec![
-                    CreateEmbed::new()
-                        .title(capitalize_string(&progress_clock.name))
-                        .image("attachment://clock.png")
-                        .color(*EMBED_OK_TUPLE),
-                ],
-                attachments: vec![CreateAttachment::bytes(png_data, "clock.png")],
-                ephemeral: Some(false),
-                ..Default::default()
-            })
-            .await?;
+    match bumped {
+        Ok(progress_clock) => {
+            let pinned = pins::refresh_pinned_messages(
+                ctx.http(),
+                &ctx.data().db,
+                &progress_clock.namespace,
+                Some(&progress_clock.name),
+            )
+            .await;
+            send_changed_clock(ctx, &progress_clock, &pinned).await?;
+            fire_completions_here(ctx).await;
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("{}", e);
             ctx.send(poise::CreateReply {
                 embeds: vec![create_error_embed(
                     "internal error",
@@ -337,21 +763,23 @@ pub async fn bump_progress_clock(
     Ok(())
 }
 
-pub async fn display_clock_name_autocomplete<'a>(
+pub async fn dice_macro_autocomplete<'a>(
     ctx: Context<'_>,
     partial: &'a str,
 ) -> impl Stream<Item = String> + 'a {
-    let db = ctx.data().db.lock().await;
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return futures::stream::iter(vec![]);
+    };
+    let partial = partial.to_owned();
     let items = ctx
-        .guild()
-        .map_or_else(
-            || db.get_available_clocks(&ctx.author().name, partial),
-            |namespace| db.get_available_clocks(&namespace.name, partial),
-        )
-        .map(|progress_clocks| {
-            progress_clocks
+        .data()
+        .db
+        .read(move |db| db.get_available_macros(&namespace, &partial))
+        .await
+        .map(|dice_macros| {
+            dice_macros
                 .iter()
-                .map(|pclock| pclock.name.clone())
+                .map(|dice_macro| dice_macro.name.clone())
                 .collect()
         })
         .unwrap_or(vec![]);
@@ -359,6 +787,60 @@ pub async fn display_clock_name_autocomplete<'a>(
     futures::stream::iter(items)
 }
 
+pub async fn display_clock_name_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Stream<Item = String> + 'a {
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return futures::stream::iter(vec![]);
+    };
+    let partial = partial.to_owned();
+    let gm = is_gm(ctx).await;
+    let items = ctx
+        .data()
+        .db
+        .read(move |db| {
+            let accesses = db.get_clock_accesses(&namespace)?;
+            Ok(db
+                .get_available_clocks(&namespace, &partial)?
+                .into_iter()
+                .filter(|pclock| gm || accesses.get(&pclock.name) != Some(&ClockAccess::GmOnly))
+                .map(|pclock| pclock.name)
+                .collect())
+        })
+        .await
+        .unwrap_or(vec![]);
+
+    futures::stream::iter(items)
+}
+
+/// Suggests tags already used in this server. Only the last of several comma-separated tags is completed.
+pub async fn clock_tag_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Stream<Item = String> + 'a {
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return futures::stream::iter(vec![]);
+    };
+    let gm = is_gm(ctx).await;
+    let (before, last) = match partial.rsplit_once(',') {
+        Some((before, last)) => (format!("{before}, "), last.trim().to_lowercase()),
+        None => (String::new(), partial.trim().to_lowercase()),
+    };
+    let items: Vec<String> = ctx
+        .data()
+        .db
+        .read(move |db| db.get_tags(&namespace, gm))
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tag| tag.contains(&last))
+        .map(|tag| format!("{before}{tag}"))
+        .collect();
+
+    futures::stream::iter(items)
+}
+
 /// Show an existing progress clock.
 ///
 /// **Example Usage:**
@@ -371,37 +853,20 @@ pub async fn display_clock(
     #[autocomplete = "display_clock_name_autocomplete"]
     name: String,
 ) -> Result<(), Error> {
-    let db = ctx.data().db.lock().await;
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, false).await? {
+        return Ok(());
+    }
     let items = ctx
-        .guild()
-        .map_or_else(
-            || db.get_available_clocks(&ctx.author().name, ""),
-            |guild| db.get_available_clocks(&guild.name, ""),
-        )
+        .data()
+        .db
+        .read(move |db| db.get_available_clocks(&namespace, ""))
+        .await
         .unwrap_or_default();
 
-    match items.iter().find(|item| (**item).name.cmp(&name).is_eq()) {
+    match items.iter().find(|item| item.name.cmp(&name).is_eq()) {
         Some(progress_clock) => {
-            let attachment = match render_progress_clock(progress_clock) {
-                Ok(png_data) => vec![CreateAttachment::bytes(png_data, "clock.png")],
-                Err(_) => {
-                    vec![]
-                }
-            };
-
-            ctx.send(poise::CreateReply {
-                embeds: vec![
-                    CreateEmbed::new()
-                        .title(capitalize_string(&progress_clock.name))
-                        .image("attachment://clock.png")
-                        .color(*EMBED_OK_TUPLE),
-                ],
-                attachments: attachment,
-                ephemeral: Some(false),
-                reply: false,
-                ..Default::default()
-            })
-            .await?;
+            send_clock(ctx, progress_clock).await?;
         }
         None => {
             ctx.send(poise::CreateReply {
@@ -474,7 +939,7 @@ pub mod play_music {
             B,
         ) -> Result<A, Error>,
     {
-        let manager = songbird::get(&ctx.serenity_context())
+        let manager = songbird::get(ctx.serenity_context())
             .await
             .expect("Could not find serenity maanger");
         match ctx.guild_id() {
@@ -527,7 +992,7 @@ pub mod play_music {
                             .to_str()
                             .expect("Couldn't convert non-utf8 path to string.")
                             .to_owned();
-                        if interim_entry.starts_with(&partial) {
+                        if interim_entry.starts_with(partial) {
                             Some(interim_entry)
                         } else {
                             None
@@ -555,7 +1020,7 @@ pub mod play_music {
 
         ctx.defer().await?;
 
-        let manager = songbird::get(&ctx.serenity_context())
+        let manager = songbird::get(ctx.serenity_context())
             .await
             .expect("Could not find serenity maanger");
 
@@ -634,7 +1099,7 @@ pub mod play_music {
 
                 match input {
                     Some(input) => {
-                        let handle = call.enqueue_input(input.into()).await;
+                        let handle = call.enqueue_input(input).await;
                         if !play_now.unwrap_or(false) {
                             handle.pause()?;
                         }
@@ -763,3 +1228,17 @@ pub mod play_music {
         Ok(())
     }
 }
+
+#[test]
+fn test_join_lines_to_fit() {
+    let lines: Vec<String> = (0..300).map(|n| format!("line {n}")).collect();
+    assert_eq!(join_lines_to_fit(&lines[..3]), "line 0\nline 1\nline 2");
+
+    let joined = join_lines_to_fit(&lines);
+    assert!(joined.chars().count() <= EMBED_FIELD_LIMIT);
+    assert!(joined.starts_with("line 0\n"));
+    assert!(joined.ends_with(" more"), "{joined}");
+
+    assert_eq!(shorten("ritual", 10), "ritual");
+    assert_eq!(shorten("ritual", 4), "rit…");
+}
@@ -0,0 +1,1117 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    clock::{Access, Category, ClockEdit, FillMode, LinkKind},
+    commands::{
+        Context, EMBED_FIELD_LIMIT, EMBED_OK_TUPLE, Error, clock_tag_autocomplete,
+        clocks::describe_labels,
+        create_error_embed, create_quick_success_embed, display_clock_name_autocomplete,
+        fire_completions_here, get_namespace, is_gm, join_lines_to_fit, may_access_clock,
+        pins::{pin_message, refresh_pinned_messages},
+        play_music::music_file_autocomplete,
+        send_changed_clock,
+    },
+    db::{
+        ClockAccess, ClockEvent, ClockLabels, ClockLink, ClockLinkKind, ClockSchedule,
+        ClockStorage, ClockTrigger, DB, MAX_CLOCK_TAGS, ProgressClock,
+    },
+    events::{find_sound, purge_clocks},
+    svg::{ClockGroup, render_clock_group},
+};
+
+/// Applies `change` to the named clock, then posts the clock as it is afterwards.
+/// `change` is given the name of whoever made the change, and returns the clock's name afterwards, as it may have been renamed.
+async fn change_clock<F>(ctx: Context<'_>, name: &str, change: F) -> Result<(), Error>
+where
+    F: FnOnce(&DB, &String, &String, &str) -> Result<String, Error> + Send + 'static,
+{
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, name, true).await? {
+        return Ok(());
+    }
+    let name = name.to_owned();
+    let actor = ctx.author().display_name().to_owned();
+
+    let changed = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let name = change(db, &namespace, &name, &actor)?;
+            db.get_clock(&namespace, &name)
+                .map_err(|_| format!("There's no clock named {name}.").into())
+        })
+        .await;
+
+    match changed {
+        Ok(progress_clock) => {
+            let pinned = refresh_pinned_messages(
+                ctx.http(),
+                &ctx.data().db,
+                &progress_clock.namespace,
+                Some(&progress_clock.name),
+            )
+            .await;
+            send_changed_clock(ctx, &progress_clock, &pinned).await?;
+            fire_completions_here(ctx).await;
+        }
+        Err(e) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed("clock error", &e.to_string())],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Set exactly how many segments of a progress clock are filled.
+///
+/// **Example Usage:**
+/// `/clock set name:Escape Plan filled:3` - Fill exactly 3 segments of "Escape Plan"
+#[poise::command(slash_command)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "How many segments are filled?"] filled: u8,
+) -> Result<(), Error> {
+    change_clock(ctx, &name, move |db, namespace, name, actor| {
+        db.set_clock(namespace, name, filled, actor)?;
+        Ok(name.clone())
+    })
+    .await
+}
+
+/// Empty segments of a progress clock, such as when a danger recedes.
+///
+/// **Example Usage:**
+/// `/clock tick_down name:Alarm` - Empty 1 segment of "Alarm"
+/// `/clock tick_down name:Alarm count:2` - Empty 2 segments of "Alarm"
+#[poise::command(slash_command)]
+pub async fn tick_down(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Tick down by how much?"] count: Option<u8>,
+) -> Result<(), Error> {
+    change_clock(ctx, &name, move |db, namespace, name, actor| {
+        db.tick_down_clock(namespace, name, count.unwrap_or(1), actor)?;
+        Ok(name.clone())
+    })
+    .await
+}
+
+/// Empty every segment of a progress clock.
+///
+/// **Example Usage:**
+/// `/clock reset name:Alarm` - Start "Alarm" over from zero
+#[poise::command(slash_command)]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    change_clock(ctx, &name, |db, namespace, name, actor| {
+        db.reset_clock(namespace, name, actor)?;
+        Ok(name.clone())
+    })
+    .await
+}
+
+/// Rename, resize or recolour a progress clock without losing its progress.
+///
+/// **Example Usage:**
+/// `/clock edit name:Escape Plam new_name:Escape Plan` - Fix a typo in a clock's name
+/// `/clock edit name:Ritual segments:8` - Resize "Ritual" to 8 segments, keeping how full it is
+/// `/clock edit name:Ritual segments:8 keep:Keep the filled segments` - Resize "Ritual", keeping the same segments filled
+/// `/clock edit name:Ritual color:#FF5733 ephemeral:false` - Recolour "Ritual" and keep it past a day
+#[poise::command(slash_command)]
+pub async fn edit(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "What's the new name of the clock?"] new_name: Option<String>,
+    #[description = "How many segments should the clock have?"] segments: Option<u8>,
+    #[description = "What should a resized clock keep? (default: the fill ratio)"] keep: Option<
+        FillMode,
+    >,
+    #[description = "What's the colour of the clock (html name or hex code)"] color: Option<String>,
+    #[description = "Delete clock after a day?"] ephemeral: Option<bool>,
+) -> Result<(), Error> {
+    if new_name.is_none() && segments.is_none() && color.is_none() && ephemeral.is_none() {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "clock error",
+                "Pick at least one thing to change.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let edit = ClockEdit {
+        name: new_name,
+        segments,
+        fill_mode: keep.unwrap_or(FillMode::Ratio),
+        color,
+        ephemeral,
+    };
+
+    change_clock(ctx, &name, move |db, namespace, name, actor| {
+        let mut progress_clock = db
+            .get_clock(namespace, name)
+            .map_err(|_| format!("There's no clock named {name}."))?;
+        progress_clock.edit(edit)?;
+        db.update_clock(namespace, name, &progress_clock, actor)?;
+        Ok(progress_clock.name)
+    })
+    .await
+}
+
+/// List the latest changes to a progress clock.
+///
+/// **Example Usage:**
+/// `/clock history name:Escape Plan` - See who changed "Escape Plan", when, and how
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, false).await? {
+        return Ok(());
+    }
+    let history_name = name.clone();
+    let history = ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_history(&namespace, &history_name))
+        .await;
+
+    let embed = match history {
+        Ok(events) if events.is_empty() => create_error_embed(
+            "clock history",
+            &format!("There's no history for a clock named {name}."),
+        ),
+        Ok(events) => {
+            let lines: Vec<String> = events
+                .iter()
+                .map(|event| {
+                    format!(
+                        "<t:{}:R> **{}** by {} — {}{}",
+                        event.time,
+                        event.action,
+                        event.actor,
+                        event.describe(),
+                        if event.undone { " *(undone)*" } else { "" }
+                    )
+                })
+                .collect();
+            create_quick_success_embed(&format!("history of {name}"), &join_lines_to_fit(&lines))
+        }
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not load the clock's history.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Undoes or redoes a change with `revert`, then posts what changed and the clock as it is afterwards.
+/// `restored` picks the clock's state after reverting out of the change. Only GMs revert changes to restricted clocks.
+async fn revert_clock_change<F>(
+    ctx: Context<'_>,
+    title: &str,
+    revert: F,
+    restored: fn(&ClockEvent) -> Option<&ProgressClock>,
+) -> Result<(), Error>
+where
+    F: FnOnce(&DB, &String, bool) -> Result<ClockEvent, Error> + Send + 'static,
+{
+    let namespace = get_namespace(ctx).await?;
+    let reverted_namespace = namespace.clone();
+    let gm = is_gm(ctx).await;
+
+    match ctx
+        .data()
+        .db
+        .write(move |db| {
+            let event = revert(db, &reverted_namespace, gm)?;
+            // a clock that's gone can't say who may see it, so it's kept quiet too.
+            let hidden = db
+                .get_clock_access(&reverted_namespace, event.name())
+                .map_or(true, |access| access == ClockAccess::GmOnly);
+            Ok((event, hidden))
+        })
+        .await
+    {
+        Ok((event, hidden)) => {
+            // a rename may have been undone, so every pinned message is brought up to date.
+            let pinned =
+                refresh_pinned_messages(ctx.http(), &ctx.data().db, &namespace, None).await;
+
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_quick_success_embed(
+                    title,
+                    &format!(
+                        "{}'s {} of {}: {}.",
+                        event.actor,
+                        event.action,
+                        event.name(),
+                        event.describe()
+                    ),
+                )],
+                ephemeral: Some(hidden),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+
+            if let Some(progress_clock) = restored(&event) {
+                send_changed_clock(ctx, progress_clock, &pinned).await?;
+            }
+            fire_completions_here(ctx).await;
+        }
+        Err(e) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed("clock error", &e.to_string())],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Revert the latest change to a progress clock in this server.
+///
+/// **Example Usage:**
+/// `/clock undo` - Take back a mistaken bump, or bring back a clock removed in the last day
+#[poise::command(slash_command)]
+pub async fn undo(ctx: Context<'_>) -> Result<(), Error> {
+    revert_clock_change(
+        ctx,
+        "undid",
+        |db, namespace, gm| db.undo_clock_change(namespace, gm),
+        |event| event.before.as_ref(),
+    )
+    .await
+}
+
+/// Make the latest undone change to a progress clock again.
+#[poise::command(slash_command)]
+pub async fn redo(ctx: Context<'_>) -> Result<(), Error> {
+    revert_clock_change(
+        ctx,
+        "redid",
+        |db, namespace, gm| db.redo_clock_change(namespace, gm),
+        |event| event.after.as_ref(),
+    )
+    .await
+}
+
+/// Start the session, ticking every clock set to tick when a session starts.
+///
+/// **Example Usage:**
+/// `/clock start_session` - Advance the faction clocks that move between sessions
+#[poise::command(slash_command)]
+pub async fn start_session(ctx: Context<'_>) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "clock error",
+                "Only the GM can start the session.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+    let namespace = get_namespace(ctx).await?;
+    let actor = ctx.author().display_name().to_owned();
+    let ticked_namespace = namespace.clone();
+    let ticked = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let ticked = db.start_session(&ticked_namespace, &actor)?;
+            // the summary is public, so GM-only clocks tick quietly.
+            let accesses = db.get_clock_accesses(&ticked_namespace)?;
+            Ok(ticked
+                .into_iter()
+                .filter(|clock| accesses.get(&clock.name) != Some(&ClockAccess::GmOnly))
+                .collect::<Vec<_>>())
+        })
+        .await;
+
+    let (embed, ephemeral) = match ticked {
+        Ok(clocks) if clocks.is_empty() => (
+            create_quick_success_embed(
+                "started the session",
+                "No clocks are set to tick when a session starts.",
+            ),
+            true,
+        ),
+        Ok(clocks) => {
+            for progress_clock in &clocks {
+                refresh_pinned_messages(
+                    ctx.http(),
+                    &ctx.data().db,
+                    &namespace,
+                    Some(&progress_clock.name),
+                )
+                .await;
+            }
+            let lines: Vec<String> = clocks
+                .iter()
+                .map(|progress_clock| {
+                    format!(
+                        "**{}** ticked to {}/{}",
+                        progress_clock.name,
+                        progress_clock.segments_filled,
+                        progress_clock.segments
+                    )
+                })
+                .collect();
+            (
+                create_quick_success_embed("started the session", &join_lines_to_fit(&lines)),
+                false,
+            )
+        }
+        Err(e) => {
+            println!("{}", e);
+            (
+                create_error_embed("internal error", "Could not start the session."),
+                true,
+            )
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(ephemeral),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+    fire_completions_here(ctx).await;
+
+    Ok(())
+}
+
+/// End the session, expiring every clock made to last until the session ends.
+///
+/// **Example Usage:**
+/// `/clock end_session` - Clear away this session's clocks once the game wraps up
+#[poise::command(slash_command)]
+pub async fn end_session(ctx: Context<'_>) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "clock error",
+                "Only the GM can end the session.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+    let namespace = get_namespace(ctx).await?;
+
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.end_session(&namespace))
+        .await
+    {
+        Ok(0) => create_quick_success_embed(
+            "ended the session",
+            "No clocks were set to last until the session ends.",
+        ),
+        Ok(expired) => {
+            purge_clocks(ctx.http(), &ctx.data().db).await?;
+            create_quick_success_embed("ended the session", &format!("{expired} clocks expired."))
+        }
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not end the session.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Pin a progress clock here, kept up to date whenever it changes.
+///
+/// **Example Usage:**
+/// `/clock pin name:Escape Plan` - Post "Escape Plan" once, then edit that message on every bump
+///
+/// Delete the message to stop updating it.
+#[poise::command(slash_command, required_permissions = "MANAGE_MESSAGES")]
+pub async fn pin(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    if !may_access_clock(ctx, &get_namespace(ctx).await?, &name, false).await? {
+        return Ok(());
+    }
+    pin_message(ctx, Some(name)).await
+}
+
+/// Longest message a clock can post when it fills up, leaving room in the announcement for linked clocks.
+const MAX_TRIGGER_MESSAGE: usize = EMBED_FIELD_LIMIT - 200;
+
+/// Lists what a clock does when it fills up, one action per line.
+fn describe_trigger(trigger: &ClockTrigger) -> String {
+    if trigger.is_empty() {
+        return String::from("Nothing happens when it fills up.");
+    }
+
+    let mut actions = Vec::new();
+    if let Some(message) = &trigger.message {
+        actions.push(format!("Posts \"{message}\""));
+    }
+    if let Some(role_id) = trigger.role_id {
+        actions.push(format!("Pings <@&{role_id}>"));
+    }
+    if let Some(linked_clock) = &trigger.linked_clock {
+        actions.push(format!("Bumps {linked_clock} by {}", trigger.linked_count));
+    }
+    if let Some(sound) = &trigger.sound {
+        actions.push(format!("Plays {sound} if the bot is in a voice channel"));
+    }
+    actions.join("\n")
+}
+
+/// Choose what happens when a progress clock fills up.
+///
+/// **Example Usage:**
+/// `/clock on_complete name:Alarm message:The guards arrive! role:@Players` - Post a message and ping the players
+/// `/clock on_complete name:Ritual linked_clock:Demon Unleashed` - Start "Demon Unleashed" once "Ritual" fills
+/// `/clock on_complete name:Collapse sound:rumble.mp3` - Play a sound in the voice channel
+/// `/clock on_complete name:Alarm clear:true` - Stop "Alarm" doing anything when it fills
+///
+/// Options left out keep what they were set to. Messages are posted in the channel this is run in.
+#[poise::command(slash_command)]
+#[allow(clippy::too_many_arguments)]
+pub async fn on_complete(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "What to post when the clock fills up"] message: Option<String>,
+    #[description = "Which role to ping when the clock fills up"] role: Option<serenity::Role>,
+    #[description = "Which clock to bump when this one fills up"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    linked_clock: Option<String>,
+    #[description = "Bump the linked clock by how much? (default: 1)"] linked_count: Option<u8>,
+    #[description = "Which sound to play when the clock fills up"]
+    #[autocomplete = "music_file_autocomplete"]
+    sound: Option<String>,
+    #[description = "Stop the clock doing anything when it fills up?"] clear: Option<bool>,
+) -> Result<(), Error> {
+    let refusal = if let Some(sound) = &sound
+        && find_sound(&ctx.data().music_dir, sound).is_none()
+    {
+        Some(format!("There's no sound called {sound}."))
+    } else if message
+        .as_ref()
+        .is_some_and(|message| message.chars().count() > MAX_TRIGGER_MESSAGE)
+    {
+        Some(format!(
+            "Keep the message to {MAX_TRIGGER_MESSAGE} characters, so it fits in the announcement."
+        ))
+    } else if role.is_some() && !is_gm(ctx).await {
+        Some(String::from("Only the GM can pick a role to ping."))
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed("clock error", &refusal)],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let channel_id = ctx.channel_id().get();
+    let clock_name = name.clone();
+    let changed = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let mut trigger = match clear {
+                Some(true) => ClockTrigger::default(),
+                _ => db.get_clock_trigger(&namespace, &name)?,
+            };
+            if let Some(linked_clock) = &linked_clock {
+                if *linked_clock == name {
+                    return Err("A clock can't bump itself.".into());
+                }
+                db.get_clock(&namespace, linked_clock)
+                    .map_err(|_| format!("There's no clock named {linked_clock}."))?;
+            }
+
+            trigger.channel_id = Some(channel_id);
+            trigger.message = message.or(trigger.message);
+            trigger.role_id = role.map(|role| role.id.get()).or(trigger.role_id);
+            trigger.linked_clock = linked_clock.or(trigger.linked_clock);
+            trigger.linked_count = linked_count.unwrap_or(trigger.linked_count.max(1));
+            trigger.sound = sound.or(trigger.sound);
+            db.set_clock_trigger(&namespace, &name, &trigger)?;
+            Ok(trigger)
+        })
+        .await;
+
+    let embed = match changed {
+        Ok(trigger) => create_quick_success_embed(
+            &format!("when {clock_name} fills up"),
+            &describe_trigger(&trigger),
+        ),
+        Err(e) => create_error_embed("clock error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Lists when a clock ticks on its own, one way per line.
+fn describe_schedule(schedule: &ClockSchedule) -> String {
+    if schedule.is_empty() {
+        return String::from("It only ticks when someone bumps it.");
+    }
+
+    let mut ticks = Vec::new();
+    if let Some(every_hours) = schedule.every_hours {
+        let next = schedule
+            .next_tick_at
+            .map(|time| format!(", next <t:{time}:R>"))
+            .unwrap_or_default();
+        ticks.push(format!(
+            "Ticks {} every {every_hours} hours{next}",
+            schedule.count
+        ));
+    }
+    if schedule.on_session_start {
+        ticks.push(format!(
+            "Ticks {} when `/clock start_session` is run",
+            schedule.count
+        ));
+    }
+    ticks.join("\n")
+}
+
+/// Make a progress clock tick on its own, on a timer or when a session starts.
+///
+/// **Example Usage:**
+/// `/clock schedule name:Bluecoat Crackdown every_hours:24` - Tick "Bluecoat Crackdown" once a day
+/// `/clock schedule name:Lampblacks Expansion on_session_start:true count:2` - Tick 2 segments at the start of each session
+/// `/clock schedule name:Bluecoat Crackdown stop:true` - Stop "Bluecoat Crackdown" ticking on its own
+///
+/// Options left out keep what they were set to. Changing `every_hours` starts the timer over.
+#[poise::command(slash_command)]
+pub async fn schedule(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Tick every how many hours?"]
+    #[min = 1]
+    every_hours: Option<u32>,
+    #[description = "Tick when a session starts?"] on_session_start: Option<bool>,
+    #[description = "Tick by how much? (default: 1)"] count: Option<u8>,
+    #[description = "Stop the clock ticking on its own?"] stop: Option<bool>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let clock_name = name.clone();
+    let scheduled = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let schedule = match stop {
+                Some(true) => ClockSchedule::default(),
+                _ => {
+                    let schedule = db.get_clock_schedule(&namespace, &name)?;
+                    ClockSchedule {
+                        every_hours: every_hours.or(schedule.every_hours),
+                        count: count.unwrap_or(schedule.count.max(1)),
+                        on_session_start: on_session_start.unwrap_or(schedule.on_session_start),
+                        next_tick_at: None,
+                    }
+                }
+            };
+            db.set_clock_schedule(&namespace, &name, &schedule)?;
+            db.get_clock_schedule(&namespace, &name)
+        })
+        .await;
+
+    let embed = match scheduled {
+        Ok(schedule) => create_quick_success_embed(
+            &format!("schedule of {clock_name}"),
+            &describe_schedule(&schedule),
+        ),
+        Err(e) => create_error_embed("clock error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Link two progress clocks, so they race, tick one another, or pull against each other.
+///
+/// **Example Usage:**
+/// `/clock link name:Alarm other:Escape kind:Racing` - See whether the guards or the crew get there first
+/// `/clock link name:Bribe the Guard other:Infiltrate kind:Child of` - Tick "Infiltrate" once "Bribe the Guard" fills
+/// `/clock link name:Crew other:Cult kind:Tug of war` - Let the crew and the cult fill one clock from opposite sides
+///
+/// Clocks in a tug of war need the same number of segments. When one side runs out of room, it empties the other.
+#[poise::command(slash_command)]
+pub async fn link(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Which clock to link it to?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    other: String,
+    #[description = "How are the clocks linked?"] kind: LinkKind,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    for name in [&name, &other] {
+        if !may_access_clock(ctx, &namespace, name, true).await? {
+            return Ok(());
+        }
+    }
+    let link = ClockLink {
+        namespace,
+        kind: kind.kind(),
+        clock_name: name,
+        other_name: other,
+    };
+    let message = match kind {
+        LinkKind::Race => format!("{} is racing {}.", link.clock_name, link.other_name),
+        LinkKind::Child => format!(
+            "{} ticks {} when it fills.",
+            link.clock_name, link.other_name
+        ),
+        LinkKind::TugOfWar => format!(
+            "{} and {} are in a tug of war.",
+            link.clock_name, link.other_name
+        ),
+    };
+
+    let embed = match ctx.data().db.write(move |db| db.link_clocks(&link)).await {
+        Ok(_) => create_quick_success_embed("linked clocks", &message),
+        Err(e) => create_error_embed("clock error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Take a progress clock out of a race or tug of war, or away from its parent.
+///
+/// **Example Usage:**
+/// `/clock unlink name:Alarm kind:Racing` - End the race between "Alarm" and its rival
+#[poise::command(slash_command)]
+pub async fn unlink(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Which link to remove?"] kind: LinkKind,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let unlinked_name = name.clone();
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.unlink_clock(&namespace, &unlinked_name, kind.kind()))
+        .await
+    {
+        Ok(_) => create_quick_success_embed("unlinked clocks", &format!("Unlinked {name}.")),
+        Err(e) => create_error_embed("clock error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Renders the clock called `name` together with the clocks it's linked to by `kind`, or by whichever link it
+/// has if `kind` is `None`. A child clock is drawn with its parent and siblings.
+/// GM-only clocks are left out unless `gm` is set.
+/// Returns a title for the image, the image, and whether it shows any GM-only clocks.
+fn render_linked_clocks(
+    db: &DB,
+    namespace: &str,
+    name: &str,
+    kind: Option<ClockLinkKind>,
+    gm: bool,
+) -> Result<(String, Vec<u8>, bool), Error> {
+    let clock = db
+        .get_clock(namespace, name)
+        .map_err(|_| format!("There's no clock named {name}."))?;
+    let theme = db.get_clock_theme(namespace)?;
+    let accesses = db.get_clock_accesses(namespace)?;
+    let hidden = |name: &str| accesses.get(name) == Some(&ClockAccess::GmOnly);
+    let links: Vec<ClockLink> = db
+        .get_clock_links(namespace, name)?
+        .into_iter()
+        .filter(|link| kind.is_none_or(|kind| link.kind == kind))
+        .filter(|link| gm || !hidden(link.other(name)))
+        .collect();
+
+    for link in &links {
+        let Ok(other) = db.get_clock(namespace, link.other(name)) else {
+            continue;
+        };
+        let shows_hidden = hidden(name) || hidden(&other.name);
+        match link.kind {
+            ClockLinkKind::TugOfWar => {
+                // the clock that was linked first fills clockwise.
+                let (clock, other) = if link.clock_name == name {
+                    (&clock, &other)
+                } else {
+                    (&other, &clock)
+                };
+                let title = format!("{} vs {}", clock.name, other.name);
+                return Ok((
+                    title,
+                    render_clock_group(&ClockGroup::TugOfWar(clock, other), &theme)?,
+                    shows_hidden,
+                ));
+            }
+            ClockLinkKind::Race => {
+                let title = format!("{} vs {}", clock.name, other.name);
+                return Ok((
+                    title,
+                    render_clock_group(&ClockGroup::Race(&clock, &other), &theme)?,
+                    shows_hidden,
+                ));
+            }
+            ClockLinkKind::Child => {}
+        }
+    }
+
+    let visible_children = |parent: &str| -> Result<Vec<ProgressClock>, Error> {
+        Ok(db
+            .get_child_clocks(namespace, parent)?
+            .into_iter()
+            .filter(|child| gm || !hidden(&child.name))
+            .collect())
+    };
+    let children = visible_children(name)?;
+    let parent = links
+        .iter()
+        .find(|link| link.kind == ClockLinkKind::Child && link.clock_name == name)
+        .and_then(|link| db.get_clock(namespace, &link.other_name).ok());
+    let (parent, children) = match parent {
+        Some(parent) if children.is_empty() => {
+            let siblings = visible_children(&parent.name)?;
+            (parent, siblings)
+        }
+        _ if !children.is_empty() => (clock, children),
+        _ => return Err(format!("{name} isn't linked to another clock like that.").into()),
+    };
+    let shows_hidden = hidden(&parent.name) || children.iter().any(|child| hidden(&child.name));
+    Ok((
+        parent.name.clone(),
+        render_clock_group(&ClockGroup::Family(&parent, &children), &theme)?,
+        shows_hidden,
+    ))
+}
+
+/// Show a progress clock together with the clocks it's linked to.
+///
+/// **Example Usage:**
+/// `/clock linked name:Alarm` - Show "Alarm" next to the clock it's racing
+/// `/clock linked name:Infiltrate kind:Child of` - Show "Infiltrate" with its child clocks underneath
+#[poise::command(slash_command)]
+pub async fn linked(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Which link to show? (default: any)"] kind: Option<LinkKind>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, false).await? {
+        return Ok(());
+    }
+    let gm = is_gm(ctx).await;
+    let rendered = ctx
+        .data()
+        .db
+        .read(move |db| render_linked_clocks(db, &namespace, &name, kind.map(LinkKind::kind), gm))
+        .await;
+
+    match rendered {
+        Ok((title, png_data, shows_hidden)) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![
+                    serenity::CreateEmbed::new()
+                        .title(title)
+                        .image("attachment://linked.png")
+                        .color(*EMBED_OK_TUPLE),
+                ],
+                attachments: vec![serenity::CreateAttachment::bytes(png_data, "linked.png")],
+                ephemeral: Some(shows_hidden),
+                ..Default::default()
+            })
+            .await?;
+        }
+        Err(e) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed("clock error", &e.to_string())],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Choose who can see and change a progress clock. Only the GM can do this.
+///
+/// **Example Usage:**
+/// `/clock access name:Cult's Plan access:GM only` - Hide "Cult's Plan" from the players
+/// `/clock access name:Heat access:Players can only view` - Let the players show "Heat" but not bump it
+///
+/// The GM is anyone with the role set by `/gm_role`, or who can manage the server.
+#[poise::command(slash_command)]
+pub async fn access(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "Who can see and change the clock?"] access: Access,
+) -> Result<(), Error> {
+    let embed = if !is_gm(ctx).await {
+        create_error_embed("clock error", "Only the GM can change who can see a clock.")
+    } else {
+        let namespace = get_namespace(ctx).await?;
+        let changed_name = name.clone();
+        let changed_namespace = namespace.clone();
+        match ctx
+            .data()
+            .db
+            .write(move |db| {
+                db.set_clock_access(&changed_namespace, &changed_name, access.access())
+            })
+            .await
+        {
+            Ok(_) => {
+                // boards and pins are public, so they drop or bring back the clock.
+                refresh_pinned_messages(ctx.http(), &ctx.data().db, &namespace, None).await;
+                let message = match access {
+                    Access::Everyone => format!("Everyone can see and change {name}."),
+                    Access::ViewOnly => {
+                        format!("Players can see {name}, but only the GM can change it.")
+                    }
+                    Access::GmOnly => format!("Only the GM can see {name}."),
+                };
+                create_quick_success_embed("clock access", &message)
+            }
+            Err(e) => create_error_embed("clock error", &e.to_string()),
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// File a progress clock under a category and tags, to find it with `/clocks list`.
+///
+/// **Example Usage:**
+/// `/clock tag name:Lampblacks category:Faction add:crows, docks` - File "Lampblacks" as a faction, tagged "crows" and "docks"
+/// `/clock tag name:Lampblacks remove:docks` - Take the "docks" tag off "Lampblacks"
+/// `/clock tag name:Lampblacks clear:true` - Remove its category and every tag
+///
+/// Several tags can be added or removed at once, separated by commas.
+#[poise::command(slash_command)]
+pub async fn tag(
+    ctx: Context<'_>,
+    #[description = "Name of clock?"]
+    #[autocomplete = "display_clock_name_autocomplete"]
+    name: String,
+    #[description = "What kind of thing does the clock track?"] category: Option<Category>,
+    #[description = "Tags to add, separated by commas"]
+    #[autocomplete = "clock_tag_autocomplete"]
+    add: Option<String>,
+    #[description = "Tags to remove, separated by commas"]
+    #[autocomplete = "clock_tag_autocomplete"]
+    remove: Option<String>,
+    #[description = "Remove the category and every tag first?"] clear: Option<bool>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    if !may_access_clock(ctx, &namespace, &name, true).await? {
+        return Ok(());
+    }
+    let clock_name = name.clone();
+    let labelled = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let mut labels = match clear {
+                Some(true) => ClockLabels::default(),
+                _ => db.get_clock_labels(&namespace, &name)?,
+            };
+            labels.category = category.map(Category::category).or(labels.category);
+            let removed = remove
+                .as_deref()
+                .map(ClockLabels::parse_tags)
+                .unwrap_or_default();
+            labels.tags.retain(|tag| !removed.contains(tag));
+            for tag in add
+                .as_deref()
+                .map(ClockLabels::parse_tags)
+                .unwrap_or_default()
+            {
+                if !labels.tags.contains(&tag) {
+                    labels.tags.push(tag);
+                }
+            }
+            if labels.tags.len() > MAX_CLOCK_TAGS {
+                return Err(format!("A clock can have at most {MAX_CLOCK_TAGS} tags.").into());
+            }
+            db.set_clock_labels(&namespace, &name, &labels)?;
+            Ok(labels)
+        })
+        .await;
+
+    let embed = match labelled {
+        Ok(labels) => create_quick_success_embed(
+            &format!("tags of {clock_name}"),
+            &describe_labels(&labels).unwrap_or(String::from("It has no category or tags.")),
+        ),
+        Err(e) => create_error_embed("clock error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Change an existing progress clock.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands(
+        "set",
+        "tick_down",
+        "reset",
+        "edit",
+        "history",
+        "undo",
+        "redo",
+        "start_session",
+        "end_session",
+        "pin",
+        "on_complete",
+        "link",
+        "unlink",
+        "linked",
+        "schedule",
+        "access",
+        "tag"
+    )
+)]
+pub async fn clock(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
@@ -0,0 +1,562 @@
+use poise::serenity_prelude::{self as serenity, CreateAttachment, CreateEmbed, CreateEmbedFooter};
+
+use crate::{
+    clock::{Category, Conflict, FileFormat, Sort, Style, ThemePreset, check_new_clock},
+    commands::{
+        Context, EMBED_OK_TUPLE, Error, clock_tag_autocomplete, create_error_embed,
+        create_quick_success_embed, fire_completions_here, get_namespace, is_gm, join_lines_to_fit,
+        pins::{pin_message, refresh_pinned_messages},
+        shorten,
+    },
+    db::{
+        ClockAccess, ClockExport, ClockFilter, ClockLabels, ClockOrder, ClockTheme, ExportFormat,
+        ImportConflict, ProgressClock,
+    },
+    svg::{parse_color, render_clock_board},
+};
+
+/// A board shows at most this many clocks, so the image stays a sensible size.
+const MAX_BOARD_CLOCKS: usize = 48;
+
+/// How many clocks `/clocks list` shows on each page.
+const LIST_PAGE_SIZE: usize = 10;
+/// Names and labels in `/clocks list` are cut short, so a full page fits in one message.
+const LIST_NAME_CHARS: usize = 40;
+const LIST_LABELS_CHARS: usize = 50;
+
+/// Describes a clock's category and tags on one line, or `None` if it has neither.
+pub fn describe_labels(labels: &ClockLabels) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(category) = labels.category {
+        parts.push(category.as_str().to_owned());
+    }
+    parts.extend(labels.tags.iter().map(|tag| format!("`{tag}`")));
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Renders clocks onto a board, as an embed with the image it shows.
+pub fn board_message(
+    mut clocks: Vec<ProgressClock>,
+    theme: &ClockTheme,
+) -> Result<(CreateEmbed, CreateAttachment), Error> {
+    let mut embed = CreateEmbed::new()
+        .title("Clocks")
+        .image("attachment://board.png")
+        .color(*EMBED_OK_TUPLE);
+    if clocks.len() > MAX_BOARD_CLOCKS {
+        embed = embed.description(format!(
+            "Showing {MAX_BOARD_CLOCKS} of {} clocks.",
+            clocks.len()
+        ));
+        clocks.truncate(MAX_BOARD_CLOCKS);
+    }
+
+    let png_data = render_clock_board(&clocks, theme)?;
+    Ok((embed, CreateAttachment::bytes(png_data, "board.png")))
+}
+
+/// Show every progress clock in this server in one image, apart from GM-only ones.
+///
+/// **Example Usage:**
+/// `/clocks board` - Post all the clocks at the start of a session
+#[poise::command(slash_command)]
+pub async fn board(ctx: Context<'_>) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let board = ctx
+        .data()
+        .db
+        .read(move |db| {
+            Ok((
+                db.get_public_clocks(&namespace)?,
+                db.get_clock_theme(&namespace)?,
+            ))
+        })
+        .await;
+
+    let (clocks, theme) = match board {
+        Ok((clocks, _)) if clocks.is_empty() => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed(
+                    "clock board",
+                    "No clocks yet. Create one with `/add_progress_clock`.",
+                )],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            return Ok(());
+        }
+        Ok(board) => board,
+        Err(e) => {
+            println!("{}", e);
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed(
+                    "internal error",
+                    "Could not load your clocks.",
+                )],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let (embed, attachment) = board_message(clocks, &theme)?;
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        attachments: vec![attachment],
+        ephemeral: Some(false),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Pin a board of every progress clock here, kept up to date as the clocks change.
+///
+/// **Example Usage:**
+/// `/clocks pin` - Keep a board of the campaign's clocks at the top of the channel
+///
+/// Delete the message to stop updating it.
+#[poise::command(slash_command, required_permissions = "MANAGE_MESSAGES")]
+pub async fn pin(ctx: Context<'_>) -> Result<(), Error> {
+    pin_message(ctx, None).await
+}
+
+/// List the progress clocks in this server, narrowed down by name, category or tag.
+///
+/// **Example Usage:**
+/// `/clocks list category:Faction sort:Most complete first` - See which factions are closest to their goals
+/// `/clocks list tag:docks` - Find every clock tagged "docks"
+/// `/clocks list name:heist page:2` - See the second page of clocks with "heist" in their name
+#[poise::command(slash_command)]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Only clocks whose names contain this"] name: Option<String>,
+    #[description = "Only clocks in this category"] category: Option<Category>,
+    #[description = "Only clocks with this tag"]
+    #[autocomplete = "clock_tag_autocomplete"]
+    tag: Option<String>,
+    #[description = "What order to list them in? (default: by name)"] sort: Option<Sort>,
+    #[description = "Which page to show? (default: 1)"]
+    #[min = 1]
+    page: Option<usize>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let gm = is_gm(ctx).await;
+    let filter = ClockFilter {
+        partial: name.unwrap_or_default(),
+        category: category.map(Category::category),
+        tag,
+    };
+    let order = sort.map_or(ClockOrder::Name, Sort::order);
+    let clocks = ctx
+        .data()
+        .db
+        .read(move |db| {
+            let accesses = db.get_clock_accesses(&namespace)?;
+            Ok(db
+                .list_clocks(&namespace, &filter, order)?
+                .into_iter()
+                .map(|(clock, labels)| {
+                    let access = accesses
+                        .get(&clock.name)
+                        .copied()
+                        .unwrap_or(ClockAccess::Everyone);
+                    (clock, labels, access)
+                })
+                .filter(|(_, _, access)| gm || *access != ClockAccess::GmOnly)
+                .collect::<Vec<_>>())
+        })
+        .await;
+
+    let embed = match clocks {
+        Ok(clocks) if clocks.is_empty() => {
+            create_error_embed("clocks", "No clocks match those filters.")
+        }
+        Ok(clocks) => {
+            let pages = clocks.len().div_ceil(LIST_PAGE_SIZE);
+            let page = page.unwrap_or(1).min(pages);
+            let lines: Vec<String> = clocks
+                .iter()
+                .skip((page - 1) * LIST_PAGE_SIZE)
+                .take(LIST_PAGE_SIZE)
+                .map(|(clock, labels, access)| {
+                    let percent =
+                        clock.segments_filled as usize * 100 / (clock.segments as usize).max(1);
+                    let mut line = format!(
+                        "**{}** — {}/{} ({percent}%)",
+                        shorten(&clock.name, LIST_NAME_CHARS),
+                        clock.segments_filled,
+                        clock.segments
+                    );
+                    if *access == ClockAccess::GmOnly {
+                        line.push_str(" *(GM only)*");
+                    }
+                    if let Some(labels) = describe_labels(labels) {
+                        line.push_str(&format!("\n{}", shorten(&labels, LIST_LABELS_CHARS)));
+                    }
+                    line
+                })
+                .collect();
+            create_quick_success_embed("clocks", &join_lines_to_fit(&lines)).footer(
+                CreateEmbedFooter::new(format!("Page {page} of {pages} · {} clocks", clocks.len())),
+            )
+        }
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not load your clocks.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Imports larger than this are turned away before they're downloaded.
+const MAX_IMPORT_BYTES: u32 = 4 * 1024 * 1024;
+
+/// Replies with an error, only to the author.
+async fn reply_error(ctx: Context<'_>, message: &str) -> Result<(), Error> {
+    ctx.send(poise::CreateReply {
+        embeds: vec![create_error_embed("clock error", message)],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+    Ok(())
+}
+
+/// Save every progress clock in this server to a file, with its tags and history. GM only.
+///
+/// **Example Usage:**
+/// `/clocks export` - Back up the campaign's clocks as JSON
+/// `/clocks export format:YAML` - Export them as YAML, to read or edit by hand
+///
+/// The file can be brought into another server with `/clocks import`.
+#[poise::command(slash_command)]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "Which kind of file? (default: JSON)"] format: Option<FileFormat>,
+) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        return reply_error(ctx, "Only the GM can export the clocks.").await;
+    }
+
+    let namespace = get_namespace(ctx).await?;
+    let format = format.map_or(ExportFormat::Json, FileFormat::format);
+    let exported = ctx
+        .data()
+        .db
+        .read(move |db| {
+            let export = db.export_clocks(&namespace)?;
+            Ok((export.clocks.len(), export.to_bytes(format)?))
+        })
+        .await;
+
+    match exported {
+        Ok((0, _)) => reply_error(ctx, "There are no clocks to export.").await,
+        Ok((count, data)) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_quick_success_embed(
+                    "exported clocks",
+                    &format!("Exported {count} clocks. Bring them back with `/clocks import`."),
+                )],
+                attachments: vec![CreateAttachment::bytes(
+                    data,
+                    format!("clocks.{}", format.extension()),
+                )],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            Ok(())
+        }
+        Err(e) => {
+            println!("{}", e);
+            reply_error(ctx, "Could not export your clocks.").await
+        }
+    }
+}
+
+/// Bring in progress clocks from a file made by `/clocks export`. GM only.
+///
+/// **Example Usage:**
+/// `/clocks import file:clocks.json` - Add the clocks, keeping any here with the same names
+/// `/clocks import file:clocks.yaml conflict:Replace it with the imported clock` - Restore a backup over the clocks here
+///
+/// Each imported clock can be taken back with `/clock undo`.
+#[poise::command(slash_command)]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "A file from /clocks export"] file: serenity::Attachment,
+    #[description = "What to do with clocks whose names are taken? (default: keep the clock that's here)"]
+    conflict: Option<Conflict>,
+) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        return reply_error(ctx, "Only the GM can import clocks.").await;
+    }
+    if file.size > MAX_IMPORT_BYTES {
+        return reply_error(ctx, "That file is too big to be a clock export.").await;
+    }
+
+    let data = file.download().await?;
+    let export = match ClockExport::from_bytes(&data, ExportFormat::from_file_name(&file.filename))
+    {
+        Ok(export) => export,
+        Err(e) => return reply_error(ctx, &e.to_string()).await,
+    };
+
+    ctx.defer_ephemeral().await?;
+    let namespace = get_namespace(ctx).await?;
+    let conflict = conflict.map_or(ImportConflict::Skip, Conflict::conflict);
+    let actor = ctx.author().display_name().to_owned();
+    let imported_namespace = namespace.clone();
+    let imported = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let mut lines = Vec::new();
+            for exported in &export.clocks {
+                let name = &exported.clock.name;
+                let imported = check_new_clock(&exported.clock)
+                    .and_then(|_| db.import_clock(&imported_namespace, exported, conflict, &actor));
+                lines.push(match imported {
+                    Ok(Some(imported_name)) if imported_name == *name => {
+                        format!("Imported **{name}**")
+                    }
+                    Ok(Some(imported_name)) => {
+                        format!("Imported **{name}** as **{imported_name}**")
+                    }
+                    Ok(None) => {
+                        format!("Skipped **{name}**, as there's already a clock named that")
+                    }
+                    Err(e) => format!("Couldn't import **{name}**: {e}"),
+                });
+            }
+            Ok(lines)
+        })
+        .await;
+
+    let embed = match imported {
+        Ok(lines) if lines.is_empty() => {
+            create_error_embed("imported clocks", "There were no clocks in that file.")
+        }
+        Ok(lines) => {
+            refresh_pinned_messages(ctx.http(), &ctx.data().db, &namespace, None).await;
+            create_quick_success_embed("imported clocks", &join_lines_to_fit(&lines))
+        }
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not import the clocks.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+    fire_completions_here(ctx).await;
+
+    Ok(())
+}
+
+/// Describes a theme's colours on one line each.
+fn describe_theme(theme: &ClockTheme) -> String {
+    let palette: Vec<String> = theme
+        .palette
+        .iter()
+        .map(|color| format!("`{color}`"))
+        .collect();
+    format!(
+        "Style: {}, {} pixels wide\nPalette: {}, shaded at {}%\nSpokes and labels: `{}`\nBackground: {}",
+        theme.style.as_str(),
+        theme.size,
+        palette.join(", "),
+        (theme.shade_opacity * 100.0).round(),
+        theme.spoke_color,
+        theme
+            .background
+            .as_ref()
+            .map_or(String::from("none"), |background| format!("`{background}`"))
+    )
+}
+
+/// A clock in each colour of `theme`'s palette, to show what the theme looks like.
+fn theme_preview(theme: &ClockTheme) -> Vec<ProgressClock> {
+    theme
+        .palette
+        .iter()
+        .enumerate()
+        .map(|(idx, color)| ProgressClock {
+            namespace: String::new(),
+            name: color.clone(),
+            segments: 4,
+            segments_filled: idx as u8 % 4 + 1,
+            ephemeral: false,
+            color: Some(color.clone()),
+        })
+        .collect()
+}
+
+/// Change how every progress clock in this server is drawn. GM only to change.
+///
+/// **Example Usage:**
+/// `/clocks theme` - See the theme the clocks are drawn with
+/// `/clocks theme preset:Parchment` - Draw the clocks in ink on old paper
+/// `/clocks theme palette:crimson, gold, #4f7c99 background:none` - Pick the colours of clocks without their own
+/// `/clocks theme style:Bar size:300 shade_opacity:80` - Draw the clocks as big, solid bars
+/// `/clocks theme reset:true` - Go back to white spokes and green clocks
+///
+/// Clocks given their own colour keep it. Others take a colour from the palette, which stays the same for each clock.
+#[poise::command(slash_command)]
+#[allow(clippy::too_many_arguments)]
+pub async fn theme(
+    ctx: Context<'_>,
+    #[description = "A ready-made theme to start from"] preset: Option<ThemePreset>,
+    #[description = "Colours for clocks without their own, separated by commas"] palette: Option<
+        String,
+    >,
+    #[description = "The colour of the spokes, rims and labels"] spoke_color: Option<String>,
+    #[description = "The colour behind the clocks, or \"none\""] background: Option<String>,
+    #[description = "The shape of the clocks"] style: Option<Style>,
+    #[description = "How wide each clock is, in pixels (default: 200)"]
+    #[min = 100]
+    #[max = 400]
+    size: Option<u32>,
+    #[description = "How solid filled segments are, in percent (default: 40)"]
+    #[min = 10]
+    #[max = 100]
+    shade_opacity: Option<u8>,
+    #[description = "Go back to the default theme?"] reset: Option<bool>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let changed = preset.is_some()
+        || palette.is_some()
+        || spoke_color.is_some()
+        || background.is_some()
+        || style.is_some()
+        || size.is_some()
+        || shade_opacity.is_some()
+        || reset.unwrap_or(false);
+    if changed && !is_gm(ctx).await {
+        return reply_error(ctx, "Only the GM can change the theme.").await;
+    }
+
+    let current_namespace = namespace.clone();
+    let current = ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_theme(&current_namespace))
+        .await;
+    let mut theme = match current {
+        Ok(theme) => theme,
+        Err(e) => {
+            println!("{}", e);
+            return reply_error(ctx, "Could not load the theme.").await;
+        }
+    };
+
+    if reset.unwrap_or(false) {
+        theme = ClockTheme::default();
+    }
+    if let Some(preset) = preset {
+        theme = preset.theme();
+    }
+    if let Some(palette) = palette {
+        let colors: Result<Vec<String>, String> = palette
+            .split(',')
+            .map(str::trim)
+            .filter(|color| !color.is_empty())
+            .map(parse_color)
+            .collect();
+        match colors {
+            Ok(colors) if colors.is_empty() => {
+                return reply_error(ctx, "A palette needs at least one colour.").await;
+            }
+            Ok(colors) => theme.palette = colors,
+            Err(e) => return reply_error(ctx, &e).await,
+        }
+    }
+    if let Some(spoke_color) = spoke_color {
+        match parse_color(&spoke_color) {
+            Ok(spoke_color) => theme.spoke_color = spoke_color,
+            Err(e) => return reply_error(ctx, &e).await,
+        }
+    }
+    if let Some(background) = background {
+        theme.background = match background.trim().eq_ignore_ascii_case("none") {
+            true => None,
+            false => match parse_color(&background) {
+                Ok(background) => Some(background),
+                Err(e) => return reply_error(ctx, &e).await,
+            },
+        };
+    }
+
+    if let Some(style) = style {
+        theme.style = style.style();
+    }
+    if let Some(size) = size {
+        theme.size = size;
+    }
+    if let Some(shade_opacity) = shade_opacity {
+        theme.shade_opacity = f32::from(shade_opacity) / 100.0;
+    }
+
+    if changed {
+        let saved_theme = (theme != ClockTheme::default()).then(|| theme.clone());
+        let saved_namespace = namespace.clone();
+        let saved = ctx
+            .data()
+            .db
+            .write(move |db| db.set_clock_theme(&saved_namespace, saved_theme.as_ref()))
+            .await;
+        if let Err(e) = saved {
+            println!("{}", e);
+            return reply_error(ctx, "Could not save the theme.").await;
+        }
+        refresh_pinned_messages(ctx.http(), &ctx.data().db, &namespace, None).await;
+    }
+
+    let (mut embed, attachment) = board_message(theme_preview(&theme), &theme)?;
+    embed = embed
+        .title(if changed { "theme changed" } else { "theme" })
+        .description(describe_theme(&theme));
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        attachments: vec![attachment],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// See all the progress clocks in this server at once.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands("board", "list", "pin", "export", "import", "theme")
+)]
+pub async fn clocks(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
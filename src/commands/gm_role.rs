@@ -0,0 +1,48 @@
+use poise::serenity_prelude as serenity;
+
+use crate::commands::{Context, Error, create_error_embed, create_quick_success_embed};
+
+/// Choose which role makes someone the GM, able to see and change hidden clocks.
+///
+/// **Example Usage:**
+/// `/gm_role role:@Game Master` - Let everyone with the Game Master role run hidden clocks
+/// `/gm_role` - Leave hidden clocks to those who can manage the server
+///
+/// Anyone who can manage the server is always a GM.
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn gm_role(
+    ctx: Context<'_>,
+    #[description = "Which role is the GM? (leave out to clear it)"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let role_id = role.as_ref().map(|role| role.id.get());
+    let message = match &role {
+        Some(role) => format!("Everyone with <@&{}> is now a GM.", role.id),
+        None => String::from("Only those who can manage the server are GMs now."),
+    };
+
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.set_gm_role(guild_id.get(), role_id))
+        .await
+    {
+        Ok(_) => create_quick_success_embed("gm role", &message),
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not set the GM role.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
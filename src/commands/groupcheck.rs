@@ -0,0 +1,252 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use poise::ChoiceParameter;
+
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    EditMessage,
+};
+
+use crate::{
+    commands::{
+        Context, EMBED_ERR_TUPLE, EMBED_OK_TUPLE, Error, create_error_embed,
+        dice_macro_autocomplete, get_namespace, roll_first,
+    },
+    groupcheck::{GroupCheck, GroupRule},
+};
+
+static GROUP_ROLL_ID: &str = "groupcheck:roll";
+
+/// Embed descriptions get unwieldy well before Discord's limits, so checks are kept to a table's worth of players.
+const MAX_PARTICIPANTS: usize = 25;
+const DEFAULT_TIMEOUT_MINUTES: u64 = 5;
+const MAX_TIMEOUT_MINUTES: u64 = 60;
+
+fn create_group_check_embed(check: &GroupCheck, closes_at: u64, closed: bool) -> CreateEmbed {
+    let mut lines: Vec<String> = check
+        .rolls
+        .iter()
+        .map(|roll| match (roll.total, &roll.value) {
+            (Some(total), Some(value)) => {
+                let mark = match check.meets_dc(total) {
+                    Some(true) => " ✅",
+                    Some(false) => " ❌",
+                    None => "",
+                };
+                format!("🎲 <@{}> — {value}{mark}", roll.user_id)
+            }
+            _ if closed => format!("❌ <@{}> — did not roll", roll.user_id),
+            _ => format!("⏳ <@{}>", roll.user_id),
+        })
+        .collect();
+
+    if !closed {
+        lines.push(format!("\nCloses <t:{closes_at}:R>."));
+    }
+
+    let title = match check.dc {
+        Some(dc) => format!("Group Check — {} (DC {dc})", check.expression),
+        None => format!("Group Check — {}", check.expression),
+    };
+
+    let embed = CreateEmbed::new()
+        .color(*EMBED_OK_TUPLE)
+        .title(title)
+        .description(lines.join("\n"))
+        .footer(CreateEmbedFooter::new(check.rule.name()));
+
+    if !closed {
+        return embed;
+    }
+
+    let outcome = check.outcome();
+    let (color, verdict) = match outcome.success {
+        Some(true) => (*EMBED_OK_TUPLE, "The group succeeds!"),
+        Some(false) => (*EMBED_ERR_TUPLE, "The group fails."),
+        None => (*EMBED_OK_TUPLE, ""),
+    };
+    embed
+        .color(color)
+        .field("Result", format!("{} {verdict}", outcome.summary), false)
+}
+
+fn create_group_check_buttons() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(GROUP_ROLL_ID)
+            .label("Roll")
+            .style(ButtonStyle::Primary),
+    ])
+}
+
+/// Collects the players mentioned in `players` and every member with `role`.
+async fn collect_participants(
+    ctx: Context<'_>,
+    players: Option<String>,
+    role: Option<serenity::Role>,
+) -> Result<Vec<u64>, Error> {
+    let mut participants: Vec<u64> = players
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(serenity::utils::parse_user_mention)
+        .map(|user_id| user_id.get())
+        .collect();
+
+    if let Some(role) = role
+        && let Some(guild_id) = ctx.guild_id()
+    {
+        participants.extend(
+            guild_id
+                .members(ctx.http(), Some(1000), None)
+                .await?
+                .into_iter()
+                .filter(|member| member.roles.contains(&role.id) && !member.user.bot)
+                .map(|member| member.user.id.get()),
+        );
+    }
+
+    Ok(participants)
+}
+
+/// Ask several players to make the same roll, and combine their results.
+///
+/// **Example Usage:**
+/// `/groupcheck expression:1d20 + 2 players:@Ann @Bo @Cy dc:12` - Everyone rolls; the group succeeds if half of them meet DC 12
+/// `/groupcheck expression:stealth role:@Party dc:15` - Everyone with the Party role rolls the saved macro "stealth"
+/// `/groupcheck expression:1d20 players:@Ann @Bo rule:Best of` - Take the best roll
+/// `/groupcheck expression:1d6 role:@Party rule:Sum timeout:10` - Add everyone's rolls, waiting up to 10 minutes
+///
+/// Each player presses the Roll button once. The check closes when everyone has rolled or the time runs out,
+/// and players who didn't roll count as failing.
+#[poise::command(slash_command, guild_only)]
+pub async fn groupcheck(
+    ctx: Context<'_>,
+    #[description = "Dice string or macro everyone rolls, e.g. 1d20 + 2"]
+    #[autocomplete = "dice_macro_autocomplete"]
+    expression: String,
+    #[description = "Mention the players who should roll"] players: Option<String>,
+    #[description = "Everyone with this role rolls"] role: Option<serenity::Role>,
+    #[description = "Target each roll has to meet"] dc: Option<u32>,
+    #[description = "How rolls combine (default: half succeed)"] rule: Option<GroupRule>,
+    #[description = "Minutes to wait for rolls (default: 5)"] timeout: Option<u64>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let macro_name = expression.clone();
+    let dice_string = ctx
+        .data()
+        .db
+        .read(move |db| db.get_macro(&namespace, &macro_name))
+        .await
+        .map(|dice_macro| dice_macro.dice_string)
+        .unwrap_or(expression.clone());
+
+    let participants = collect_participants(ctx, players, role).await?;
+    let check = if participants.len() > MAX_PARTICIPANTS {
+        Err(format!(
+            "A group check can have at most {MAX_PARTICIPANTS} players."
+        ))
+    } else if let Err(message) = roll_first(dice_string.clone()) {
+        Err(message)
+    } else {
+        GroupCheck::new(
+            expression,
+            dc,
+            rule.unwrap_or(GroupRule::HalfSucceed),
+            participants,
+        )
+    };
+
+    let mut check = match check {
+        Ok(check) => check,
+        Err(message) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed("group check", &message)],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let timeout = Duration::from_secs(
+        60 * timeout
+            .unwrap_or(DEFAULT_TIMEOUT_MINUTES)
+            .clamp(1, MAX_TIMEOUT_MINUTES),
+    );
+    let closes_at = (SystemTime::now() + timeout)
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let mentions: Vec<String> = check
+        .rolls
+        .iter()
+        .map(|roll| format!("<@{}>", roll.user_id))
+        .collect();
+    let reply_handle = ctx
+        .send(poise::CreateReply {
+            content: Some(format!("{} roll for the group check!", mentions.join(" "))),
+            embeds: vec![create_group_check_embed(&check, closes_at, false)],
+            components: Some(vec![create_group_check_buttons()]),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+    let message_id = reply_handle.message().await?.id;
+
+    while !check.is_complete() {
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        let Some(interaction) = ComponentInteractionCollector::new(ctx.serenity_context())
+            .message_id(message_id)
+            .custom_ids(vec![GROUP_ROLL_ID.to_owned()])
+            .timeout(remaining)
+            .await
+        else {
+            break;
+        };
+
+        let recorded = roll_first(dice_string.clone()).and_then(|roll_result| {
+            check.record(
+                interaction.user.id.get(),
+                roll_result.total,
+                roll_result.value,
+            )
+        });
+
+        let response = match recorded {
+            Ok(_) => CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(create_group_check_embed(
+                        &check,
+                        closes_at,
+                        check.is_complete(),
+                    ))
+                    .components(if check.is_complete() {
+                        vec![]
+                    } else {
+                        vec![create_group_check_buttons()]
+                    }),
+            ),
+            Err(message) => CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(create_error_embed("group check", &message))
+                    .ephemeral(true),
+            ),
+        };
+        interaction.create_response(ctx.http(), response).await?;
+    }
+
+    if !check.is_complete() {
+        // the interaction token may have expired by now, so the message is edited through the channel.
+        let edit = EditMessage::new()
+            .embed(create_group_check_embed(&check, closes_at, true))
+            .components(vec![]);
+        ctx.channel_id()
+            .edit_message(ctx.http(), message_id, edit)
+            .await?;
+    }
+
+    Ok(())
+}
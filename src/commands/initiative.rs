@@ -0,0 +1,458 @@
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ChannelId, CreateActionRow, CreateButton, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditMessage, MessageId,
+    futures::{self, Stream},
+};
+
+use crate::{
+    commands::{
+        Context, Data, EMBED_OK_TUPLE, Error, create_error_embed, create_quick_success_embed,
+        dice_macro_autocomplete, get_namespace, join_lines_to_fit, roll_first,
+    },
+    db::{Combatant, InitiativeTracker},
+};
+
+/// Every button on a tracker message has a custom ID starting with this prefix.
+pub const INITIATIVE_BUTTON_PREFIX: &str = "initiative:";
+
+static NEXT_TURN_ID: &str = "initiative:next";
+
+static NO_TRACKER_MESSAGE: &str =
+    "There's no initiative tracker in this channel. Use `/initiative join` to start one.";
+
+fn create_tracker_embed(tracker: &InitiativeTracker) -> CreateEmbed {
+    let lines: Vec<String> = tracker
+        .combatants
+        .iter()
+        .enumerate()
+        .map(|(idx, combatant)| {
+            let marker = if combatant.delayed {
+                "⏸"
+            } else if idx == tracker.turn {
+                "▶"
+            } else {
+                "▫"
+            };
+            format!(
+                "{marker} **{}** — {}{}",
+                combatant.name,
+                combatant.initiative,
+                if combatant.npc { " (NPC)" } else { "" }
+            )
+        })
+        .collect();
+
+    let embed = CreateEmbed::new()
+        .color(*EMBED_OK_TUPLE)
+        .title(format!("Initiative — Round {}", tracker.round))
+        .description(if lines.is_empty() {
+            String::from("No combatants yet.")
+        } else {
+            join_lines_to_fit(&lines)
+        });
+
+    match tracker.current() {
+        Some(combatant) if !combatant.delayed => embed.footer(CreateEmbedFooter::new(format!(
+            "It's {}'s turn.",
+            combatant.name
+        ))),
+        _ => embed,
+    }
+}
+
+fn create_tracker_buttons() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(NEXT_TURN_ID)
+            .label("Next turn")
+            .style(ButtonStyle::Primary),
+    ])
+}
+
+/// Edits the tracker message in place, or posts a new one if there is none to edit.
+async fn refresh_tracker_message(
+    ctx: &serenity::Context,
+    tracker: &mut InitiativeTracker,
+) -> Result<(), Error> {
+    let channel_id = ChannelId::new(tracker.channel_id);
+
+    if let Some(message_id) = tracker.message_id {
+        let edit = EditMessage::new()
+            .embed(create_tracker_embed(tracker))
+            .components(vec![create_tracker_buttons()]);
+        if channel_id
+            .edit_message(&ctx.http, MessageId::new(message_id), edit)
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+
+    let message = channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .embed(create_tracker_embed(tracker))
+                .components(vec![create_tracker_buttons()]),
+        )
+        .await?;
+    tracker.message_id = Some(message.id.get());
+    Ok(())
+}
+
+/// Applies `operation` to the tracker in this channel and saves it, all in one write so that
+/// simultaneous changes can't overwrite each other. `start` makes a new tracker if the channel has none.
+/// Returns `None` if there's no tracker, otherwise the tracker afterwards and what `operation` returned.
+async fn change_tracker<F>(
+    data: &Data,
+    channel_id: u64,
+    start: bool,
+    operation: F,
+) -> Result<Option<(InitiativeTracker, Result<String, String>)>, Error>
+where
+    F: FnOnce(&mut InitiativeTracker) -> Result<String, String> + Send + 'static,
+{
+    data.db
+        .write(move |db| {
+            let mut tracker = match db.get_initiative(channel_id) {
+                Ok(tracker) => tracker,
+                Err(_) if start => InitiativeTracker::new(channel_id),
+                Err(_) => return Ok(None),
+            };
+            let result = operation(&mut tracker);
+            if result.is_ok() {
+                db.save_initiative(&tracker)?;
+            }
+            Ok(Some((tracker, result)))
+        })
+        .await
+}
+
+/// Updates the tracker's message, and saves its ID if a new message had to be posted.
+/// Only the ID is saved, as the tracker may have changed again while the message was posted.
+async fn publish_tracker(ctx: Context<'_>, mut tracker: InitiativeTracker) -> Result<(), Error> {
+    let message_id = tracker.message_id;
+    refresh_tracker_message(ctx.serenity_context(), &mut tracker).await?;
+    if let Some(new_message_id) = tracker.message_id
+        && tracker.message_id != message_id
+    {
+        let channel_id = tracker.channel_id;
+        ctx.data()
+            .db
+            .write(move |db| db.set_initiative_message(channel_id, new_message_id))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Applies `operation` to the tracker in this channel, then saves it and updates its message.
+async fn update_tracker<F>(ctx: Context<'_>, operation: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut InitiativeTracker) -> Result<String, String> + Send + 'static,
+{
+    let embed = match change_tracker(ctx.data(), ctx.channel_id().get(), false, operation).await? {
+        Some((tracker, Ok(message))) => {
+            publish_tracker(ctx, tracker).await?;
+            create_quick_success_embed("initiative", &message)
+        }
+        Some((_, Err(message))) => create_error_embed("initiative", &message),
+        None => create_error_embed("no initiative tracker", NO_TRACKER_MESSAGE),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub async fn combatant_name_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Stream<Item = String> + 'a {
+    let channel_id = ctx.channel_id().get();
+    let items: Vec<String> = ctx
+        .data()
+        .db
+        .read(move |db| db.get_initiative(channel_id))
+        .await
+        .map(|tracker| {
+            tracker
+                .combatants
+                .into_iter()
+                .map(|combatant| combatant.name)
+                .filter(|name| name.to_lowercase().contains(&partial.to_lowercase()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    futures::stream::iter(items)
+}
+
+/// Roll initiative and join the turn order in this channel.
+///
+/// **Example Usage:**
+/// `/initiative join expression:1d20 + 3` - Join as yourself with a +3 initiative bonus
+/// `/initiative join expression:1d20 + 1 name:Goblin 1 npc:true` - Add an NPC to the turn order
+/// `/initiative join expression:init` - Join by rolling the saved macro "init"
+///
+/// Ties go to players before NPCs, and are otherwise settled by a random tiebreaker.
+#[poise::command(slash_command)]
+pub async fn join(
+    ctx: Context<'_>,
+    #[description = "Dice string or macro to roll, e.g. 1d20 + 3"]
+    #[autocomplete = "dice_macro_autocomplete"]
+    expression: String,
+    #[description = "Who is joining? Defaults to you."]
+    #[max_length = 64]
+    name: Option<String>,
+    #[description = "Is this an NPC?"] npc: Option<bool>,
+) -> Result<(), Error> {
+    let name = name.unwrap_or(ctx.author().display_name().to_owned());
+    let namespace = get_namespace(ctx).await?;
+    let macro_name = expression.clone();
+    let dice_string = ctx
+        .data()
+        .db
+        .read(move |db| db.get_macro(&namespace, &macro_name))
+        .await
+        .map(|dice_macro| dice_macro.dice_string)
+        .unwrap_or(expression);
+
+    let embed = match roll_first(dice_string) {
+        Ok(roll_result) => {
+            let combatant = Combatant {
+                name: name.clone(),
+                initiative: roll_result.total,
+                tiebreaker: rand::random(),
+                npc: npc.unwrap_or(false),
+                delayed: false,
+            };
+            let joined = change_tracker(ctx.data(), ctx.channel_id().get(), true, move |tracker| {
+                tracker.add(combatant).map(|_| String::new())
+            })
+            .await?;
+
+            match joined {
+                Some((tracker, Ok(_))) => {
+                    publish_tracker(ctx, tracker).await?;
+                    create_quick_success_embed(
+                        "joined initiative",
+                        &format!("{name} rolled {}.", roll_result.value),
+                    )
+                }
+                Some((_, Err(message))) => create_error_embed("initiative", &message),
+                None => create_error_embed("no initiative tracker", NO_TRACKER_MESSAGE),
+            }
+        }
+        Err(_) => create_error_embed(
+            "Roll Error",
+            "The entered dice text was not valid. Take a look at the /help command for a guide on how to use the bot!",
+        ),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// End the current turn and move to the next combatant.
+#[poise::command(slash_command)]
+pub async fn next(ctx: Context<'_>) -> Result<(), Error> {
+    update_tracker(ctx, |tracker| {
+        tracker.next();
+        Ok(format!("Round {}.", tracker.round))
+    })
+    .await
+}
+
+/// Go back to the previous combatant's turn.
+#[poise::command(slash_command)]
+pub async fn previous(ctx: Context<'_>) -> Result<(), Error> {
+    update_tracker(ctx, |tracker| {
+        tracker.previous();
+        Ok(format!("Round {}.", tracker.round))
+    })
+    .await
+}
+
+/// Step out of the turn order until you resume.
+///
+/// **Example Usage:**
+/// `/initiative delay` - Delay the combatant whose turn it is
+/// `/initiative delay name:Goblin 1` - Delay a specific combatant
+#[poise::command(slash_command)]
+pub async fn delay(
+    ctx: Context<'_>,
+    #[description = "Who is delaying? Defaults to the current combatant."]
+    #[autocomplete = "combatant_name_autocomplete"]
+    name: Option<String>,
+) -> Result<(), Error> {
+    update_tracker(ctx, move |tracker| {
+        let name = match name {
+            Some(name) => name,
+            None => tracker
+                .current()
+                .map(|combatant| combatant.name.clone())
+                .ok_or(String::from("Nobody is in the initiative order."))?,
+        };
+        tracker.delay(&name)?;
+        Ok(format!("{name} is delaying."))
+    })
+    .await
+}
+
+/// Bring a delayed combatant back. They act right away.
+///
+/// **Example Usage:**
+/// `/initiative resume name:Goblin 1` - Goblin 1 takes their turn now
+#[poise::command(slash_command)]
+pub async fn resume(
+    ctx: Context<'_>,
+    #[description = "Who is resuming?"]
+    #[autocomplete = "combatant_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    update_tracker(ctx, move |tracker| {
+        tracker.resume(&name)?;
+        Ok(format!("{name} acts now."))
+    })
+    .await
+}
+
+/// Remove a combatant from the turn order.
+///
+/// **Example Usage:**
+/// `/initiative remove name:Goblin 1` - Remove Goblin 1
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Who is leaving?"]
+    #[autocomplete = "combatant_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    update_tracker(ctx, move |tracker| {
+        tracker.remove(&name)?;
+        Ok(format!("Removed {name}."))
+    })
+    .await
+}
+
+/// Post the tracker again at the bottom of the channel.
+#[poise::command(slash_command)]
+pub async fn show(ctx: Context<'_>) -> Result<(), Error> {
+    update_tracker(ctx, |tracker| {
+        tracker.message_id = None;
+        Ok(String::from("Posted the initiative tracker."))
+    })
+    .await
+}
+
+/// End the encounter and clear the tracker.
+#[poise::command(slash_command)]
+pub async fn end(ctx: Context<'_>) -> Result<(), Error> {
+    let channel_id = ctx.channel_id().get();
+    let ended = ctx
+        .data()
+        .db
+        .write(move |db| {
+            let tracker = db.get_initiative(channel_id)?;
+            db.remove_initiative(channel_id)?;
+            Ok(tracker)
+        })
+        .await;
+
+    let embed = match ended {
+        Ok(tracker) => {
+            if let Some(message_id) = tracker.message_id {
+                let edit = EditMessage::new()
+                    .embed(
+                        create_tracker_embed(&tracker)
+                            .title(format!("Initiative — Ended in round {}", tracker.round)),
+                    )
+                    .components(vec![]);
+                // the tracker message may have been deleted, which is fine.
+                let _ = ctx
+                    .channel_id()
+                    .edit_message(ctx.http(), MessageId::new(message_id), edit)
+                    .await;
+            }
+            create_quick_success_embed("initiative", "Ended the encounter.")
+        }
+        Err(_) => create_error_embed("no initiative tracker", NO_TRACKER_MESSAGE),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Track turn order for an encounter in this channel.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands("join", "next", "previous", "delay", "resume", "remove", "show", "end")
+)]
+pub async fn initiative(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Advances the turn when the button on a tracker message is pressed.
+pub async fn handle_interaction(
+    ctx: &serenity::Context,
+    interaction: &serenity::ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let message_id = interaction.message.id.get();
+    let advanced = change_tracker(data, interaction.channel_id.get(), false, move |tracker| {
+        // an older tracker message still has its button, but only the latest one moves the turn.
+        if tracker.message_id != Some(message_id) {
+            return Err(String::from(
+                "This tracker is out of date. Use the latest one in this channel.",
+            ));
+        }
+        tracker.next();
+        Ok(String::new())
+    })
+    .await?;
+
+    let response = match advanced {
+        Some((tracker, Ok(_))) => CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new().embed(create_tracker_embed(&tracker)),
+        ),
+        Some((_, Err(message))) => CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .embed(create_error_embed("initiative", &message))
+                .ephemeral(true),
+        ),
+        None => CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .embed(create_error_embed(
+                    "no initiative tracker",
+                    NO_TRACKER_MESSAGE,
+                ))
+                .ephemeral(true),
+        ),
+    };
+
+    interaction.create_response(&ctx.http, response).await?;
+
+    Ok(())
+}
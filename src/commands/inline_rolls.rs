@@ -0,0 +1,118 @@
+use poise::serenity_prelude::{self as serenity, CreateEmbed, CreateMessage};
+
+use crate::{
+    commands::{
+        Context, Data, EMBED_OK_TUPLE, Error, create_error_embed, create_quick_success_embed,
+    },
+    dice::{find_inline_rolls, handle_dice_string},
+};
+
+/// Discord rejects embeds with more than 25 fields.
+const MAX_INLINE_ROLLS: usize = 25;
+
+/// Builds a single embed with one field per `[[dice]]` expression found in `content`.
+fn create_inline_roll_embed(content: &str) -> Option<CreateEmbed> {
+    let dice_strings = find_inline_rolls(content);
+    if dice_strings.is_empty() {
+        return None;
+    }
+
+    let fields = dice_strings
+        .into_iter()
+        .take(MAX_INLINE_ROLLS)
+        .map(|dice_string| {
+            let value = match handle_dice_string(dice_string.to_owned()) {
+                Ok(results) if results.len() == 1 => results[0].value.clone(),
+                Ok(results) => results
+                    .iter()
+                    .map(|result| format!("**{}**: {}", result.name, result.value))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                Err(_) => String::from("Not a valid dice string."),
+            };
+            (format!("`{dice_string}`"), value, false)
+        });
+
+    Some(
+        CreateEmbed::new()
+            .color(*EMBED_OK_TUPLE)
+            .title("Roll Result")
+            .fields(fields),
+    )
+}
+
+/// Replies to a message with its inline rolls, if the channel has opted in.
+pub async fn handle_message(
+    ctx: &serenity::Context,
+    message: &serenity::Message,
+    data: &Data,
+) -> Result<(), Error> {
+    if message.author.bot || !message.content.contains("[[") {
+        return Ok(());
+    }
+
+    let channel_id = message.channel_id.get();
+    let enabled = data
+        .db
+        .read(move |db| db.inline_rolls_enabled(channel_id))
+        .await?;
+    if !enabled {
+        return Ok(());
+    }
+
+    if let Some(embed) = create_inline_roll_embed(&message.content) {
+        message
+            .channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new().embed(embed).reference_message(message),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Roll dice written inside `[[` and `]]` in ordinary messages in this channel.
+///
+/// **Example Usage:**
+/// `/inline_rolls enabled:true` - Roll every `[[dice]]` expression posted in this channel
+/// `/inline_rolls enabled:false` - Stop rolling inline expressions in this channel
+///
+/// Once enabled, a message like `I swing at the goblin [[hit: 1d20 + 5]]` gets a reply with the roll.
+#[poise::command(slash_command, required_permissions = "MANAGE_CHANNELS")]
+pub async fn inline_rolls(
+    ctx: Context<'_>,
+    #[description = "Roll [[dice]] in this channel's messages?"] enabled: bool,
+) -> Result<(), Error> {
+    let channel_id = ctx.channel_id().get();
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.set_inline_rolls(channel_id, enabled))
+        .await
+    {
+        Ok(_) => create_quick_success_embed(
+            "inline rolls",
+            if enabled {
+                "Inline rolls are now enabled in this channel."
+            } else {
+                "Inline rolls are now disabled in this channel."
+            },
+        ),
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not update inline rolls.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
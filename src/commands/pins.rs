@@ -0,0 +1,198 @@
+//! Messages that keep showing a clock, or a board of every clock, as the clocks change.
+//!
+//! Pinned messages are edited in place rather than posted again, and forgotten once they're deleted.
+
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, CreateAttachment, CreateEmbed, CreateMessage, EditAttachments,
+    EditMessage, MessageId, StatusCode,
+};
+
+use crate::{
+    commands::{
+        Context, Error, clock_message, clocks::board_message, create_error_embed,
+        create_quick_success_embed, get_namespace,
+    },
+    db::{ClockAccess, ClockStorage, DB, DbPool, PinnedMessage},
+};
+
+/// Builds what a pinned message shows right now. Removed and hidden clocks, and empty boards, have no image.
+/// Boards leave out GM-only clocks, as everyone in the channel can see them.
+fn pinned_message_contents(
+    db: &DB,
+    pinned_message: &PinnedMessage,
+) -> Result<(CreateEmbed, Option<CreateAttachment>), Error> {
+    let (embed, attachment) = match &pinned_message.clock_name {
+        Some(name) => match db.get_clock(&pinned_message.namespace, name) {
+            Ok(_)
+                if db.get_clock_access(&pinned_message.namespace, name)? == ClockAccess::GmOnly =>
+            {
+                return Ok((
+                    create_error_embed(name, &format!("{name} was hidden by the GM.")),
+                    None,
+                ));
+            }
+            Ok(progress_clock) => clock_message(
+                &progress_clock,
+                &db.get_clock_theme(&pinned_message.namespace)?,
+            )?,
+            Err(_) => {
+                return Ok((
+                    create_error_embed(name, &format!("{name} was removed.")),
+                    None,
+                ));
+            }
+        },
+        None => {
+            let clocks = db.get_public_clocks(&pinned_message.namespace)?;
+            if clocks.is_empty() {
+                return Ok((
+                    create_error_embed(
+                        "clocks",
+                        "No clocks yet. Create one with `/add_progress_clock`.",
+                    ),
+                    None,
+                ));
+            }
+            board_message(clocks, &db.get_clock_theme(&pinned_message.namespace)?)?
+        }
+    };
+    Ok((embed, Some(attachment)))
+}
+
+/// Edits the messages pinned in `namespace` that show the clock called `name`, or a board, to match the clocks now.
+/// A `name` of `None` edits every message pinned in `namespace`.
+/// Returns the messages that were edited. Deleted messages are forgotten, and other failures only logged,
+/// so a missing message never stops the change that prompted the edit.
+pub async fn refresh_pinned_messages(
+    http: &serenity::Http,
+    db: &DbPool,
+    namespace: &str,
+    name: Option<&str>,
+) -> Vec<PinnedMessage> {
+    let namespace = namespace.to_owned();
+    let name = name.map(str::to_owned);
+    let pinned = db
+        .read(move |db| {
+            db.get_pinned_messages(&namespace, name.as_deref())?
+                .into_iter()
+                .map(|pinned_message| {
+                    let contents = pinned_message_contents(db, &pinned_message)?;
+                    Ok((pinned_message, contents))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .await;
+
+    let pinned = match pinned {
+        Ok(pinned) => pinned,
+        Err(e) => {
+            println!("{}", e);
+            return vec![];
+        }
+    };
+
+    let mut refreshed = Vec::new();
+    for (pinned_message, (embed, attachment)) in pinned {
+        let mut attachments = EditAttachments::new();
+        if let Some(attachment) = attachment {
+            attachments = attachments.add(attachment);
+        }
+        let edit = EditMessage::new().embed(embed).attachments(attachments);
+
+        match ChannelId::new(pinned_message.channel_id)
+            .edit_message(http, MessageId::new(pinned_message.message_id), edit)
+            .await
+        {
+            Ok(_) => refreshed.push(pinned_message),
+            Err(serenity::Error::Http(e)) if e.status_code() == Some(StatusCode::NOT_FOUND) => {
+                let message_id = pinned_message.message_id;
+                if let Err(e) = db
+                    .write(move |db| db.remove_pinned_message(message_id))
+                    .await
+                {
+                    println!("{}", e);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+    refreshed
+}
+
+/// Posts the clock called `clock_name`, or a board for `None`, in this channel and remembers the message,
+/// so it's edited whenever the clocks change.
+pub async fn pin_message(ctx: Context<'_>, clock_name: Option<String>) -> Result<(), Error> {
+    let mut pinned_message = PinnedMessage {
+        message_id: 0,
+        channel_id: ctx.channel_id().get(),
+        namespace: get_namespace(ctx).await?,
+        clock_name,
+    };
+
+    let shown = pinned_message.clone();
+    let contents = ctx
+        .data()
+        .db
+        .read(move |db| {
+            if let Some(name) = &shown.clock_name {
+                db.get_clock(&shown.namespace, name)
+                    .map_err(|_| format!("There's no clock named {name}."))?;
+                if db.get_clock_access(&shown.namespace, name)? == ClockAccess::GmOnly {
+                    return Err(format!("{name} is GM-only, so it can't be pinned.").into());
+                }
+            }
+            pinned_message_contents(db, &shown)
+        })
+        .await;
+
+    let (embed, attachment) = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed("clock error", &e.to_string())],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut message = CreateMessage::new().embed(embed);
+    if let Some(attachment) = attachment {
+        message = message.add_file(attachment);
+    }
+    pinned_message.message_id = ctx
+        .channel_id()
+        .send_message(ctx.http(), message)
+        .await?
+        .id
+        .get();
+
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.save_pinned_message(&pinned_message))
+        .await
+    {
+        Ok(_) => create_quick_success_embed(
+            "pinned",
+            "The message will change along with the clocks. Delete it to stop.",
+        ),
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not pin the message.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+
+use crate::{
+    commands::{Data, Error, create_error_embed, create_roll_embed},
+    dice::{
+        handle_dice_string,
+        modify::{RollModifier, modify_dice_string},
+    },
+};
+
+/// Every button on a roll reply has a custom ID starting with this prefix.
+pub const ROLL_BUTTON_PREFIX: &str = "roll:";
+
+static REROLL_ID: &str = "roll:reroll";
+static ADVANTAGE_ID: &str = "roll:advantage";
+static INSPIRATION_ID: &str = "roll:inspiration";
+
+/// Bonus added by the bardic inspiration button.
+const INSPIRATION_BONUS: u32 = 2;
+
+/// Returns the row of buttons attached to every `/roll` reply.
+pub fn create_roll_buttons() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(REROLL_ID)
+            .label("Reroll")
+            .style(ButtonStyle::Primary),
+        CreateButton::new(ADVANTAGE_ID)
+            .label("Roll with advantage")
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(INSPIRATION_ID)
+            .label(format!("Add +{INSPIRATION_BONUS} (bardic inspiration)"))
+            .style(ButtonStyle::Secondary),
+    ])
+}
+
+/// Rolls `dice_string` again with the modifier that belongs to the pressed button.
+fn create_reroll_embed(dice_string: &str, custom_id: &str) -> CreateEmbed {
+    let (title, modified) = if custom_id == ADVANTAGE_ID {
+        (
+            "Roll Result (Advantage)",
+            modify_dice_string(dice_string, RollModifier::Advantage),
+        )
+    } else if custom_id == INSPIRATION_ID {
+        (
+            "Roll Result (Bardic Inspiration)",
+            modify_dice_string(dice_string, RollModifier::Bonus(INSPIRATION_BONUS)),
+        )
+    } else {
+        ("Roll Result (Reroll)", Ok(dice_string.to_owned()))
+    };
+
+    match modified.and_then(handle_dice_string) {
+        Ok(roll_results) => create_roll_embed(title, &roll_results),
+        Err(_) => create_error_embed("Roll Error", "Could not roll that dice string again."),
+    }
+}
+
+/// Re-evaluates the stored dice string of a roll reply and edits its embed in place.
+pub async fn handle_interaction(
+    ctx: &serenity::Context,
+    interaction: &serenity::ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let message_id = interaction.message.id.get();
+    let roll_message = data
+        .db
+        .read(move |db| db.get_roll_message(message_id))
+        .await;
+
+    let response = match roll_message {
+        Ok(roll_message) if roll_message.user_id != interaction.user.id.get() => {
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(create_error_embed(
+                        "not your roll",
+                        "Only the person who rolled can use these buttons.",
+                    ))
+                    .ephemeral(true),
+            )
+        }
+        Ok(roll_message) => {
+            CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().embed(
+                create_reroll_embed(&roll_message.dice_string, &interaction.data.custom_id),
+            ))
+        }
+        Err(e) => {
+            println!("{}", e);
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(create_error_embed(
+                        "roll not found",
+                        "Could not find the dice string for this roll.",
+                    ))
+                    .ephemeral(true),
+            )
+        }
+    };
+
+    interaction.create_response(&ctx.http, response).await?;
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::commands::{Context, Error, create_error_embed, create_quick_success_embed};
+
+/// Which clocks, macros and tables a channel uses.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Scope {
+    /// Shared with every other channel in the server that isn't scoped.
+    #[name = "Whole server"]
+    Server,
+    /// Kept to this channel alone.
+    #[name = "This channel"]
+    Channel,
+    /// Shared with every channel scoped to the same campaign.
+    #[name = "Campaign"]
+    Campaign,
+}
+
+/// Share this channel's clocks, macros and tables with the server, a campaign, or nobody.
+///
+/// **Example Usage:**
+/// `/scope scope:Campaign campaign:Curse of Strahd` - Use the "Curse of Strahd" clocks in this channel
+/// `/scope scope:This channel` - Keep this channel's clocks to itself
+/// `/scope scope:Whole server` - Go back to the server's shared clocks
+///
+/// Nothing is moved or deleted when the scope changes. Switching back shows the old clocks again.
+#[poise::command(slash_command, required_permissions = "MANAGE_CHANNELS")]
+pub async fn scope(
+    ctx: Context<'_>,
+    #[description = "Whose clocks, macros and tables should this channel use?"] scope: Scope,
+    #[description = "Name of the campaign, for the Campaign scope"] campaign: Option<String>,
+) -> Result<(), Error> {
+    let channel_id = ctx.channel_id().get();
+    let campaign = campaign
+        .map(|campaign| campaign.trim().to_lowercase())
+        .filter(|campaign| !campaign.is_empty());
+
+    let (scope, message) = match (scope, campaign) {
+        (Scope::Server, _) => (
+            None,
+            String::from("This channel now shares the server's clocks, macros and tables."),
+        ),
+        (Scope::Channel, _) => (
+            Some(format!("channel:{channel_id}")),
+            String::from("This channel now has its own clocks, macros and tables."),
+        ),
+        (Scope::Campaign, Some(campaign)) => (
+            Some(format!("campaign:{campaign}")),
+            format!(
+                "This channel now uses the clocks, macros and tables of the {campaign} campaign."
+            ),
+        ),
+        (Scope::Campaign, None) => {
+            ctx.send(poise::CreateReply {
+                embeds: vec![create_error_embed(
+                    "scope",
+                    "Name the campaign this channel belongs to.",
+                )],
+                ephemeral: Some(true),
+                reply: true,
+                ..Default::default()
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.set_namespace_scope(channel_id, scope.as_deref()))
+        .await
+    {
+        Ok(_) => create_quick_success_embed("scope", &message),
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not update this channel's scope.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use poise::serenity_prelude::{
+    Attachment, CreateEmbed, CreateEmbedFooter,
+    futures::{self, Stream},
+};
+
+use crate::{
+    commands::{
+        Context, EMBED_FIELD_LIMIT, EMBED_OK_TUPLE, Error, capitalize_string, create_error_embed,
+        create_quick_success_embed, get_namespace, is_gm, join_lines_to_fit, shorten,
+    },
+    tables::{parse_table, roll_table},
+};
+
+/// Uploaded tables larger than this are rejected.
+const MAX_TABLE_BYTES: u32 = 256 * 1024;
+/// Discord rejects embeds with more than 25 fields.
+const MAX_LINKED_ROLLS: usize = 25;
+/// Discord rejects embeds with more text than this, all told. Rolls are cut short to fit.
+const EMBED_TOTAL_LIMIT: usize = 6000;
+/// How much of that the roll on the table itself gets, leaving the rest for linked rolls.
+const ROLLED_TEXT_LIMIT: usize = 2048;
+/// The longest title Discord allows a field.
+const FIELD_NAME_LIMIT: usize = 256;
+
+pub async fn table_name_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Stream<Item = String> + 'a {
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return futures::stream::iter(vec![]);
+    };
+    let partial = partial.to_owned();
+    let items = ctx
+        .data()
+        .db
+        .read(move |db| db.get_available_tables(&namespace, &partial))
+        .await
+        .unwrap_or_default();
+
+    futures::stream::iter(items)
+}
+
+/// Upload a random table as a CSV or markdown file. Only the GM can do this.
+///
+/// **Example Usage:**
+/// `/table upload name:encounters file:encounters.md` - Save a table, rolled with 1dN where N is its highest entry
+/// `/table upload name:treasure file:treasure.csv die:2d6` - Save a table rolled with 2d6
+///
+/// Each line is a range and an entry, such as `1-3 | 2d4 goblins` or `4,A wandering merchant`.
+/// Dice in an entry are rolled, and `{name}` rolls on the table called `name`.
+/// Uploading a table with an existing name replaces it.
+#[poise::command(slash_command)]
+pub async fn upload(
+    ctx: Context<'_>,
+    #[description = "What's the name of the table?"] name: String,
+    #[description = "CSV or markdown file with lines like `1-3 | Goblins`"] file: Attachment,
+    #[description = "Dice to roll on the table (default: 1dN)"] die: Option<String>,
+) -> Result<(), Error> {
+    let embed = if !is_gm(ctx).await {
+        create_error_embed("table error", "Only the GM can upload tables.")
+    } else if file.size > MAX_TABLE_BYTES {
+        create_error_embed("table too large", "Tables can be at most 256KB.")
+    } else {
+        let source = String::from_utf8(file.download().await?).unwrap_or_default();
+        match parse_table(&get_namespace(ctx).await?, &name, &source, die) {
+            Ok(table) => match ctx
+                .data()
+                .db
+                .write(move |db| {
+                    db.save_table(&table)?;
+                    Ok(table)
+                })
+                .await
+            {
+                Ok(table) => create_quick_success_embed(
+                    "saved the table!",
+                    &format!(
+                        "Saved {name} with {} entries, rolled with {}.",
+                        table.entries.len(),
+                        table.die
+                    ),
+                ),
+                Err(e) => {
+                    println!("{}", e);
+                    create_error_embed("internal error", "Could not save your table.")
+                }
+            },
+            Err(message) => create_error_embed("invalid table", &message),
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Roll on a random table.
+///
+/// **Example Usage:**
+/// `/table roll name:encounters` - Roll on the "encounters" table
+#[poise::command(slash_command)]
+pub async fn roll(
+    ctx: Context<'_>,
+    #[description = "Name of the table?"]
+    #[autocomplete = "table_name_autocomplete"]
+    name: String,
+    #[description = "Keep roll private?"] keep_private: Option<bool>,
+) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    // linked tables are looked up as the roll goes, so the whole roll happens on the database's thread.
+    let rolled = ctx
+        .data()
+        .db
+        .read(move |db| {
+            let table = db.get_table(&namespace, &name)?;
+            let rolls = roll_table(&table, |link| {
+                db.get_table(&namespace, &link.to_owned()).ok()
+            });
+            Ok((table, rolls))
+        })
+        .await;
+
+    let embed = match rolled {
+        Ok((table, rolls)) => match rolls {
+            Ok(rolls) => {
+                let title = capitalize_string(&table.name);
+                let footer = format!("Rolled {} on {}", rolls[0].roll, table.die);
+                let text = shorten(&rolls[0].text, ROLLED_TEXT_LIMIT);
+                let mut room = EMBED_TOTAL_LIMIT
+                    .saturating_sub(title.chars().count() + footer.chars().count())
+                    .saturating_sub(text.chars().count());
+                let mut fields = Vec::new();
+                for table_roll in rolls.iter().skip(1).take(MAX_LINKED_ROLLS) {
+                    let name = shorten(
+                        &format!("{} ({})", table_roll.table, table_roll.roll),
+                        FIELD_NAME_LIMIT,
+                    );
+                    let value = shorten(&table_roll.text, EMBED_FIELD_LIMIT);
+                    let length = name.chars().count() + value.chars().count();
+                    if length > room {
+                        break;
+                    }
+                    room -= length;
+                    fields.push((name, value, false));
+                }
+                CreateEmbed::new()
+                    .color(*EMBED_OK_TUPLE)
+                    .title(title)
+                    .description(text)
+                    .fields(fields)
+                    .footer(CreateEmbedFooter::new(footer))
+            }
+            Err(message) => create_error_embed("table roll error", &message),
+        },
+        Err(_) => create_error_embed(
+            "table not found",
+            "Could not find the table you were looking for.",
+        ),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: keep_private,
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// List the random tables saved in this server.
+#[poise::command(slash_command)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let embed = match ctx
+        .data()
+        .db
+        .read(move |db| db.get_available_tables(&namespace, ""))
+        .await
+    {
+        Ok(names) if names.is_empty() => create_quick_success_embed(
+            "random tables",
+            "No tables yet. Upload one with `/table upload`.",
+        ),
+        Ok(names) => create_quick_success_embed("random tables", &join_lines_to_fit(&names)),
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not list your tables.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a random table. Only the GM can do this.
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the table?"]
+    #[autocomplete = "table_name_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "table error",
+                "Only the GM can remove tables.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+    let namespace = get_namespace(ctx).await?;
+    let removed_name = name.clone();
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.remove_table(&namespace, &removed_name))
+        .await
+    {
+        Ok(_) => create_quick_success_embed("removed table.", &format!("Removed {name}.")),
+        Err(e) => create_error_embed("table error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Upload and roll on random tables.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands("upload", "roll", "list", "remove")
+)]
+pub async fn table(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
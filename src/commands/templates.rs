@@ -0,0 +1,281 @@
+use poise::serenity_prelude::futures::{self, Stream};
+
+use crate::{
+    clock::{Category, built_in_templates, check_new_clock},
+    commands::{
+        Context, Error, clock_tag_autocomplete, clocks::describe_labels, create_error_embed,
+        create_quick_success_embed, get_namespace, is_gm, join_lines_to_fit,
+    },
+    db::{ClockLabels, ClockTemplate, ProgressClock},
+    svg::parse_color,
+};
+
+/// Finds the template called `name`, ignoring case. This server's own templates come before the built-in ones.
+pub async fn find_clock_template(
+    ctx: Context<'_>,
+    namespace: &str,
+    name: &str,
+) -> Option<ClockTemplate> {
+    let (template_namespace, template_name) = (namespace.to_owned(), name.to_owned());
+    match ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_template(&template_namespace, &template_name))
+        .await
+    {
+        Ok(Some(template)) => Some(template),
+        Ok(None) => built_in_templates()
+            .into_iter()
+            .find(|template| template.name.eq_ignore_ascii_case(name)),
+        Err(e) => {
+            println!("{}", e);
+            None
+        }
+    }
+}
+
+pub async fn clock_template_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Stream<Item = String> + 'a {
+    let Ok(namespace) = get_namespace(ctx).await else {
+        return futures::stream::iter(vec![]);
+    };
+    let partial = partial.to_owned();
+    let mut items: Vec<String> = ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_templates(&namespace, ""))
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|template| template.name)
+        .collect();
+    for template in built_in_templates() {
+        if !items
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&template.name))
+        {
+            items.push(template.name);
+        }
+    }
+    let partial = partial.to_lowercase();
+    items.retain(|name| name.to_lowercase().contains(&partial));
+
+    futures::stream::iter(items)
+}
+
+/// Describes the clocks a template makes on one line, like "4 segments, red, ephemeral".
+fn describe_template(template: &ClockTemplate) -> String {
+    let mut parts = vec![format!("{} segments", template.segments)];
+    if let Some(color) = &template.color {
+        parts.push(color.clone());
+    }
+    if template.ephemeral {
+        parts.push(String::from("ephemeral"));
+    }
+    parts.extend(describe_labels(&template.labels));
+    parts.join(", ")
+}
+
+/// Save a template to make progress clocks from with `/add_progress_clock template:`.
+///
+/// **Example Usage:**
+/// `/clock_template save name:Heist Alarm segments:4 color:red ephemeral:true` - Save a template for quick alarm clocks
+/// `/clock_template save name:Faction Project segments:8 category:Faction` - Save a template for faction clocks
+///
+/// Saving a template with an existing name replaces it. Only the GM can save templates.
+#[poise::command(slash_command)]
+pub async fn save(
+    ctx: Context<'_>,
+    #[description = "What's the name of the template?"] name: String,
+    #[description = "How many segments do its clocks have?"] segments: u8,
+    #[description = "What colour are its clocks? (html name or hex code)"] color: Option<String>,
+    #[description = "Do its clocks expire after a day?"] ephemeral: Option<bool>,
+    #[description = "What kind of thing do its clocks track?"] category: Option<Category>,
+    #[description = "Tags for its clocks, separated by commas"]
+    #[autocomplete = "clock_tag_autocomplete"]
+    tags: Option<String>,
+) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "template error",
+                "Only the GM can save templates.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+    let template = ClockTemplate {
+        namespace: get_namespace(ctx).await?,
+        name: name.trim().to_owned(),
+        segments,
+        // an invalid colour is kept as it was given, for the check below to explain.
+        color: color.map(|color| parse_color(&color).unwrap_or(color)),
+        ephemeral: ephemeral.unwrap_or(false),
+        labels: ClockLabels {
+            category: category.map(Category::category),
+            tags: tags
+                .as_deref()
+                .map(ClockLabels::parse_tags)
+                .unwrap_or_default(),
+        },
+    };
+    let description = describe_template(&template);
+
+    // a template is only worth keeping if the clocks it makes would be.
+    let saved = check_new_clock(&ProgressClock {
+        namespace: template.namespace.clone(),
+        name: template.name.clone(),
+        segments: template.segments,
+        segments_filled: 0,
+        ephemeral: template.ephemeral,
+        color: template.color.clone(),
+    });
+    let saved = match saved {
+        Ok(_) => {
+            ctx.data()
+                .db
+                .write(move |db| db.save_clock_template(&template))
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    let embed = match saved {
+        Ok(_) => create_quick_success_embed(&format!("saved template {name}"), &description),
+        Err(e) => create_error_embed("template error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// List the clock templates this server can use, including the built-in ones.
+#[poise::command(slash_command)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let namespace = get_namespace(ctx).await?;
+    let embed = match ctx
+        .data()
+        .db
+        .read(move |db| db.get_clock_templates(&namespace, ""))
+        .await
+    {
+        Ok(templates) => {
+            let describe = |template: &ClockTemplate| {
+                format!("**{}** — {}", template.name, describe_template(template))
+            };
+            let mut lines: Vec<String> = templates.iter().map(describe).collect();
+            if lines.is_empty() {
+                lines.push(String::from(
+                    "No templates of your own yet. Save one with `/clock_template save`.",
+                ));
+            }
+            lines.push(String::from("\n**Built in:**"));
+            lines.extend(
+                built_in_templates()
+                    .iter()
+                    .filter(|built_in| {
+                        !templates
+                            .iter()
+                            .any(|template| template.name.eq_ignore_ascii_case(&built_in.name))
+                    })
+                    .map(describe),
+            );
+            create_quick_success_embed("clock templates", &join_lines_to_fit(&lines))
+        }
+        Err(e) => {
+            println!("{}", e);
+            create_error_embed("internal error", "Could not list your templates.")
+        }
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a clock template. Clocks already made from it stay as they are.
+///
+/// Only the GM can remove templates.
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the template?"]
+    #[autocomplete = "clock_template_autocomplete"]
+    name: String,
+) -> Result<(), Error> {
+    if !is_gm(ctx).await {
+        ctx.send(poise::CreateReply {
+            embeds: vec![create_error_embed(
+                "template error",
+                "Only the GM can remove templates.",
+            )],
+            ephemeral: Some(true),
+            reply: true,
+            ..Default::default()
+        })
+        .await?;
+        return Ok(());
+    }
+    let namespace = get_namespace(ctx).await?;
+    let removed_name = name.clone();
+    let embed = match ctx
+        .data()
+        .db
+        .write(move |db| db.remove_clock_template(&namespace, &removed_name))
+        .await
+    {
+        Ok(_) => create_quick_success_embed("removed template.", &format!("Removed {name}.")),
+        Err(_)
+            if built_in_templates()
+                .iter()
+                .any(|template| template.name.eq_ignore_ascii_case(&name)) =>
+        {
+            create_error_embed(
+                "template error",
+                &format!(
+                    "{name} is built in, so it can't be removed. Saving a template called {name} replaces it."
+                ),
+            )
+        }
+        Err(e) => create_error_embed("template error", &e.to_string()),
+    };
+
+    ctx.send(poise::CreateReply {
+        embeds: vec![embed],
+        ephemeral: Some(true),
+        reply: true,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Save templates to make progress clocks from.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands("save", "list", "remove")
+)]
+pub async fn clock_template(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
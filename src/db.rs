@@ -1,77 +1,2692 @@
-use rusqlite::Connection;
+mod export;
+mod migrations;
+mod pool;
+mod storage;
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+pub use export::{ClockExport, ExportFormat, ImportConflict};
+pub use pool::DbPool;
+pub use storage::ClockStorage;
+#[cfg(test)]
+pub use storage::MemoryStorage;
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
-/// helps retrieve SQL schemas & other stuff for structs implementing this trait.
-pub trait ORM {
-    fn schema() -> &'static str;
-}
+/// where the bot and the offline CLI keep their data, unless given `--database`.
+pub const DATABASE_PATH: &str = "./troller.sqlite";
+
+/// who timed ticks are recorded as in a clock's history.
+const SCHEDULE_ACTOR: &str = "schedule";
+
+/// helps retrieve SQL schemas & other stuff for structs implementing this trait.
+/// `schema` is the table as it was first created. Later changes to it are steps in `migrations`.
+#[allow(clippy::upper_case_acronyms)]
+pub trait ORM {
+    fn schema() -> &'static str;
+}
+
+/// an SQLite db handle with the following schema:
+/// progress_clock(namespace TEXT, name TEXT, segments INTEGER, segments_filled INTEGER, creation_time DATETIME, ephemeral BOOL, color TEXT, deleted_at DATETIME,
+///     expires_at DATETIME, expires_with_session BOOL, announce_channel_id INTEGER)
+pub struct DB {
+    connection: Connection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressClock {
+    /// left out of exports, so clocks can be imported anywhere.
+    #[serde(skip)]
+    pub namespace: String,
+    pub name: String,
+    pub segments: u8,
+    pub segments_filled: u8,
+    pub ephemeral: bool,
+    pub color: Option<String>,
+}
+
+impl ORM for ProgressClock {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS progress_clock(
+            namespace TEXT,
+            name TEXT,
+            segments INTEGER,
+            segments_filled INTEGER,
+            creation_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+            ephemeral BOOL,
+            PRIMARY KEY(namespace, name)
+        );
+        CREATE INDEX IF NOT EXISTS progress_clock_ns ON progress_clock(namespace);
+        CREATE INDEX IF NOT EXISTS progress_clock_name ON progress_clock(name);
+        "
+    }
+}
+
+/// Removed clocks can be brought back with `/clock undo` for this many days.
+const REMOVED_CLOCK_GRACE_DAYS: f64 = 1.0;
+/// How long an ephemeral clock lasts unless it's given a lifetime, as an SQLite date modifier.
+const DEFAULT_CLOCK_LIFETIME: &str = "+1 day";
+/// How long the buttons on a `/roll` reply keep working, as an SQLite date modifier.
+const ROLL_MESSAGE_RETENTION: &str = "-30 days";
+/// `/clock history` lists at most this many changes.
+const MAX_CLOCK_HISTORY: usize = 20;
+/// Linked clocks filling each other stop after this many rounds, in case they're linked in a loop that keeps
+/// emptying and refilling.
+const MAX_COMPLETION_ROUNDS: usize = 8;
+/// A clock keeps at most this many tags, so its labels fit on a line of `/clocks list`.
+pub const MAX_CLOCK_TAGS: usize = 8;
+/// Tags are cut to this many characters.
+const MAX_TAG_CHARS: usize = 24;
+
+/// Leaves out changes to clocks only GMs may change, unless `?2` is set.
+static RESTRICTED_CLOCK_EVENTS: &str = "(?2 OR NOT EXISTS (
+        SELECT 1 FROM progress_clock AS clock
+        WHERE clock.namespace = progress_clock_event.namespace
+        AND clock.name IN (progress_clock_event.before_name, progress_clock_event.after_name)
+        AND clock.access != 'everyone'
+    ))";
+
+static CLOCK_EVENT_COLUMNS: &str =
+    "id, action, actor, CAST(strftime('%s', time) AS INTEGER), undone,
+    before_name, before_segments, before_segments_filled, before_ephemeral, before_color,
+    after_name, after_segments, after_segments_filled, after_ephemeral, after_color";
+
+/// A change made to a progress clock, with the clock's state before and after it.
+/// `before` is `None` for a newly created clock, and `after` is `None` for a removed one.
+#[derive(Serialize, Deserialize)]
+pub struct ClockEvent {
+    #[serde(skip)]
+    pub id: i64,
+    pub action: String,
+    pub actor: String,
+    /// unix timestamp of the change.
+    pub time: i64,
+    #[serde(skip)]
+    pub undone: bool,
+    pub before: Option<ProgressClock>,
+    pub after: Option<ProgressClock>,
+}
+
+impl ORM for ClockEvent {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS progress_clock_event(
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            namespace TEXT,
+            action TEXT,
+            actor TEXT,
+            time DATETIME DEFAULT CURRENT_TIMESTAMP,
+            undone BOOL DEFAULT 0,
+            before_name TEXT,
+            before_segments INTEGER,
+            before_segments_filled INTEGER,
+            before_ephemeral BOOL,
+            before_color TEXT,
+            after_name TEXT,
+            after_segments INTEGER,
+            after_segments_filled INTEGER,
+            after_ephemeral BOOL,
+            after_color TEXT
+        );
+        CREATE INDEX IF NOT EXISTS progress_clock_event_ns ON progress_clock_event(namespace);
+        "
+    }
+}
+
+impl ClockEvent {
+    /// Reads the clock's state from the five columns starting at `idx`.
+    fn snapshot_from_row(
+        namespace: &str,
+        row: &rusqlite::Row,
+        idx: usize,
+    ) -> rusqlite::Result<Option<ProgressClock>> {
+        let Some(name) = row.get::<_, Option<String>>(idx)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(ProgressClock {
+            namespace: namespace.to_owned(),
+            name,
+            segments: row.get(idx + 1)?,
+            segments_filled: row.get(idx + 2)?,
+            ephemeral: row.get(idx + 3)?,
+            color: row.get(idx + 4)?,
+        }))
+    }
+
+    fn from_row(namespace: &str, row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(ClockEvent {
+            id: row.get(0)?,
+            action: row.get(1)?,
+            actor: row.get(2)?,
+            time: row.get(3)?,
+            undone: row.get(4)?,
+            before: Self::snapshot_from_row(namespace, row, 5)?,
+            after: Self::snapshot_from_row(namespace, row, 10)?,
+        })
+    }
+
+    /// The clock's name after the change, or before it if the clock was removed.
+    pub fn name(&self) -> &String {
+        self.after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map(|progress_clock| &progress_clock.name)
+            .expect("a clock event has a state before or after the change")
+    }
+}
+
+/// a channel that has opted into inline `[[dice]]` rolls in ordinary messages.
+pub struct InlineRollChannel;
+
+impl ORM for InlineRollChannel {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS inline_roll_channel(
+            channel_id INTEGER PRIMARY KEY
+        );
+        "
+    }
+}
+
+/// when an ephemeral clock expires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockExpiry {
+    /// this many hours from now.
+    Hours(u32),
+    /// when the session in its namespace ends.
+    SessionEnd,
+}
+
+/// when a clock ticks on its own, and by how much.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClockSchedule {
+    /// ticks every this many hours, or never on a timer for `None`.
+    pub every_hours: Option<u32>,
+    /// segments filled by each tick.
+    pub count: u8,
+    /// also ticks when the session in its namespace starts.
+    pub on_session_start: bool,
+    /// unix timestamp of the next timed tick. Set from `every_hours` when the schedule is saved.
+    pub next_tick_at: Option<i64>,
+}
+
+impl ClockSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.every_hours.is_none() && !self.on_session_start
+    }
+}
+
+/// who may see and change a progress clock, besides GMs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClockAccess {
+    #[default]
+    Everyone,
+    /// players may see the clock, but only GMs change it.
+    ViewOnly,
+    /// only GMs know the clock exists.
+    GmOnly,
+}
+
+impl ClockAccess {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Everyone => "everyone",
+            Self::ViewOnly => "view",
+            Self::GmOnly => "gm",
+        }
+    }
+
+    fn from_str(access: &str) -> Self {
+        match access {
+            "view" => Self::ViewOnly,
+            "gm" => Self::GmOnly,
+            _ => Self::Everyone,
+        }
+    }
+}
+
+/// the role that marks a server's GMs.
+pub struct GmRole;
+
+impl ORM for GmRole {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS gm_role(
+            guild_id INTEGER PRIMARY KEY,
+            role_id INTEGER
+        );
+        "
+    }
+}
+
+/// a guild or user name that clocks, macros and tables were stored under, before namespaces used IDs,
+/// and that hasn't been moved to its ID yet.
+pub struct LegacyNamespace;
+
+impl ORM for LegacyNamespace {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS legacy_namespace(
+            name TEXT PRIMARY KEY,
+            recorded_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        "
+    }
+}
+
+/// what kind of thing a progress clock tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClockCategory {
+    Faction,
+    Project,
+    Threat,
+    LongTerm,
+}
+
+impl ClockCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Faction => "faction",
+            Self::Project => "project",
+            Self::Threat => "threat",
+            Self::LongTerm => "long-term",
+        }
+    }
+
+    fn from_str(category: &str) -> Option<Self> {
+        match category {
+            "faction" => Some(Self::Faction),
+            "project" => Some(Self::Project),
+            "threat" => Some(Self::Threat),
+            "long-term" => Some(Self::LongTerm),
+            _ => None,
+        }
+    }
+}
+
+/// how a progress clock is filed, so it can be found among many.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClockLabels {
+    pub category: Option<ClockCategory>,
+    /// lowercase, without commas, in the order they were added.
+    pub tags: Vec<String>,
+}
+
+impl ClockLabels {
+    /// Splits comma-separated tags, lowercasing them and dropping blanks and repeats.
+    /// Long tags are cut short, and only the first `MAX_CLOCK_TAGS` are kept.
+    pub fn parse_tags(tags: &str) -> Vec<String> {
+        let mut parsed: Vec<String> = Vec::new();
+        for tag in tags.split(',') {
+            let tag: String = tag
+                .trim()
+                .to_lowercase()
+                .chars()
+                .take(MAX_TAG_CHARS)
+                .collect();
+            let tag = tag.trim_end();
+            if !tag.is_empty() && !parsed.iter().any(|parsed_tag| parsed_tag == tag) {
+                parsed.push(tag.to_owned());
+            }
+        }
+        parsed.truncate(MAX_CLOCK_TAGS);
+        parsed
+    }
+
+    /// tags are stored as `,tag,other tag,` so one can be matched with its commas.
+    fn tags_column(&self) -> Option<String> {
+        (!self.tags.is_empty()).then(|| format!(",{},", self.tags.join(",")))
+    }
+
+    fn from_columns(category: Option<String>, tags: Option<String>) -> Self {
+        Self {
+            category: category.as_deref().and_then(ClockCategory::from_str),
+            tags: tags.as_deref().map(Self::parse_tags).unwrap_or_default(),
+        }
+    }
+}
+
+/// which clocks `DB::list_clocks` returns. Filters left as `None` match every clock.
+#[derive(Debug, Clone, Default)]
+pub struct ClockFilter {
+    /// part of the clock's name.
+    pub partial: String,
+    pub category: Option<ClockCategory>,
+    pub tag: Option<String>,
+}
+
+/// the order `DB::list_clocks` returns clocks in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockOrder {
+    Name,
+    MostComplete,
+    LeastComplete,
+    Newest,
+}
+
+impl ClockOrder {
+    fn order_by(&self) -> &'static str {
+        match self {
+            Self::Name => "name COLLATE NOCASE",
+            Self::MostComplete => {
+                "CAST(segments_filled AS REAL) / MAX(segments, 1) DESC, name COLLATE NOCASE"
+            }
+            Self::LeastComplete => {
+                "CAST(segments_filled AS REAL) / MAX(segments, 1), name COLLATE NOCASE"
+            }
+            Self::Newest => "creation_time DESC, name COLLATE NOCASE",
+        }
+    }
+}
+
+/// an ephemeral clock that has expired and been deleted.
+pub struct ExpiredClock {
+    pub namespace: String,
+    pub name: String,
+    /// channel to announce the expiry in, if it should be announced.
+    pub announce_channel_id: Option<u64>,
+}
+
+/// narrows the namespace of a channel's clocks, macros and tables, e.g. `channel:<id>` or `campaign:<name>`.
+pub struct NamespaceScope;
+
+impl ORM for NamespaceScope {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS namespace_scope(
+            channel_id INTEGER PRIMARY KEY,
+            scope TEXT
+        );
+        "
+    }
+}
+
+/// a message showing a clock, or a board of every clock in a namespace, that is edited whenever they change.
+#[derive(Debug, Clone)]
+pub struct PinnedMessage {
+    pub message_id: u64,
+    pub channel_id: u64,
+    pub namespace: String,
+    /// the clock the message shows, or `None` for a board.
+    pub clock_name: Option<String>,
+}
+
+impl ORM for PinnedMessage {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS pinned_message(
+            message_id INTEGER PRIMARY KEY,
+            channel_id INTEGER,
+            namespace TEXT,
+            clock_name TEXT
+        );
+        CREATE INDEX IF NOT EXISTS pinned_message_ns ON pinned_message(namespace);
+        "
+    }
+}
+
+/// what happens when a progress clock fills up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClockTrigger {
+    /// channel the message and role ping are posted in.
+    pub channel_id: Option<u64>,
+    pub message: Option<String>,
+    pub role_id: Option<u64>,
+    /// another clock in the same namespace that's bumped by `linked_count` segments.
+    pub linked_clock: Option<String>,
+    pub linked_count: u8,
+    /// a file in the music directory, played if the bot is in a voice channel.
+    pub sound: Option<String>,
+}
+
+impl ClockTrigger {
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none()
+            && self.role_id.is_none()
+            && self.linked_clock.is_none()
+            && self.sound.is_none()
+    }
+}
+
+/// how two progress clocks are tied together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockLinkKind {
+    /// both race to fill first, like a heist's alarm against its escape.
+    Race,
+    /// the clock ticks its parent, the other clock, when it fills.
+    Child,
+    /// both share one clock, filling it from opposite sides and pushing each other back.
+    TugOfWar,
+}
+
+impl ClockLinkKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Race => "race",
+            Self::Child => "child",
+            Self::TugOfWar => "tug",
+        }
+    }
+
+    /// How the kind reads in a sentence, as in "already in a race".
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Race => "a race",
+            Self::Child => "child and parent",
+            Self::TugOfWar => "a tug of war",
+        }
+    }
+
+    fn from_str(kind: &str) -> Option<Self> {
+        match kind {
+            "race" => Some(Self::Race),
+            "child" => Some(Self::Child),
+            "tug" => Some(Self::TugOfWar),
+            _ => None,
+        }
+    }
+}
+
+/// a link between two clocks in the same namespace.
+/// races and tugs of war go both ways; a child link points from the child, `clock_name`, to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockLink {
+    pub namespace: String,
+    pub kind: ClockLinkKind,
+    pub clock_name: String,
+    pub other_name: String,
+}
+
+impl ClockLink {
+    /// The clock at the other end of the link from `name`.
+    pub fn other(&self, name: &str) -> &str {
+        if self.clock_name == name {
+            &self.other_name
+        } else {
+            &self.clock_name
+        }
+    }
+}
+
+impl ORM for ClockLink {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS clock_link(
+            namespace TEXT,
+            kind TEXT,
+            clock_name TEXT,
+            other_name TEXT,
+            PRIMARY KEY(namespace, clock_name, other_name)
+        );
+        "
+    }
+}
+
+/// a clock that filled up, with what it was set to do when it did.
+/// completions are queued as clocks fill, and taken by whatever acts on them.
+pub struct ClockCompletion {
+    pub namespace: String,
+    pub name: String,
+    pub trigger: ClockTrigger,
+    /// the linked clock after it was bumped, if it still exists.
+    pub linked: Option<ProgressClock>,
+    /// the parent clock after this one ticked it, if this is a child clock.
+    pub parent: Option<ProgressClock>,
+    /// the clock this one was racing, as it was when this one filled up.
+    pub rival: Option<ProgressClock>,
+}
+
+impl ORM for ClockCompletion {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS clock_completion(
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            namespace TEXT,
+            name TEXT,
+            channel_id INTEGER,
+            message TEXT,
+            role_id INTEGER,
+            linked_clock TEXT,
+            linked_count INTEGER,
+            sound TEXT
+        );
+        "
+    }
+}
+
+/// the dice string behind a `/roll` reply, kept so that its buttons can roll it again.
+#[derive(Debug)]
+pub struct RollMessage {
+    pub message_id: u64,
+    pub user_id: u64,
+    pub dice_string: String,
+}
+
+impl ORM for RollMessage {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS roll_message(
+            message_id INTEGER PRIMARY KEY,
+            user_id INTEGER,
+            dice_string TEXT,
+            creation_time DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        "
+    }
+}
+
+/// a named dice string that can be rolled by name.
+#[derive(Debug)]
+pub struct DiceMacro {
+    pub namespace: String,
+    pub name: String,
+    pub dice_string: String,
+}
+
+impl ORM for DiceMacro {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS dice_macro(
+            namespace TEXT,
+            name TEXT,
+            dice_string TEXT,
+            PRIMARY KEY(namespace, name)
+        );
+        "
+    }
+}
+
+/// a combatant in an initiative tracker.
+#[derive(Debug, Clone)]
+pub struct Combatant {
+    pub name: String,
+    /// total of the initiative roll.
+    pub initiative: u32,
+    /// random number that settles ties between equal initiatives.
+    pub tiebreaker: u32,
+    pub npc: bool,
+    /// delayed combatants are skipped until they resume.
+    pub delayed: bool,
+}
+
+/// the turn order of an encounter in a channel, stored with the following schema:
+/// initiative_tracker(channel_id INTEGER, round INTEGER, turn INTEGER, message_id INTEGER)
+/// initiative_combatant(channel_id INTEGER, name TEXT, position INTEGER, initiative INTEGER, tiebreaker INTEGER, npc BOOL, delayed BOOL)
+#[derive(Debug)]
+pub struct InitiativeTracker {
+    pub channel_id: u64,
+    pub round: u32,
+    /// index of the combatant whose turn it is.
+    pub turn: usize,
+    /// the message that displays the tracker, if one has been posted.
+    pub message_id: Option<u64>,
+    /// combatants in turn order.
+    pub combatants: Vec<Combatant>,
+}
+
+impl ORM for InitiativeTracker {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS initiative_tracker(
+            channel_id INTEGER PRIMARY KEY,
+            round INTEGER,
+            turn INTEGER,
+            message_id INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS initiative_combatant(
+            channel_id INTEGER,
+            name TEXT,
+            position INTEGER,
+            initiative INTEGER,
+            tiebreaker INTEGER,
+            npc BOOL,
+            delayed BOOL,
+            PRIMARY KEY(channel_id, name)
+        );
+        "
+    }
+}
+
+/// a row of a random table, picked when the table's die lands between `low` and `high`.
+#[derive(Debug, Clone)]
+pub struct TableEntry {
+    pub low: u32,
+    pub high: u32,
+    pub text: String,
+}
+
+/// a random table uploaded to a namespace, stored with the following schema:
+/// random_table(namespace TEXT, name TEXT, die TEXT)
+/// random_table_entry(namespace TEXT, table_name TEXT, low INTEGER, high INTEGER, text TEXT)
+#[derive(Debug)]
+pub struct RandomTable {
+    pub namespace: String,
+    pub name: String,
+    /// the dice string rolled to pick an entry.
+    pub die: String,
+    pub entries: Vec<TableEntry>,
+}
+
+impl ORM for RandomTable {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS random_table(
+            namespace TEXT,
+            name TEXT,
+            die TEXT,
+            PRIMARY KEY(namespace, name)
+        );
+        CREATE TABLE IF NOT EXISTS random_table_entry(
+            namespace TEXT,
+            table_name TEXT,
+            low INTEGER,
+            high INTEGER,
+            text TEXT
+        );
+        CREATE INDEX IF NOT EXISTS random_table_entry_table ON random_table_entry(namespace, table_name);
+        "
+    }
+}
+
+/// the settings a new progress clock starts with, saved under a name to make clocks from.
+/// clock_template(namespace TEXT, name TEXT, segments INTEGER, color TEXT, ephemeral BOOL, category TEXT, tags TEXT)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockTemplate {
+    pub namespace: String,
+    pub name: String,
+    pub segments: u8,
+    pub color: Option<String>,
+    pub ephemeral: bool,
+    pub labels: ClockLabels,
+}
+
+impl ORM for ClockTemplate {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS clock_template(
+            namespace TEXT,
+            name TEXT,
+            segments INTEGER,
+            color TEXT,
+            ephemeral BOOL,
+            category TEXT,
+            tags TEXT,
+            PRIMARY KEY(namespace, name)
+        );
+        "
+    }
+}
+
+/// the shape a progress clock is drawn as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockStyle {
+    /// a circle cut into wedges, as clocks are drawn in Blades in the Dark.
+    Pie,
+    /// a circle of segments around an empty middle.
+    Ring,
+    /// a row of segments, filled from left to right.
+    Bar,
+}
+
+impl ClockStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pie => "pie",
+            Self::Ring => "ring",
+            Self::Bar => "bar",
+        }
+    }
+
+    fn from_str(style: &str) -> Option<Self> {
+        match style {
+            "pie" => Some(Self::Pie),
+            "ring" => Some(Self::Ring),
+            "bar" => Some(Self::Bar),
+            _ => None,
+        }
+    }
+}
+
+/// how a namespace's clocks are drawn.
+/// clock_theme(namespace TEXT, palette TEXT, spoke_color TEXT, background TEXT, style TEXT, size INTEGER,
+///     shade_opacity REAL)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockTheme {
+    /// colours for clocks that weren't given one. Each clock keeps to one of them, picked by its name.
+    pub palette: Vec<String>,
+    /// the colour of the rim and spokes, and of the labels on boards.
+    pub spoke_color: String,
+    /// drawn behind the clocks. Without one, they're drawn on whatever Discord shows behind the image.
+    pub background: Option<String>,
+    pub style: ClockStyle,
+    /// the width of a clock in pixels. Boards and groups grow to match.
+    pub size: u32,
+    /// how opaque filled segments are, from 0 to 1.
+    pub shade_opacity: f32,
+}
+
+impl Default for ClockTheme {
+    fn default() -> Self {
+        ClockTheme {
+            palette: vec![String::from("green")],
+            spoke_color: String::from("white"),
+            background: None,
+            style: ClockStyle::Pie,
+            size: 200,
+            shade_opacity: 0.4,
+        }
+    }
+}
+
+impl ORM for ClockTheme {
+    fn schema() -> &'static str {
+        "
+        CREATE TABLE IF NOT EXISTS clock_theme(
+            namespace TEXT PRIMARY KEY,
+            palette TEXT,
+            spoke_color TEXT,
+            background TEXT
+        );
+        "
+    }
+}
+
+impl DB {
+    /// Opens the database at `path` for reading and writing, bringing its schema up to date.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let connection = Connection::open(path)?;
+        // the write-ahead log lets read-only connections read while this one writes.
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        migrations::migrate(&connection)?;
+
+        let db = DB { connection };
+        db.purge_removed_clocks()?;
+
+        Ok(db)
+    }
+
+    /// Opens the database at `path` for reading only. Its schema must already be up to date.
+    pub fn open_read_only(path: &Path) -> Result<Self, Error> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        Ok(DB { connection })
+    }
+
+    /// Runs `unit` so that either all of its changes are saved or none are.
+    /// Unlike a transaction, units can be nested, so a unit can be built out of others.
+    fn atomically<T, F>(&self, unit: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error>,
+    {
+        self.connection.execute_batch("SAVEPOINT unit;")?;
+        match unit() {
+            Ok(value) => {
+                self.connection.execute_batch("RELEASE unit;")?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.connection
+                    .execute_batch("ROLLBACK TO unit; RELEASE unit;")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `change` on the clock called `name`, and records the clock's state before and after in its history.
+    /// `change` returns the clock's name afterwards, as it may have been renamed.
+    fn record_clock_change<F>(
+        &self,
+        namespace: &str,
+        name: &str,
+        action: &str,
+        actor: &str,
+        change: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce() -> Result<String, Error>,
+    {
+        self.atomically(|| {
+            let before = self.get_clock(namespace, name).ok();
+            let after_name = change()?;
+            let after = self.get_clock(namespace, &after_name).ok();
+            if before.is_none() && after.is_none() {
+                return Err(format!("There's no clock named {name}.").into());
+            }
+            if after.is_some() && after_name != name {
+                self.rename_clock_references(namespace, name, &after_name)?;
+            }
+            self.queue_clock_completion(namespace, before.as_ref(), after.as_ref())?;
+
+            self.insert_clock_event(namespace, action, actor, before.as_ref(), after.as_ref())?;
+            if let Some(after) = &after {
+                self.push_back_tug_opponent(namespace, after, actor)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds a change to the history of clocks in `namespace`.
+    fn insert_clock_event(
+        &self,
+        namespace: &str,
+        action: &str,
+        actor: &str,
+        before: Option<&ProgressClock>,
+        after: Option<&ProgressClock>,
+    ) -> Result<(), Error> {
+        // a new change replaces the changes that could have been redone.
+        // scheduled ticks aren't anyone's change, so they leave what could be redone alone.
+        if actor != SCHEDULE_ACTOR {
+            self.connection.execute(
+                "DELETE FROM progress_clock_event WHERE namespace = ?1 AND undone = 1;",
+                rusqlite::params![namespace],
+            )?;
+        }
+        self.connection.execute(
+            "INSERT INTO progress_clock_event
+            (namespace, action, actor,
+                before_name, before_segments, before_segments_filled, before_ephemeral, before_color,
+                after_name, after_segments, after_segments_filled, after_ephemeral, after_color)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13);",
+            rusqlite::params![
+                namespace,
+                action,
+                actor,
+                before.map(|clock| &clock.name),
+                before.map(|clock| clock.segments),
+                before.map(|clock| clock.segments_filled),
+                before.map(|clock| clock.ephemeral),
+                before.and_then(|clock| clock.color.as_ref()),
+                after.map(|clock| &clock.name),
+                after.map(|clock| clock.segments),
+                after.map(|clock| clock.segments_filled),
+                after.map(|clock| clock.ephemeral),
+                after.and_then(|clock| clock.color.as_ref()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Empties segments of the clock `progress_clock` is in a tug of war with, so that the two never fill more
+    /// than the one clock they share. The push back is recorded as a change of its own.
+    fn push_back_tug_opponent(
+        &self,
+        namespace: &str,
+        progress_clock: &ProgressClock,
+        actor: &str,
+    ) -> Result<(), Error> {
+        let Some(link) = self
+            .get_clock_links(namespace, &progress_clock.name)?
+            .into_iter()
+            .find(|link| link.kind == ClockLinkKind::TugOfWar)
+        else {
+            return Ok(());
+        };
+        let Ok(before) = self.get_clock(namespace, link.other(&progress_clock.name)) else {
+            return Ok(());
+        };
+
+        let room = progress_clock
+            .segments
+            .saturating_sub(progress_clock.segments_filled);
+        if before.segments_filled <= room {
+            return Ok(());
+        }
+        self.connection.execute(
+            "UPDATE progress_clock SET segments_filled = ?1
+            WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;",
+            rusqlite::params![room, namespace, &before.name],
+        )?;
+        let after = ProgressClock {
+            segments_filled: room,
+            ..before.clone()
+        };
+        self.insert_clock_event(namespace, "push back", actor, Some(&before), Some(&after))
+    }
+
+    /// Returns the links the clock called `name` has to other clocks, from either end.
+    pub fn get_clock_links(&self, namespace: &str, name: &str) -> Result<Vec<ClockLink>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT kind, clock_name, other_name FROM clock_link
+            WHERE namespace = ?1 AND (clock_name = ?2 OR other_name = ?2);",
+        )?;
+        let mut links = Vec::new();
+        for link in statement.query_map(rusqlite::params![namespace, name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })? {
+            let (kind, clock_name, other_name) = link?;
+            let Some(kind) = ClockLinkKind::from_str(&kind) else {
+                continue;
+            };
+            links.push(ClockLink {
+                namespace: namespace.to_owned(),
+                kind,
+                clock_name,
+                other_name,
+            });
+        }
+        Ok(links)
+    }
+
+    /// Returns the parent of the clock called `name`, if it's a child clock.
+    fn get_parent_clock_name(&self, namespace: &str, name: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .get_clock_links(namespace, name)?
+            .into_iter()
+            .find(|link| link.kind == ClockLinkKind::Child && link.clock_name == name)
+            .map(|link| link.other_name))
+    }
+
+    /// Returns the children of the clock called `name` that still exist.
+    pub fn get_child_clocks(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Vec<ProgressClock>, Error> {
+        Ok(self
+            .get_clock_links(namespace, name)?
+            .into_iter()
+            .filter(|link| link.kind == ClockLinkKind::Child && link.other_name == name)
+            .filter_map(|link| self.get_clock(namespace, &link.clock_name).ok())
+            .collect())
+    }
+
+    /// Links two clocks. A clock races at most one other clock, tugs against at most one, and has at most one
+    /// parent. Clocks in a tug of war need the same number of segments, with room for both their progress.
+    pub fn link_clocks(&self, link: &ClockLink) -> Result<(), Error> {
+        let namespace = &link.namespace;
+        let clock = self
+            .get_clock(namespace, &link.clock_name)
+            .map_err(|_| format!("There's no clock named {}.", link.clock_name))?;
+        let other = self
+            .get_clock(namespace, &link.other_name)
+            .map_err(|_| format!("There's no clock named {}.", link.other_name))?;
+        if clock.name == other.name {
+            return Err("A clock can't be linked to itself.".into());
+        }
+        if let Some(existing) = self
+            .get_clock_links(namespace, &clock.name)?
+            .into_iter()
+            .find(|existing| existing.other(&clock.name) == other.name)
+        {
+            return Err(format!(
+                "{} and {} are already linked as {}.",
+                existing.clock_name,
+                existing.other_name,
+                existing.kind.description()
+            )
+            .into());
+        }
+
+        match link.kind {
+            ClockLinkKind::Race | ClockLinkKind::TugOfWar => {
+                for name in [&clock.name, &other.name] {
+                    if self
+                        .get_clock_links(namespace, name)?
+                        .iter()
+                        .any(|existing| existing.kind == link.kind)
+                    {
+                        return Err(
+                            format!("{name} is already in {}.", link.kind.description()).into()
+                        );
+                    }
+                }
+            }
+            ClockLinkKind::Child => {
+                if self
+                    .get_parent_clock_name(namespace, &clock.name)?
+                    .is_some()
+                {
+                    return Err(format!("{} already has a parent clock.", clock.name).into());
+                }
+                let mut ancestor = Some(other.name.clone());
+                while let Some(name) = ancestor {
+                    if name == clock.name {
+                        return Err(
+                            format!("{} can't be a child of its own child.", clock.name).into()
+                        );
+                    }
+                    ancestor = self.get_parent_clock_name(namespace, &name)?;
+                }
+            }
+        }
+
+        if link.kind == ClockLinkKind::TugOfWar {
+            if clock.segments != other.segments {
+                return Err("Clocks in a tug of war need the same number of segments.".into());
+            }
+            if clock.segments_filled + other.segments_filled > clock.segments {
+                return Err(format!(
+                    "{} and {} have filled more than one clock between them.",
+                    clock.name, other.name
+                )
+                .into());
+            }
+        }
+
+        self.connection.execute(
+            "INSERT INTO clock_link (namespace, kind, clock_name, other_name) VALUES (?1, ?2, ?3, ?4);",
+            rusqlite::params![namespace, link.kind.as_str(), &clock.name, &other.name],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the clock called `name` from its race or tug of war, or from its parent.
+    pub fn unlink_clock(
+        &self,
+        namespace: &str,
+        name: &str,
+        kind: ClockLinkKind,
+    ) -> Result<(), Error> {
+        let removed = self.connection.execute(
+            "DELETE FROM clock_link
+            WHERE namespace = ?1 AND kind = ?2 AND (clock_name = ?3 OR (other_name = ?3 AND kind != 'child'));",
+            rusqlite::params![namespace, kind.as_str(), name],
+        )?;
+        match (removed, kind) {
+            (0, ClockLinkKind::Child) => Err(format!("{name} has no parent clock.").into()),
+            (0, _) => Err(format!("{name} isn't linked like that.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes links to clocks that no longer exist, even as removed clocks.
+    fn forget_orphaned_links(&self) -> Result<(), Error> {
+        self.connection.execute(
+            "DELETE FROM clock_link
+            WHERE NOT EXISTS (
+                SELECT 1 FROM progress_clock
+                WHERE namespace = clock_link.namespace AND name = clock_link.clock_name
+            )
+            OR NOT EXISTS (
+                SELECT 1 FROM progress_clock
+                WHERE namespace = clock_link.namespace AND name = clock_link.other_name
+            );",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Permanently deletes a removed or expired clock called `name`, along with its history, so the name can be reused.
+    fn forget_removed_clock(&self, namespace: &str, name: &str) -> Result<(), Error> {
+        self.connection.execute(
+            "DELETE FROM progress_clock_event
+            WHERE namespace = ?1
+            AND ?2 IN (before_name, after_name)
+            AND EXISTS (
+                SELECT 1 FROM progress_clock
+                WHERE namespace = ?1 AND name = ?2
+                AND (deleted_at IS NOT NULL OR (ephemeral = 1 AND expires_at <= CURRENT_TIMESTAMP))
+            );",
+            rusqlite::params![namespace, name],
+        )?;
+        self.connection.execute(
+            "DELETE FROM progress_clock
+            WHERE namespace = ?1 AND name = ?2
+            AND (deleted_at IS NOT NULL OR (ephemeral = 1 AND expires_at <= CURRENT_TIMESTAMP));",
+            rusqlite::params![namespace, name],
+        )?;
+        self.forget_orphaned_links()
+    }
+
+    /// Permanently deletes ephemeral clocks that have expired, along with their history, and returns them.
+    pub fn purge_expired_clocks(&self) -> Result<Vec<ExpiredClock>, Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let mut statement = self.connection.prepare(
+            "SELECT namespace, name, announce_channel_id FROM progress_clock
+            WHERE deleted_at IS NULL AND ephemeral = 1 AND expires_at <= CURRENT_TIMESTAMP;",
+        )?;
+        let mut expired = Vec::new();
+        for clock in statement.query_map([], |row| {
+            Ok(ExpiredClock {
+                namespace: row.get(0)?,
+                name: row.get(1)?,
+                announce_channel_id: row.get::<_, Option<i64>>(2)?.map(|id| id as u64),
+            })
+        })? {
+            expired.push(clock?);
+        }
+
+        for clock in &expired {
+            self.forget_removed_clock(&clock.namespace, &clock.name)?;
+        }
+        transaction.commit()?;
+        Ok(expired)
+    }
+
+    /// Sets when an ephemeral clock expires. A clock that wasn't ephemeral becomes ephemeral.
+    pub fn set_clock_expiry(
+        &self,
+        namespace: &str,
+        name: &str,
+        expiry: ClockExpiry,
+    ) -> Result<(), Error> {
+        let (expires_at, expires_with_session) = match expiry {
+            ClockExpiry::Hours(hours) => (Some(format!("+{hours} hours")), false),
+            ClockExpiry::SessionEnd => (None, true),
+        };
+        let updated = self.connection.execute(
+            "UPDATE progress_clock
+            SET ephemeral = 1,
+                expires_at = CASE WHEN ?1 IS NULL THEN NULL ELSE datetime('now', ?1) END,
+                expires_with_session = ?2
+            WHERE namespace = ?3 AND name = ?4 AND deleted_at IS NULL;",
+            rusqlite::params![expires_at, expires_with_session, namespace, name],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the channel a clock's expiry is announced in, or `None` to not announce it.
+    pub fn set_clock_announcement(
+        &self,
+        namespace: &str,
+        name: &str,
+        channel_id: Option<u64>,
+    ) -> Result<(), Error> {
+        let updated = self.connection.execute(
+            "UPDATE progress_clock SET announce_channel_id = ?1
+            WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;",
+            rusqlite::params![channel_id.map(|id| id as i64), namespace, name],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get_clock_access(&self, namespace: &str, name: &str) -> Result<ClockAccess, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT access FROM progress_clock WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+        )?;
+        match statement.query_row(rusqlite::params![namespace, name], |row| {
+            row.get::<_, Option<String>>(0)
+        }) {
+            Ok(access) => Ok(ClockAccess::from_str(access.as_deref().unwrap_or_default())),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(format!("There's no clock named {name}.").into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns who may see and change each clock in `namespace` that not everyone may.
+    pub fn get_clock_accesses(
+        &self,
+        namespace: &str,
+    ) -> Result<HashMap<String, ClockAccess>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, access FROM progress_clock
+            WHERE namespace = ?1 AND deleted_at IS NULL AND access != 'everyone';",
+        )?;
+        let mut accesses = HashMap::new();
+        for access in statement.query_map(rusqlite::params![namespace], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })? {
+            let (name, access) = access?;
+            accesses.insert(name, ClockAccess::from_str(&access));
+        }
+        Ok(accesses)
+    }
+
+    pub fn set_clock_access(
+        &self,
+        namespace: &str,
+        name: &str,
+        access: ClockAccess,
+    ) -> Result<(), Error> {
+        let updated = self.connection.execute(
+            "UPDATE progress_clock SET access = ?1 WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;",
+            rusqlite::params![access.as_str(), namespace, name],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the clocks in `namespace` that can be shown to everyone, leaving out GM-only ones.
+    pub fn get_public_clocks(&self, namespace: &str) -> Result<Vec<ProgressClock>, Error> {
+        let accesses = self.get_clock_accesses(namespace)?;
+        Ok(self
+            .get_available_clocks(namespace, "")?
+            .into_iter()
+            .filter(|clock| accesses.get(&clock.name) != Some(&ClockAccess::GmOnly))
+            .collect())
+    }
+
+    pub fn get_gm_role(&self, guild_id: u64) -> Result<Option<u64>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT role_id FROM gm_role WHERE guild_id = ?1;")?;
+        match statement.query_row(rusqlite::params![guild_id as i64], |row| {
+            row.get::<_, i64>(0)
+        }) {
+            Ok(role_id) => Ok(Some(role_id as u64)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sets the role that marks a server's GMs, or `None` to leave it to those who can manage the server.
+    pub fn set_gm_role(&self, guild_id: u64, role_id: Option<u64>) -> Result<(), Error> {
+        match role_id {
+            Some(role_id) => self.connection.execute(
+                "INSERT OR REPLACE INTO gm_role (guild_id, role_id) VALUES (?1, ?2);",
+                rusqlite::params![guild_id as i64, role_id as i64],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM gm_role WHERE guild_id = ?1;",
+                rusqlite::params![guild_id as i64],
+            )?,
+        };
+        Ok(())
+    }
+
+    pub fn get_clock_labels(&self, namespace: &str, name: &str) -> Result<ClockLabels, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT category, tags FROM progress_clock
+            WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+        )?;
+        match statement.query_row(rusqlite::params![namespace, name], |row| {
+            Ok(ClockLabels::from_columns(row.get(0)?, row.get(1)?))
+        }) {
+            Ok(labels) => Ok(labels),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(format!("There's no clock named {name}.").into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn set_clock_labels(
+        &self,
+        namespace: &str,
+        name: &str,
+        labels: &ClockLabels,
+    ) -> Result<(), Error> {
+        let updated = self.connection.execute(
+            "UPDATE progress_clock SET category = ?1, tags = ?2
+            WHERE namespace = ?3 AND name = ?4 AND deleted_at IS NULL;",
+            rusqlite::params![
+                labels.category.map(|category| category.as_str()),
+                labels.tags_column(),
+                namespace,
+                name
+            ],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns every tag used by a clock in `namespace`, in alphabetical order.
+    /// Tags only used by GM-only clocks are left out unless `gm` is set.
+    pub fn get_tags(&self, namespace: &str, gm: bool) -> Result<Vec<String>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT tags FROM progress_clock
+            WHERE namespace = ?1 AND tags IS NOT NULL AND deleted_at IS NULL
+            AND (?2 OR access IS NOT 'gm');",
+        )?;
+        let mut tags = std::collections::BTreeSet::new();
+        for clock_tags in statement.query_map(rusqlite::params![namespace, gm], |row| {
+            row.get::<_, String>(0)
+        })? {
+            tags.extend(ClockLabels::parse_tags(&clock_tags?));
+        }
+        Ok(tags.into_iter().collect())
+    }
+
+    /// Returns the clocks in `namespace` that match `filter`, with their labels, sorted by `order`.
+    pub fn list_clocks(
+        &self,
+        namespace: &str,
+        filter: &ClockFilter,
+        order: ClockOrder,
+    ) -> Result<Vec<(ProgressClock, ClockLabels)>, Error> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT name, segments, segments_filled, ephemeral, color, category, tags
+            FROM progress_clock
+            WHERE namespace = ?1
+            AND name LIKE ?2
+            AND (?3 IS NULL OR category = ?3)
+            AND (?4 IS NULL OR instr(tags, ?4) > 0)
+            AND deleted_at IS NULL
+            AND (ephemeral = 0 OR expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+            ORDER BY {};",
+            order.order_by()
+        ))?;
+        let tag = filter
+            .tag
+            .as_deref()
+            .map(|tag| format!(",{},", tag.trim().to_lowercase()));
+
+        let mut clocks = Vec::new();
+        for clock in statement.query_map(
+            rusqlite::params![
+                namespace,
+                format!("%{}%", filter.partial),
+                filter.category.map(|category| category.as_str()),
+                tag
+            ],
+            |row| {
+                Ok((
+                    ProgressClock {
+                        namespace: namespace.to_owned(),
+                        name: row.get(0)?,
+                        segments: row.get(1)?,
+                        segments_filled: row.get(2)?,
+                        ephemeral: row.get(3)?,
+                        color: row.get(4)?,
+                    },
+                    ClockLabels::from_columns(row.get(5)?, row.get(6)?),
+                ))
+            },
+        )? {
+            clocks.push(clock?);
+        }
+        Ok(clocks)
+    }
+
+    pub fn get_clock_schedule(&self, namespace: &str, name: &str) -> Result<ClockSchedule, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT tick_every_hours, tick_count, tick_on_session_start,
+                CAST(strftime('%s', next_tick_at) AS INTEGER)
+            FROM progress_clock
+            WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+        )?;
+        match statement.query_row(rusqlite::params![namespace, name], |row| {
+            Ok(ClockSchedule {
+                every_hours: row.get(0)?,
+                count: row.get::<_, Option<u8>>(1)?.unwrap_or(1),
+                on_session_start: row.get::<_, Option<bool>>(2)?.unwrap_or(false),
+                next_tick_at: row.get(3)?,
+            })
+        }) {
+            Ok(schedule) => Ok(schedule),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(format!("There's no clock named {name}.").into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sets when the clock called `name` ticks on its own.
+    /// A new or changed timer first ticks `every_hours` from now. An unchanged one keeps its next tick.
+    pub fn set_clock_schedule(
+        &self,
+        namespace: &str,
+        name: &str,
+        schedule: &ClockSchedule,
+    ) -> Result<(), Error> {
+        let updated = self.connection.execute(
+            "UPDATE progress_clock
+            SET tick_every_hours = ?1, tick_count = ?2, tick_on_session_start = ?3,
+                next_tick_at = CASE
+                    WHEN ?1 IS NULL THEN NULL
+                    WHEN tick_every_hours IS ?1 AND next_tick_at IS NOT NULL THEN next_tick_at
+                    ELSE datetime('now', '+' || ?1 || ' hours')
+                END
+            WHERE namespace = ?4 AND name = ?5 AND deleted_at IS NULL;",
+            rusqlite::params![
+                schedule.every_hours,
+                schedule.count,
+                schedule.on_session_start,
+                namespace,
+                name
+            ],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ticks every clock whose timed tick is due, and returns the clocks afterwards.
+    /// Ticks missed while the bot wasn't running are made up all at once.
+    pub fn run_scheduled_ticks(&self) -> Result<Vec<ProgressClock>, Error> {
+        // full clocks have nothing to fill, but keep to their schedule for when they're emptied.
+        self.connection.execute(
+            "UPDATE progress_clock
+            SET next_tick_at = datetime(
+                next_tick_at,
+                '+' || tick_every_hours * (CAST((julianday('now') - julianday(next_tick_at)) * 24 / tick_every_hours AS INTEGER) + 1) || ' hours'
+            )
+            WHERE tick_every_hours > 0 AND next_tick_at <= CURRENT_TIMESTAMP AND segments_filled >= segments;",
+            [],
+        )?;
+        let mut statement = self.connection.prepare(
+            "SELECT namespace, name, tick_every_hours, tick_count,
+                CAST((julianday('now') - julianday(next_tick_at)) * 24 / tick_every_hours AS INTEGER) + 1
+            FROM progress_clock
+            WHERE tick_every_hours > 0 AND next_tick_at <= CURRENT_TIMESTAMP
+            AND segments_filled < segments AND deleted_at IS NULL
+            AND (ephemeral = 0 OR expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP);",
+        )?;
+        let mut due = Vec::new();
+        for clock in statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, Option<u8>>(3)?.unwrap_or(1),
+                row.get::<_, u32>(4)?,
+            ))
+        })? {
+            due.push(clock?);
+        }
+
+        let mut ticked = Vec::new();
+        for (namespace, name, every_hours, count, missed) in due {
+            let ticks = u8::try_from(u32::from(count).saturating_mul(missed)).unwrap_or(u8::MAX);
+            let next_tick = format!("+{} hours", every_hours.saturating_mul(missed));
+            // the tick and the next tick's time are saved together, so a restart can't tick twice or not at all.
+            self.record_clock_change(&namespace, &name, "scheduled tick", SCHEDULE_ACTOR, || {
+                self.connection.execute(
+                    "UPDATE progress_clock
+                    SET segments_filled = MIN(segments_filled + ?1, segments),
+                        next_tick_at = datetime(next_tick_at, ?2)
+                    WHERE namespace = ?3 AND name = ?4 AND deleted_at IS NULL;",
+                    rusqlite::params![ticks, next_tick, &namespace, &name],
+                )?;
+                Ok(name.clone())
+            })?;
+            ticked.push(self.get_clock(&namespace, &name)?);
+        }
+        Ok(ticked)
+    }
+
+    /// Starts the session in `namespace`, ticking its clocks that tick when a session starts.
+    /// Returns the clocks afterwards.
+    pub fn start_session(&self, namespace: &str, actor: &str) -> Result<Vec<ProgressClock>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, tick_count FROM progress_clock
+            WHERE namespace = ?1 AND tick_on_session_start = 1
+            AND deleted_at IS NULL
+            AND (ephemeral = 0 OR expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+            ORDER BY name;",
+        )?;
+        let mut due = Vec::new();
+        for clock in statement.query_map(rusqlite::params![namespace], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<u8>>(1)?.unwrap_or(1),
+            ))
+        })? {
+            due.push(clock?);
+        }
+
+        // the session starts for every clock or for none of them.
+        self.atomically(|| {
+            let mut ticked = Vec::new();
+            for (name, count) in due {
+                self.bump_clock(namespace, &name, count, actor)?;
+                ticked.push(self.get_clock(namespace, &name)?);
+            }
+            Ok(ticked)
+        })
+    }
+
+    /// Ends the session in `namespace`, so its clocks that last until the session ends expire.
+    /// Returns how many clocks expired.
+    pub fn end_session(&self, namespace: &str) -> Result<usize, Error> {
+        Ok(self.connection.execute(
+            "UPDATE progress_clock SET expires_at = CURRENT_TIMESTAMP
+            WHERE namespace = ?1 AND ephemeral = 1 AND expires_with_session = 1 AND deleted_at IS NULL;",
+            rusqlite::params![namespace],
+        )?)
+    }
+
+    /// Permanently deletes clocks removed longer than the grace period ago, along with their history.
+    pub fn purge_removed_clocks(&self) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "DELETE FROM progress_clock_event
+            WHERE EXISTS (
+                SELECT 1 FROM progress_clock AS clock
+                WHERE clock.namespace = progress_clock_event.namespace
+                AND clock.name IN (progress_clock_event.before_name, progress_clock_event.after_name)
+                AND julianday('now') - julianday(clock.deleted_at) >= ?1
+            );",
+            rusqlite::params![REMOVED_CLOCK_GRACE_DAYS],
+        )?;
+        transaction.execute(
+            "DELETE FROM progress_clock WHERE julianday('now') - julianday(deleted_at) >= ?1;",
+            rusqlite::params![REMOVED_CLOCK_GRACE_DAYS],
+        )?;
+        self.forget_orphaned_links()?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Overwrites the clock called `name` with `progress_clock`, which may have been renamed.
+    /// Turning a clock ephemeral starts its one day countdown, and turning it back clears its lifetime.
+    pub fn update_clock<'a>(
+        &self,
+        namespace: &'a String,
+        name: &'a String,
+        progress_clock: &ProgressClock,
+        actor: &str,
+    ) -> Result<(), Error> {
+        // both sides of a tug of war share one clock, so they keep the same number of segments.
+        if let Some(link) = self
+            .get_clock_links(namespace, name)?
+            .into_iter()
+            .find(|link| link.kind == ClockLinkKind::TugOfWar)
+            && let Ok(other) = self.get_clock(namespace, link.other(name))
+            && other.segments != progress_clock.segments
+        {
+            return Err(format!(
+                "{name} is in a tug of war with {}, so it keeps {} segments.",
+                other.name, other.segments
+            )
+            .into());
+        }
+        self.forget_removed_clock(namespace, &progress_clock.name)?;
+        self.record_clock_change(namespace, name, "edit", actor, || {
+            let mut statement = self.connection.prepare(
+                "UPDATE progress_clock
+                    SET name = ?1,
+                        segments = ?2,
+                        segments_filled = ?3,
+                        expires_at = CASE WHEN ?4 = 0 THEN NULL WHEN ephemeral = 0 THEN datetime('now', ?8) ELSE expires_at END,
+                        expires_with_session = ?4 AND expires_with_session,
+                        ephemeral = ?4,
+                        color = ?5
+                    WHERE namespace = ?6 AND name = ?7 AND deleted_at IS NULL;
+                ",
+            )?;
+
+            let updated = statement.execute(rusqlite::params![
+                &progress_clock.name,
+                &progress_clock.segments,
+                &progress_clock.segments_filled,
+                &progress_clock.ephemeral,
+                &progress_clock.color,
+                namespace,
+                name,
+                DEFAULT_CLOCK_LIFETIME
+            ]);
+
+            match updated {
+                Ok(0) => Err(format!("There's no clock named {name}.").into()),
+                Ok(_) => Ok(progress_clock.name.clone()),
+                Err(rusqlite::Error::SqliteFailure(error, _))
+                    if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    Err(format!("There's already a clock named {}.", progress_clock.name).into())
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    /// Sets how many segments of a clock are filled, clamped to its segment count.
+    fn fill_clock(
+        &self,
+        namespace: &str,
+        name: &str,
+        segments_filled: u8,
+    ) -> Result<String, Error> {
+        let mut statement = self.connection.prepare(
+            "UPDATE progress_clock
+                SET segments_filled = MIN(?1, segments)
+                WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;
+            ",
+        )?;
+        match statement.execute(rusqlite::params![segments_filled, namespace, name])? {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(name.to_owned()),
+        }
+    }
+
+    /// Sets how many segments of a clock are filled, clamped to its segment count.
+    pub fn set_clock<'a>(
+        &self,
+        namespace: &'a str,
+        name: &'a str,
+        segments_filled: u8,
+        actor: &str,
+    ) -> Result<(), Error> {
+        self.record_clock_change(namespace, name, "set", actor, || {
+            self.fill_clock(namespace, name, segments_filled)
+        })
+    }
+
+    /// Empties `count` segments of a clock, stopping at zero.
+    pub fn tick_down_clock<'a>(
+        &self,
+        namespace: &'a String,
+        name: &'a String,
+        count: u8,
+        actor: &str,
+    ) -> Result<(), Error> {
+        self.record_clock_change(namespace, name, "tick down", actor, || {
+            let mut statement = self.connection.prepare(
+                "UPDATE progress_clock
+                    SET segments_filled = MAX(segments_filled - ?1, 0)
+                    WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;
+                ",
+            )?;
+            match statement.execute(rusqlite::params![count, namespace, name])? {
+                0 => Err(format!("There's no clock named {name}.").into()),
+                _ => Ok(name.clone()),
+            }
+        })
+    }
+
+    /// Empties every segment of a clock.
+    pub fn reset_clock<'a>(
+        &self,
+        namespace: &'a str,
+        name: &'a str,
+        actor: &str,
+    ) -> Result<(), Error> {
+        self.record_clock_change(namespace, name, "reset", actor, || {
+            self.fill_clock(namespace, name, 0)
+        })
+    }
+
+    /// Returns the most recent changes to the clock called `name`, newest first.
+    pub fn get_clock_history<'a>(
+        &self,
+        namespace: &'a String,
+        name: &'a String,
+    ) -> Result<Vec<ClockEvent>, Error> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {CLOCK_EVENT_COLUMNS}
+            FROM progress_clock_event
+            WHERE namespace = ?1 AND ?2 IN (before_name, after_name)
+            ORDER BY id DESC
+            LIMIT ?3;"
+        ))?;
+
+        let mut events = Vec::new();
+        for event in statement.query_map(
+            rusqlite::params![namespace, name, MAX_CLOCK_HISTORY as i64],
+            |row| ClockEvent::from_row(namespace, row),
+        )? {
+            events.push(event?);
+        }
+
+        Ok(events)
+    }
+
+    /// Puts the clock that `event` changed back into `state`. A `state` of `None` removes the clock.
+    fn restore_clock(
+        &self,
+        namespace: &String,
+        current_name: &String,
+        state: Option<&ProgressClock>,
+    ) -> Result<(), Error> {
+        let before = self.get_clock(namespace, current_name).ok();
+        let restored = match state {
+            Some(progress_clock) => {
+                if progress_clock.name != *current_name {
+                    self.forget_removed_clock(namespace, &progress_clock.name)?;
+                }
+                self.connection.execute(
+                    "UPDATE progress_clock
+                    SET name = ?1, segments = ?2, segments_filled = ?3, color = ?5, deleted_at = NULL,
+                        expires_at = CASE WHEN ?4 = 0 THEN NULL WHEN ephemeral = 0 THEN datetime('now', ?8) ELSE expires_at END,
+                        expires_with_session = ?4 AND expires_with_session,
+                        ephemeral = ?4
+                    WHERE namespace = ?6 AND name = ?7;",
+                    rusqlite::params![
+                        &progress_clock.name,
+                        &progress_clock.segments,
+                        &progress_clock.segments_filled,
+                        &progress_clock.ephemeral,
+                        &progress_clock.color,
+                        namespace,
+                        current_name,
+                        DEFAULT_CLOCK_LIFETIME
+                    ],
+                )?
+            }
+            None => self.connection.execute(
+                "UPDATE progress_clock SET deleted_at = CURRENT_TIMESTAMP
+                WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+                rusqlite::params![namespace, current_name],
+            )?,
+        };
+
+        if let Some(progress_clock) = state
+            && restored > 0
+            && progress_clock.name != *current_name
+        {
+            self.rename_clock_references(namespace, current_name, &progress_clock.name)?;
+        }
+        if restored > 0 {
+            self.queue_clock_completion(namespace, before.as_ref(), state)?;
+        }
+
+        match restored {
+            0 => Err(format!("{current_name} can't be changed back any more.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reverts the latest change to a clock in `namespace` that hasn't been undone, and returns it.
+    /// Changes to clocks only GMs may change are skipped, unless `gm` is set, as is history brought in by an import.
+    pub fn undo_clock_change(&self, namespace: &String, gm: bool) -> Result<ClockEvent, Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let event = match self.connection.query_row(
+            &format!(
+                "SELECT {CLOCK_EVENT_COLUMNS}
+                FROM progress_clock_event
+                WHERE namespace = ?1 AND undone = 0 AND imported = 0 AND {RESTRICTED_CLOCK_EVENTS}
+                ORDER BY id DESC
+                LIMIT 1;"
+            ),
+            rusqlite::params![namespace, gm],
+            |row| ClockEvent::from_row(namespace, row),
+        ) {
+            Ok(event) => event,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err("There's nothing to undo.".into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        self.restore_clock(namespace, event.name(), event.before.as_ref())?;
+        transaction.execute(
+            "UPDATE progress_clock_event SET undone = 1 WHERE id = ?1;",
+            rusqlite::params![event.id],
+        )?;
+
+        transaction.commit()?;
+        Ok(event)
+    }
+
+    /// Makes the earliest undone change in `namespace` again, and returns it.
+    /// Changes to clocks only GMs may change are skipped, unless `gm` is set.
+    pub fn redo_clock_change(&self, namespace: &String, gm: bool) -> Result<ClockEvent, Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let event = match self.connection.query_row(
+            &format!(
+                "SELECT {CLOCK_EVENT_COLUMNS}
+                FROM progress_clock_event
+                WHERE namespace = ?1 AND undone = 1 AND imported = 0 AND {RESTRICTED_CLOCK_EVENTS}
+                ORDER BY id ASC
+                LIMIT 1;"
+            ),
+            rusqlite::params![namespace, gm],
+            |row| ClockEvent::from_row(namespace, row),
+        ) {
+            Ok(event) => event,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err("There's nothing to redo.".into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let current_name = event
+            .before
+            .as_ref()
+            .map(|progress_clock| &progress_clock.name)
+            .unwrap_or(event.name());
+        self.restore_clock(namespace, current_name, event.after.as_ref())?;
+        transaction.execute(
+            "UPDATE progress_clock_event SET undone = 0 WHERE id = ?1;",
+            rusqlite::params![event.id],
+        )?;
+
+        transaction.commit()?;
+        Ok(event)
+    }
+
+    /// Turns inline rolls on or off for a channel.
+    pub fn set_inline_rolls(&self, channel_id: u64, enabled: bool) -> Result<(), Error> {
+        let query = if enabled {
+            "INSERT OR IGNORE INTO inline_roll_channel (channel_id) VALUES (?1);"
+        } else {
+            "DELETE FROM inline_roll_channel WHERE channel_id = ?1;"
+        };
+        let mut statement = self.connection.prepare(query)?;
+        statement.execute(rusqlite::params![channel_id as i64])?;
+        Ok(())
+    }
+
+    pub fn inline_rolls_enabled(&self, channel_id: u64) -> Result<bool, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT 1 FROM inline_roll_channel WHERE channel_id = ?1;")?;
+        statement
+            .exists(rusqlite::params![channel_id as i64])
+            .map_err(|e| e.into())
+    }
+
+    /// Sets the scope of a channel's namespace, or shares the whole namespace again when `scope` is `None`.
+    pub fn set_namespace_scope(&self, channel_id: u64, scope: Option<&str>) -> Result<(), Error> {
+        match scope {
+            Some(scope) => self.connection.execute(
+                "INSERT OR REPLACE INTO namespace_scope (channel_id, scope) VALUES (?1, ?2);",
+                rusqlite::params![channel_id as i64, scope],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM namespace_scope WHERE channel_id = ?1;",
+                rusqlite::params![channel_id as i64],
+            )?,
+        };
+        Ok(())
+    }
+
+    pub fn get_namespace_scope(&self, channel_id: u64) -> Result<Option<String>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT scope FROM namespace_scope WHERE channel_id = ?1;")?;
+        match statement.query_row(rusqlite::params![channel_id as i64], |row| row.get(0)) {
+            Ok(scope) => Ok(Some(scope)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_pinned_message(&self, pinned_message: &PinnedMessage) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO pinned_message (message_id, channel_id, namespace, clock_name)
+            VALUES (?1, ?2, ?3, ?4);",
+            rusqlite::params![
+                pinned_message.message_id as i64,
+                pinned_message.channel_id as i64,
+                &pinned_message.namespace,
+                &pinned_message.clock_name
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the messages pinned in `namespace` that show the clock called `name`, or a board.
+    /// A `name` of `None` returns every message pinned in `namespace`.
+    pub fn get_pinned_messages(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Result<Vec<PinnedMessage>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT message_id, channel_id, clock_name FROM pinned_message
+            WHERE namespace = ?1 AND (?2 IS NULL OR clock_name IS NULL OR clock_name = ?2);",
+        )?;
+        let mut pinned_messages = Vec::new();
+        for pinned_message in statement.query_map(rusqlite::params![namespace, name], |row| {
+            Ok(PinnedMessage {
+                message_id: row.get::<_, i64>(0)? as u64,
+                channel_id: row.get::<_, i64>(1)? as u64,
+                namespace: namespace.to_owned(),
+                clock_name: row.get(2)?,
+            })
+        })? {
+            pinned_messages.push(pinned_message?);
+        }
+        Ok(pinned_messages)
+    }
+
+    pub fn remove_pinned_message(&self, message_id: u64) -> Result<(), Error> {
+        self.connection.execute(
+            "DELETE FROM pinned_message WHERE message_id = ?1;",
+            rusqlite::params![message_id as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Points the messages pinned for the clock called `name`, the clocks that bump it and its links at its new name.
+    fn rename_clock_references(
+        &self,
+        namespace: &str,
+        name: &str,
+        new_name: &str,
+    ) -> Result<(), Error> {
+        self.connection.execute(
+            "UPDATE pinned_message SET clock_name = ?1 WHERE namespace = ?2 AND clock_name = ?3;",
+            rusqlite::params![new_name, namespace, name],
+        )?;
+        self.connection.execute(
+            "UPDATE progress_clock SET complete_clock = ?1 WHERE namespace = ?2 AND complete_clock = ?3;",
+            rusqlite::params![new_name, namespace, name],
+        )?;
+        for column in ["clock_name", "other_name"] {
+            self.connection.execute(
+                &format!(
+                    "UPDATE clock_link SET {column} = ?1 WHERE namespace = ?2 AND {column} = ?3;"
+                ),
+                rusqlite::params![new_name, namespace, name],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_clock_trigger(&self, namespace: &str, name: &str) -> Result<ClockTrigger, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT complete_channel_id, complete_message, complete_role_id, complete_clock, complete_count, complete_sound
+            FROM progress_clock
+            WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+        )?;
+        match statement.query_row(rusqlite::params![namespace, name], |row| {
+            Ok(ClockTrigger {
+                channel_id: row.get::<_, Option<i64>>(0)?.map(|id| id as u64),
+                message: row.get(1)?,
+                role_id: row.get::<_, Option<i64>>(2)?.map(|id| id as u64),
+                linked_clock: row.get(3)?,
+                linked_count: row.get::<_, Option<u8>>(4)?.unwrap_or(1),
+                sound: row.get(5)?,
+            })
+        }) {
+            Ok(trigger) => Ok(trigger),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(format!("There's no clock named {name}.").into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sets what the clock called `name` does when it fills up. An empty trigger does nothing.
+    pub fn set_clock_trigger(
+        &self,
+        namespace: &str,
+        name: &str,
+        trigger: &ClockTrigger,
+    ) -> Result<(), Error> {
+        let updated = self.connection.execute(
+            "UPDATE progress_clock
+            SET complete_channel_id = ?1, complete_message = ?2, complete_role_id = ?3,
+                complete_clock = ?4, complete_count = ?5, complete_sound = ?6
+            WHERE namespace = ?7 AND name = ?8 AND deleted_at IS NULL;",
+            rusqlite::params![
+                trigger.channel_id.map(|id| id as i64),
+                &trigger.message,
+                trigger.role_id.map(|id| id as i64),
+                &trigger.linked_clock,
+                trigger.linked_count,
+                &trigger.sound,
+                namespace,
+                name
+            ],
+        )?;
+        match updated {
+            0 => Err(format!("There's no clock named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Queues the clock's trigger if a change from `before` to `after` filled it up.
+    fn queue_clock_completion(
+        &self,
+        namespace: &str,
+        before: Option<&ProgressClock>,
+        after: Option<&ProgressClock>,
+    ) -> Result<(), Error> {
+        let Some(after) = after else {
+            return Ok(());
+        };
+        let was_full = before.is_some_and(|clock| clock.segments_filled >= clock.segments);
+        if was_full || after.segments_filled < after.segments {
+            return Ok(());
+        }
+
+        let trigger = self.get_clock_trigger(namespace, &after.name)?;
+        let linked = self
+            .get_clock_links(namespace, &after.name)?
+            .iter()
+            .any(|link| match link.kind {
+                ClockLinkKind::Race => true,
+                ClockLinkKind::Child => link.clock_name == after.name,
+                ClockLinkKind::TugOfWar => false,
+            });
+        if trigger.is_empty() && !linked {
+            return Ok(());
+        }
+        self.connection.execute(
+            "INSERT INTO clock_completion
+            (namespace, name, channel_id, message, role_id, linked_clock, linked_count, sound)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);",
+            rusqlite::params![
+                namespace,
+                &after.name,
+                trigger.channel_id.map(|id| id as i64),
+                &trigger.message,
+                trigger.role_id.map(|id| id as i64),
+                &trigger.linked_clock,
+                trigger.linked_count,
+                &trigger.sound
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Takes the queued completions of clocks in `namespace`, bumping their linked clocks on the way.
+    /// Linked clocks that fill up because of it are taken too.
+    /// Without a `namespace`, only completions with a channel to be announced in are taken, from every namespace.
+    /// The rest wait for someone to change a clock in their namespace.
+    pub fn take_clock_completions(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ClockCompletion>, Error> {
+        let taken = "(?1 IS NULL AND channel_id IS NOT NULL) OR namespace = ?1";
+        let mut completions = Vec::new();
+        for _ in 0..MAX_COMPLETION_ROUNDS {
+            let transaction = self.connection.unchecked_transaction()?;
+            let mut statement = self.connection.prepare(&format!(
+                "SELECT namespace, name, channel_id, message, role_id, linked_clock, linked_count, sound
+                FROM clock_completion
+                WHERE {taken}
+                ORDER BY id;"
+            ))?;
+            let mut round = Vec::new();
+            for completion in statement.query_map(rusqlite::params![namespace], |row| {
+                Ok(ClockCompletion {
+                    namespace: row.get(0)?,
+                    name: row.get(1)?,
+                    trigger: ClockTrigger {
+                        channel_id: row.get::<_, Option<i64>>(2)?.map(|id| id as u64),
+                        message: row.get(3)?,
+                        role_id: row.get::<_, Option<i64>>(4)?.map(|id| id as u64),
+                        linked_clock: row.get(5)?,
+                        linked_count: row.get::<_, Option<u8>>(6)?.unwrap_or(1),
+                        sound: row.get(7)?,
+                    },
+                    linked: None,
+                    parent: None,
+                    rival: None,
+                })
+            })? {
+                round.push(completion?);
+            }
+            transaction.execute(
+                &format!("DELETE FROM clock_completion WHERE {taken};"),
+                rusqlite::params![namespace],
+            )?;
+            transaction.commit()?;
+
+            if round.is_empty() {
+                break;
+            }
+            for completion in &mut round {
+                let namespace = &completion.namespace;
+                // the linked clock may have been removed since the trigger was set.
+                if let Some(linked_clock) = &completion.trigger.linked_clock
+                    && self
+                        .bump_clock(
+                            namespace,
+                            linked_clock,
+                            completion.trigger.linked_count,
+                            &completion.name,
+                        )
+                        .is_ok()
+                {
+                    completion.linked = self.get_clock(namespace, linked_clock).ok();
+                }
+
+                for link in self.get_clock_links(namespace, &completion.name)? {
+                    let other = link.other(&completion.name);
+                    match link.kind {
+                        ClockLinkKind::Child
+                            if link.clock_name == completion.name
+                                && self
+                                    .bump_clock(namespace, other, 1, &completion.name)
+                                    .is_ok() =>
+                        {
+                            completion.parent = self.get_clock(namespace, other).ok();
+                        }
+                        ClockLinkKind::Race => {
+                            completion.rival = self.get_clock(namespace, other).ok();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            completions.append(&mut round);
+        }
+        Ok(completions)
+    }
+
+    /// Whether a guild or user that has been around since `owner_since`, a unix timestamp, may take what's
+    /// stored under `legacy_name`. Only names that were still waiting to be moved when IDs took over are taken,
+    /// and only by guilds and users that existed by then.
+    pub fn is_legacy_namespace(&self, legacy_name: &str, owner_since: i64) -> Result<bool, Error> {
+        match self.connection.query_row(
+            "SELECT 1 FROM legacy_namespace
+            WHERE name = ?1 AND CAST(strftime('%s', recorded_at) AS INTEGER) >= ?2;",
+            rusqlite::params![legacy_name, owner_since],
+            |_| Ok(()),
+        ) {
+            Ok(()) => Ok(true),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Moves everything stored under a guild or user's name, as namespaces used to be, to its ID based namespace.
+    /// Each name is only ever moved once, by the first guild or user that `is_legacy_namespace` lets take it.
+    /// Rows that clash with one already in `namespace` are left where they are.
+    /// Returns how many clocks, macros and tables were moved.
+    pub fn migrate_legacy_namespace<'a>(
+        &self,
+        legacy_name: &'a String,
+        namespace: &'a String,
+        owner_since: i64,
+    ) -> Result<usize, Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        if !self.is_legacy_namespace(legacy_name, owner_since)? {
+            return Ok(0);
+        }
+        let params = rusqlite::params![legacy_name, namespace];
+
+        let mut moved = transaction.execute(
+            "UPDATE OR IGNORE progress_clock SET namespace = ?2 WHERE namespace = ?1;",
+            params,
+        )?;
+        transaction.execute(
+            "UPDATE progress_clock_event SET namespace = ?2
+            WHERE namespace = ?1
+            AND NOT EXISTS (
+                SELECT 1 FROM progress_clock
+                WHERE namespace = ?1 AND name IN (before_name, after_name)
+            );",
+            params,
+        )?;
+        moved += transaction.execute(
+            "UPDATE OR IGNORE dice_macro SET namespace = ?2 WHERE namespace = ?1;",
+            params,
+        )?;
+        moved += transaction.execute(
+            "UPDATE OR IGNORE random_table SET namespace = ?2 WHERE namespace = ?1;",
+            params,
+        )?;
+        transaction.execute(
+            "UPDATE random_table_entry SET namespace = ?2
+            WHERE namespace = ?1
+            AND NOT EXISTS (
+                SELECT 1 FROM random_table WHERE namespace = ?1 AND name = table_name
+            );",
+            params,
+        )?;
+        transaction.execute(
+            "DELETE FROM legacy_namespace WHERE name = ?1;",
+            rusqlite::params![legacy_name],
+        )?;
+
+        transaction.commit()?;
+        Ok(moved)
+    }
+
+    pub fn save_roll_message(&self, roll_message: &RollMessage) -> Result<usize, Error> {
+        let mut statement = self.connection.prepare(
+            "INSERT OR REPLACE INTO roll_message (message_id, user_id, dice_string)
+            VALUES (?1, ?2, ?3);",
+        )?;
+
+        statement
+            .execute(rusqlite::params![
+                roll_message.message_id as i64,
+                roll_message.user_id as i64,
+                &roll_message.dice_string,
+            ])
+            .map_err(|e| e.into())
+    }
+
+    pub fn get_roll_message(&self, message_id: u64) -> Result<RollMessage, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT user_id, dice_string FROM roll_message WHERE message_id = ?1;")?;
+
+        let roll_message = statement.query_row(rusqlite::params![message_id as i64], |row| {
+            Ok(RollMessage {
+                message_id,
+                user_id: row.get::<_, i64>(0)? as u64,
+                dice_string: row.get(1)?,
+            })
+        })?;
+
+        Ok(roll_message)
+    }
+
+    /// Forgets the dice strings of `/roll` replies older than `ROLL_MESSAGE_RETENTION`.
+    /// Returns how many were forgotten.
+    pub fn purge_roll_messages(&self) -> Result<usize, Error> {
+        Ok(self.connection.execute(
+            "DELETE FROM roll_message WHERE creation_time <= datetime('now', ?1);",
+            rusqlite::params![ROLL_MESSAGE_RETENTION],
+        )?)
+    }
+
+    /// Saves a macro, replacing any macro of the same name in the namespace.
+    pub fn save_macro(&self, dice_macro: &DiceMacro) -> Result<usize, Error> {
+        let mut statement = self.connection.prepare(
+            "INSERT OR REPLACE INTO dice_macro (namespace, name, dice_string)
+            VALUES (?1, ?2, ?3);",
+        )?;
+
+        statement
+            .execute(rusqlite::params![
+                &dice_macro.namespace,
+                &dice_macro.name,
+                &dice_macro.dice_string,
+            ])
+            .map_err(|e| e.into())
+    }
+
+    pub fn get_macro<'a>(
+        &self,
+        namespace: &'a String,
+        name: &'a String,
+    ) -> Result<DiceMacro, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, dice_string FROM dice_macro WHERE namespace = ?1 AND name = ?2;",
+        )?;
+
+        let dice_macro = statement.query_row(rusqlite::params![namespace, name], |row| {
+            Ok(DiceMacro {
+                namespace: namespace.clone(),
+                name: row.get(0)?,
+                dice_string: row.get(1)?,
+            })
+        })?;
+
+        Ok(dice_macro)
+    }
 
-/// an SQLite db handle with the following schema:
-/// progress_clock(namespace TEXT, name TEXT, segments INTEGER, segments_filled INTEGER, creation_time DATETIME, ephemeral BOOL)
-pub struct DB {
-    connection: Connection,
-}
+    /// Given a namespace (user or guild), returns all macros whose name contains `partial`.
+    pub fn get_available_macros<'a>(
+        &self,
+        namespace: &'a String,
+        partial: &'a str,
+    ) -> Result<Vec<DiceMacro>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, dice_string FROM dice_macro
+            WHERE namespace = ?1 AND name LIKE ?2
+            ORDER BY name;",
+        )?;
 
-#[derive(Debug)]
-pub struct ProgressClock {
-    pub namespace: String,
-    pub name: String,
-    pub segments: u8,
-    pub segments_filled: u8,
-    pub ephemeral: bool,
-    pub color: Option<String>,
-}
+        let macro_iter = statement.query_map(
+            rusqlite::params![namespace, format!("%{partial}%")],
+            |row| {
+                Ok(DiceMacro {
+                    namespace: namespace.clone(),
+                    name: row.get(0)?,
+                    dice_string: row.get(1)?,
+                })
+            },
+        )?;
 
-impl ORM for ProgressClock {
-    fn schema() -> &'static str {
-        return "
-        CREATE TABLE IF NOT EXISTS progress_clock(
-            namespace TEXT,
-            name TEXT,
-            segments INTEGER,
-            segments_filled INTEGER,
-            creation_time DATETIME DEFAULT CURRENT_TIMESTAMP,
-            ephemeral BOOL,
-            color TEXT,
-            PRIMARY KEY(namespace, name)
+        let mut macros = Vec::new();
+        for item in macro_iter {
+            macros.push(item?);
+        }
+
+        Ok(macros)
+    }
+
+    pub fn get_initiative(&self, channel_id: u64) -> Result<InitiativeTracker, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT round, turn, message_id FROM initiative_tracker WHERE channel_id = ?1;",
+        )?;
+        let mut tracker = statement.query_row(rusqlite::params![channel_id as i64], |row| {
+            Ok(InitiativeTracker {
+                channel_id,
+                round: row.get(0)?,
+                turn: row.get::<_, i64>(1)? as usize,
+                message_id: row.get::<_, Option<i64>>(2)?.map(|id| id as u64),
+                combatants: vec![],
+            })
+        })?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT name, initiative, tiebreaker, npc, delayed
+            FROM initiative_combatant
+            WHERE channel_id = ?1
+            ORDER BY position;",
+        )?;
+        let combatant_iter = statement.query_map(rusqlite::params![channel_id as i64], |row| {
+            Ok(Combatant {
+                name: row.get(0)?,
+                initiative: row.get(1)?,
+                tiebreaker: row.get(2)?,
+                npc: row.get(3)?,
+                delayed: row.get(4)?,
+            })
+        })?;
+
+        for item in combatant_iter {
+            tracker.combatants.push(item?);
+        }
+
+        Ok(tracker)
+    }
+
+    /// Saves a tracker along with its turn order, replacing whatever was stored for the channel.
+    pub fn save_initiative(&self, tracker: &InitiativeTracker) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO initiative_tracker (channel_id, round, turn, message_id)
+            VALUES (?1, ?2, ?3, ?4);",
+            rusqlite::params![
+                tracker.channel_id as i64,
+                tracker.round,
+                tracker.turn as i64,
+                tracker.message_id.map(|id| id as i64),
+            ],
+        )?;
+        transaction.execute(
+            "DELETE FROM initiative_combatant WHERE channel_id = ?1;",
+            rusqlite::params![tracker.channel_id as i64],
+        )?;
+
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO initiative_combatant
+                (channel_id, name, position, initiative, tiebreaker, npc, delayed)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+            )?;
+            for (position, combatant) in tracker.combatants.iter().enumerate() {
+                statement.execute(rusqlite::params![
+                    tracker.channel_id as i64,
+                    &combatant.name,
+                    position as i64,
+                    combatant.initiative,
+                    combatant.tiebreaker,
+                    combatant.npc,
+                    combatant.delayed,
+                ])?;
+            }
+        }
+
+        transaction.commit().map_err(|e| e.into())
+    }
+
+    /// Records the message showing the tracker in a channel, leaving the rest of the tracker as it is.
+    pub fn set_initiative_message(&self, channel_id: u64, message_id: u64) -> Result<(), Error> {
+        self.connection.execute(
+            "UPDATE initiative_tracker SET message_id = ?1 WHERE channel_id = ?2;",
+            rusqlite::params![message_id as i64, channel_id as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_initiative(&self, channel_id: u64) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "DELETE FROM initiative_combatant WHERE channel_id = ?1;",
+            rusqlite::params![channel_id as i64],
+        )?;
+        transaction.execute(
+            "DELETE FROM initiative_tracker WHERE channel_id = ?1;",
+            rusqlite::params![channel_id as i64],
+        )?;
+        transaction.commit().map_err(|e| e.into())
+    }
+
+    /// Saves a clock template, replacing any template of the same name in the namespace.
+    pub fn save_clock_template(&self, template: &ClockTemplate) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO clock_template
+            (namespace, name, segments, color, ephemeral, category, tags)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+            rusqlite::params![
+                &template.namespace,
+                &template.name,
+                template.segments,
+                &template.color,
+                template.ephemeral,
+                template.labels.category.map(|category| category.as_str()),
+                template.labels.tags_column(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the templates in `namespace` whose names contain `partial`, in alphabetical order.
+    pub fn get_clock_templates(
+        &self,
+        namespace: &str,
+        partial: &str,
+    ) -> Result<Vec<ClockTemplate>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, segments, color, ephemeral, category, tags FROM clock_template
+            WHERE namespace = ?1 AND name LIKE ?2
+            ORDER BY name COLLATE NOCASE;",
+        )?;
+        let mut templates = Vec::new();
+        for template in statement.query_map(
+            rusqlite::params![namespace, format!("%{partial}%")],
+            |row| {
+                Ok(ClockTemplate {
+                    namespace: namespace.to_owned(),
+                    name: row.get(0)?,
+                    segments: row.get(1)?,
+                    color: row.get(2)?,
+                    ephemeral: row.get(3)?,
+                    labels: ClockLabels::from_columns(row.get(4)?, row.get(5)?),
+                })
+            },
+        )? {
+            templates.push(template?);
+        }
+        Ok(templates)
+    }
+
+    /// Returns the template called `name` in `namespace`, ignoring case, if there is one.
+    pub fn get_clock_template(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<ClockTemplate>, Error> {
+        Ok(self
+            .get_clock_templates(namespace, "")?
+            .into_iter()
+            .find(|template| template.name.eq_ignore_ascii_case(name)))
+    }
+
+    pub fn remove_clock_template(&self, namespace: &str, name: &str) -> Result<(), Error> {
+        let removed = self.connection.execute(
+            "DELETE FROM clock_template WHERE namespace = ?1 AND name = ?2;",
+            rusqlite::params![namespace, name],
+        )?;
+        match removed {
+            0 => Err(format!("There's no template named {name}.").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the theme `namespace` draws its clocks with, or the default theme if it hasn't picked one.
+    pub fn get_clock_theme(&self, namespace: &str) -> Result<ClockTheme, Error> {
+        let theme = self.connection.query_row(
+            "SELECT palette, spoke_color, background, style, size, shade_opacity
+            FROM clock_theme WHERE namespace = ?1;",
+            rusqlite::params![namespace],
+            |row| {
+                let palette: String = row.get(0)?;
+                let style: String = row.get(3)?;
+                Ok(ClockTheme {
+                    palette: palette.split(',').map(str::to_owned).collect(),
+                    spoke_color: row.get(1)?,
+                    background: row.get(2)?,
+                    style: ClockStyle::from_str(&style).unwrap_or(ClockStyle::Pie),
+                    size: row.get(4)?,
+                    shade_opacity: row.get(5)?,
+                })
+            },
         );
-        CREATE INDEX IF NOT EXISTS progress_clock_ns ON progress_clock(namespace);
-        CREATE INDEX IF NOT EXISTS progress_clock_name ON progress_clock(name);
-        ";
+        match theme {
+            Ok(theme) => Ok(theme),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(ClockTheme::default()),
+            Err(e) => Err(e.into()),
+        }
     }
-}
 
-impl DB {
-    pub fn new() -> Result<Self, Error> {
-        let connection = Connection::open("./troller.sqlite")?;
-        connection.execute_batch(ProgressClock::schema())?;
+    /// Sets the theme `namespace` draws its clocks with. `None` goes back to the default theme.
+    pub fn set_clock_theme(
+        &self,
+        namespace: &str,
+        theme: Option<&ClockTheme>,
+    ) -> Result<(), Error> {
+        match theme {
+            Some(theme) => self.connection.execute(
+                "INSERT OR REPLACE INTO clock_theme
+                (namespace, palette, spoke_color, background, style, size, shade_opacity)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+                rusqlite::params![
+                    namespace,
+                    theme.palette.join(","),
+                    &theme.spoke_color,
+                    &theme.background,
+                    theme.style.as_str(),
+                    theme.size,
+                    theme.shade_opacity
+                ],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM clock_theme WHERE namespace = ?1;",
+                rusqlite::params![namespace],
+            )?,
+        };
+        Ok(())
+    }
 
-        let db = DB { connection };
+    /// Saves a random table, replacing any table of the same name in the namespace.
+    pub fn save_table(&self, table: &RandomTable) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO random_table (namespace, name, die) VALUES (?1, ?2, ?3);",
+            rusqlite::params![&table.namespace, &table.name, &table.die],
+        )?;
+        transaction.execute(
+            "DELETE FROM random_table_entry WHERE namespace = ?1 AND table_name = ?2;",
+            rusqlite::params![&table.namespace, &table.name],
+        )?;
 
-        Ok(db)
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO random_table_entry (namespace, table_name, low, high, text)
+                VALUES (?1, ?2, ?3, ?4, ?5);",
+            )?;
+            for entry in table.entries.iter() {
+                statement.execute(rusqlite::params![
+                    &table.namespace,
+                    &table.name,
+                    entry.low,
+                    entry.high,
+                    &entry.text,
+                ])?;
+            }
+        }
+
+        transaction.commit().map_err(|e| e.into())
     }
 
-    pub fn get_clock<'a>(
+    pub fn get_table<'a>(
         &self,
         namespace: &'a String,
         name: &'a String,
-    ) -> Result<ProgressClock, Error> {
+    ) -> Result<RandomTable, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, die FROM random_table WHERE namespace = ?1 AND name = ?2;")?;
+        let mut table = statement.query_row(rusqlite::params![namespace, name], |row| {
+            Ok(RandomTable {
+                namespace: namespace.clone(),
+                name: row.get(0)?,
+                die: row.get(1)?,
+                entries: vec![],
+            })
+        })?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT low, high, text FROM random_table_entry
+            WHERE namespace = ?1 AND table_name = ?2
+            ORDER BY low;",
+        )?;
+        let entry_iter = statement.query_map(rusqlite::params![namespace, name], |row| {
+            Ok(TableEntry {
+                low: row.get(0)?,
+                high: row.get(1)?,
+                text: row.get(2)?,
+            })
+        })?;
+
+        for item in entry_iter {
+            table.entries.push(item?);
+        }
+
+        Ok(table)
+    }
+
+    /// Given a namespace (user or guild), returns the names of all tables containing `partial`.
+    pub fn get_available_tables<'a>(
+        &self,
+        namespace: &'a String,
+        partial: &'a str,
+    ) -> Result<Vec<String>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name FROM random_table WHERE namespace = ?1 AND name LIKE ?2 ORDER BY name;",
+        )?;
+        let name_iter = statement.query_map(
+            rusqlite::params![namespace, format!("%{partial}%")],
+            |row| row.get(0),
+        )?;
+
+        let mut names = Vec::new();
+        for item in name_iter {
+            names.push(item?);
+        }
+
+        Ok(names)
+    }
+
+    pub fn remove_table<'a>(&self, namespace: &'a String, name: &'a String) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "DELETE FROM random_table_entry WHERE namespace = ?1 AND table_name = ?2;",
+            rusqlite::params![namespace, name],
+        )?;
+        let removed = transaction.execute(
+            "DELETE FROM random_table WHERE namespace = ?1 AND name = ?2;",
+            rusqlite::params![namespace, name],
+        )?;
+        if removed == 0 {
+            return Err(format!("There's no table named {name}.").into());
+        }
+        transaction.commit().map_err(|e| e.into())
+    }
+}
+
+impl ClockStorage for DB {
+    fn get_clock(&self, namespace: &str, name: &str) -> Result<ProgressClock, Error> {
         let mut statement = self.connection.prepare(
             "SELECT name, segments, segments_filled, creation_time, ephemeral, color
             FROM progress_clock
             WHERE namespace = ?1
             AND name = ?2
-            AND ((ephemeral = 1 and julianday('now') - julianday(creation_time) < 1) OR (ephemeral = 0));
-        ")?;
+            AND deleted_at IS NULL
+            AND (ephemeral = 0 OR expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP);
+        ",
+        )?;
 
         let clock = statement.query_row(rusqlite::params![&namespace, &name], |row| {
             Ok(ProgressClock {
-                namespace: namespace.clone(),
+                namespace: namespace.to_owned(),
                 name: row.get(0)?,
                 segments: row.get(1)?,
                 segments_filled: row.get(2)?,
                 ephemeral: row.get(4)?,
-                color: Some(row.get(5)?),
+                color: row.get(5)?,
             })
         })?;
 
@@ -79,29 +2694,31 @@ impl DB {
     }
 
     /// Given a namespace (user or guild), returns all available clocks.
-    pub fn get_available_clocks<'a>(
+    fn get_available_clocks(
         &self,
-        namespace: &'a String,
-        partial: &'a str,
+        namespace: &str,
+        partial: &str,
     ) -> Result<Vec<ProgressClock>, Error> {
         let mut statement = self.connection.prepare(
             "SELECT name, segments, segments_filled, creation_time, ephemeral, color
             FROM progress_clock
             WHERE namespace = ?1
             AND name LIKE ?2
-            AND ((ephemeral = 1 and julianday('now') - julianday(creation_time) < 1) OR (ephemeral = 0));
-        ")?;
+            AND deleted_at IS NULL
+            AND (ephemeral = 0 OR expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP);
+        ",
+        )?;
         let mut clocks: Vec<ProgressClock> = Vec::new();
         let clock_iter = statement.query_map(
             rusqlite::params![&namespace, format!("%{partial}%")],
             |row| {
                 Ok(ProgressClock {
-                    namespace: namespace.clone(),
+                    namespace: namespace.to_owned(),
                     name: row.get(0)?,
                     segments: row.get(1)?,
                     segments_filled: row.get(2)?,
                     ephemeral: row.get(4)?,
-                    color: Some(row.get(5)?),
+                    color: row.get(5)?,
                 })
             },
         )?;
@@ -113,49 +2730,603 @@ impl DB {
         Ok(clocks)
     }
 
-    pub fn remove_clock<'a>(&self, namespace: &'a String, name: &'a String) -> Result<(), Error> {
-        let mut statement = self
-            .connection
-            .prepare("DELETE FROM progress_clock WHERE namespace = ?1 AND name = ?2;")?;
-        statement.execute(rusqlite::params![namespace, name])?;
-        Ok(())
+    fn save_clock(&self, progress_clock: &ProgressClock, actor: &str) -> Result<(), Error> {
+        self.forget_removed_clock(&progress_clock.namespace, &progress_clock.name)?;
+        self.record_clock_change(
+            &progress_clock.namespace,
+            &progress_clock.name,
+            "create",
+            actor,
+            || {
+                let mut statement = self.connection.prepare(
+                    "INSERT INTO progress_clock
+                    (namespace, name, segments, segments_filled, ephemeral, color, expires_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, CASE WHEN ?5 = 1 THEN datetime('now', ?7) END);",
+                )?;
+
+                statement.execute(rusqlite::params![
+                    &progress_clock.namespace,
+                    &progress_clock.name,
+                    &progress_clock.segments,
+                    &progress_clock.segments_filled,
+                    &progress_clock.ephemeral,
+                    &progress_clock.color,
+                    DEFAULT_CLOCK_LIFETIME
+                ])?;
+                Ok(progress_clock.name.clone())
+            },
+        )
     }
 
-    pub fn bump_clock<'a>(
-        &self,
-        namespace: &'a String,
-        name: &'a String,
-        count: u8,
-    ) -> Result<(), Error> {
-        let mut statement = self.connection.prepare(
-            "UPDATE progress_clock
-                SET segments_filled = MIN(segments_filled + ?1, segments)
-                WHERE namespace = ?2 AND name = ?3;
-            ",
+    fn bump_clock(&self, namespace: &str, name: &str, count: u8, actor: &str) -> Result<(), Error> {
+        self.record_clock_change(namespace, name, "bump", actor, || {
+            let mut statement = self.connection.prepare(
+                "UPDATE progress_clock
+                    SET segments_filled = MIN(segments_filled + ?1, segments)
+                    WHERE namespace = ?2 AND name = ?3 AND deleted_at IS NULL;
+                ",
+            )?;
+            match statement.execute(rusqlite::params![count, namespace, name])? {
+                0 => Err(format!("There's no clock named {name}.").into()),
+                _ => Ok(name.to_owned()),
+            }
+        })
+    }
+
+    /// Removes a clock. It can be brought back with `undo_clock_change` until the grace period passes.
+    fn remove_clock(&self, namespace: &str, name: &str, actor: &str) -> Result<(), Error> {
+        self.purge_removed_clocks()?;
+        self.record_clock_change(namespace, name, "remove", actor, || {
+            let mut statement = self.connection.prepare(
+                "UPDATE progress_clock SET deleted_at = CURRENT_TIMESTAMP
+                WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;",
+            )?;
+            match statement.execute(rusqlite::params![namespace, name])? {
+                0 => Err(format!("There's no clock named {name}.").into()),
+                _ => Ok(name.to_owned()),
+            }
+        })
+    }
+}
+
+#[test]
+fn test_expired_clocks_are_purged() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    let clock = ProgressClock {
+        namespace: namespace.to_owned(),
+        name: String::from("alarm"),
+        segments: 4,
+        segments_filled: 0,
+        ephemeral: true,
+        color: None,
+    };
+    db.save_clock(&clock, "gm")?;
+    db.set_clock_announcement(namespace, "alarm", Some(42))?;
+    assert!(db.purge_expired_clocks()?.is_empty());
+
+    db.set_clock_expiry(namespace, "alarm", ClockExpiry::Hours(0))?;
+    assert!(db.get_clock(namespace, "alarm").is_err());
+    let expired: Vec<(String, Option<u64>)> = db
+        .purge_expired_clocks()?
+        .into_iter()
+        .map(|clock| (clock.name, clock.announce_channel_id))
+        .collect();
+    assert_eq!(expired, vec![(String::from("alarm"), Some(42))]);
+
+    db.save_clock(&clock, "gm")?;
+    db.set_clock_expiry(namespace, "alarm", ClockExpiry::SessionEnd)?;
+    assert!(db.get_clock(namespace, "alarm").is_ok());
+    assert_eq!(db.end_session(namespace)?, 1);
+    assert!(db.get_clock(namespace, "alarm").is_err());
+
+    // the name of an expired clock can be reused before it's purged.
+    db.save_clock(&clock, "gm")?;
+    assert!(db.get_clock(namespace, "alarm").is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_old_roll_messages_are_purged() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    for message_id in [1, 2] {
+        db.save_roll_message(&RollMessage {
+            message_id,
+            user_id: 3,
+            dice_string: String::from("1d20"),
+        })?;
+    }
+    db.connection.execute(
+        "UPDATE roll_message SET creation_time = datetime('now', '-31 days') WHERE message_id = 1;",
+        [],
+    )?;
+    assert_eq!(db.purge_roll_messages()?, 1);
+    assert!(db.get_roll_message(1).is_err());
+    assert!(db.get_roll_message(2).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_pinned_messages_follow_renames() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = &String::from("guild:1");
+    let mut clock = ProgressClock {
+        namespace: namespace.clone(),
+        name: String::from("escape plam"),
+        segments: 6,
+        segments_filled: 0,
+        ephemeral: false,
+        color: None,
+    };
+    db.save_clock(&clock, "gm")?;
+    for (message_id, clock_name) in [(1, Some(String::from("escape plam"))), (2, None)] {
+        db.save_pinned_message(&PinnedMessage {
+            message_id,
+            channel_id: 10,
+            namespace: namespace.clone(),
+            clock_name,
+        })?;
+    }
+
+    clock.name = String::from("escape plan");
+    db.update_clock(namespace, &String::from("escape plam"), &clock, "gm")?;
+    let pinned_names = |name| -> Result<Vec<Option<String>>, Error> {
+        Ok(db
+            .get_pinned_messages(namespace, name)?
+            .into_iter()
+            .map(|pinned_message| pinned_message.clock_name)
+            .collect())
+    };
+    assert_eq!(
+        pinned_names(Some("escape plan"))?,
+        vec![Some(String::from("escape plan")), None]
+    );
+    // boards show every clock, so they're always included.
+    assert_eq!(pinned_names(Some("escape plam"))?, vec![None]);
+
+    db.undo_clock_change(namespace, true)?;
+    assert_eq!(pinned_names(Some("escape plam"))?.len(), 2);
+
+    db.remove_pinned_message(1)?;
+    assert_eq!(pinned_names(None)?, vec![None]);
+    Ok(())
+}
+
+#[test]
+fn test_filling_a_clock_fires_its_trigger() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    for (name, segments) in [("ritual", 2), ("demon", 1)] {
+        db.save_clock(
+            &ProgressClock {
+                namespace: namespace.to_owned(),
+                name: name.to_owned(),
+                segments,
+                segments_filled: 0,
+                ephemeral: false,
+                color: None,
+            },
+            "gm",
         )?;
-        statement.execute(rusqlite::params![count, namespace, name])?;
-        Ok(())
     }
+    db.set_clock_trigger(
+        namespace,
+        "ritual",
+        &ClockTrigger {
+            linked_clock: Some(String::from("demon")),
+            linked_count: 1,
+            ..Default::default()
+        },
+    )?;
+    db.set_clock_trigger(
+        namespace,
+        "demon",
+        &ClockTrigger {
+            channel_id: Some(42),
+            message: Some(String::from("The demon is loose!")),
+            ..Default::default()
+        },
+    )?;
 
-    pub fn save_clock(&self, progress_clock: &ProgressClock) -> Result<usize, Error> {
-        let mut statement = self.connection.prepare(
-            "INSERT INTO progress_clock
-            (namespace, name, segments, segments_filled, ephemeral, color)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+    db.bump_clock(namespace, "ritual", 1, "gm")?;
+    assert!(db.take_clock_completions(Some(namespace))?.is_empty());
+
+    // filling the ritual bumps the demon, which fills up too.
+    // the ritual has no channel to be announced in, so only a change in its own namespace takes it.
+    db.bump_clock(namespace, "ritual", 1, "gm")?;
+    assert!(db.take_clock_completions(None)?.is_empty());
+    assert!(db.take_clock_completions(Some("guild:2"))?.is_empty());
+    let completions = db.take_clock_completions(Some(namespace))?;
+    let names: Vec<&str> = completions
+        .iter()
+        .map(|completion| completion.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["ritual", "demon"]);
+    assert_eq!(
+        completions[0]
+            .linked
+            .as_ref()
+            .map(|clock| clock.segments_filled),
+        Some(1)
+    );
+
+    // a full clock doesn't fill up again.
+    db.bump_clock(namespace, "ritual", 1, "gm")?;
+    assert!(db.take_clock_completions(Some(namespace))?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_linked_clocks() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    for name in ["crew", "cult", "bribe", "heist"] {
+        db.save_clock(
+            &ProgressClock {
+                namespace: namespace.to_owned(),
+                name: name.to_owned(),
+                segments: 4,
+                segments_filled: 0,
+                ephemeral: false,
+                color: None,
+            },
+            "gm",
         )?;
+    }
+    let link = |kind, clock_name: &str, other_name: &str| ClockLink {
+        namespace: namespace.to_owned(),
+        kind,
+        clock_name: clock_name.to_owned(),
+        other_name: other_name.to_owned(),
+    };
+    let filled = |name| -> Result<u8, Error> { Ok(db.get_clock(namespace, name)?.segments_filled) };
 
-        statement
-            .execute(rusqlite::params![
-                &progress_clock.namespace,
-                &progress_clock.name,
-                &progress_clock.segments,
-                &progress_clock.segments_filled,
-                &progress_clock.ephemeral,
-                &progress_clock
-                    .color
-                    .as_ref()
-                    .unwrap_or(&String::from("green"))
-            ])
-            .map_err(|e| e.into())
+    // a side of a tug of war that runs out of room pushes the other back.
+    db.link_clocks(&link(ClockLinkKind::TugOfWar, "crew", "cult"))?;
+    assert!(
+        db.link_clocks(&link(ClockLinkKind::TugOfWar, "cult", "heist"))
+            .is_err()
+    );
+    assert!(
+        db.link_clocks(&link(ClockLinkKind::Race, "cult", "crew"))
+            .is_err()
+    );
+    db.bump_clock(namespace, "cult", 3, "gm")?;
+    db.bump_clock(namespace, "crew", 2, "gm")?;
+    assert_eq!((filled("crew")?, filled("cult")?), (2, 2));
+    // resizing one side would leave the shared clock lopsided.
+    let mut crew = db.get_clock(namespace, "crew")?;
+    crew.segments = 6;
+    assert!(
+        db.update_clock(&namespace.to_owned(), &String::from("crew"), &crew, "gm")
+            .is_err()
+    );
+
+    // a child filling ticks its parent, which can't become its own grandchild.
+    db.link_clocks(&link(ClockLinkKind::Child, "bribe", "heist"))?;
+    assert!(
+        db.link_clocks(&link(ClockLinkKind::Child, "heist", "bribe"))
+            .is_err()
+    );
+    db.bump_clock(namespace, "bribe", 4, "gm")?;
+    let completions = db.take_clock_completions(Some(namespace))?;
+    assert_eq!(
+        completions[0]
+            .parent
+            .as_ref()
+            .map(|parent| parent.segments_filled),
+        Some(1)
+    );
+
+    // renaming a clock keeps its links.
+    let mut heist = db.get_clock(namespace, "heist")?;
+    heist.name = String::from("score");
+    db.update_clock(&namespace.to_owned(), &String::from("heist"), &heist, "gm")?;
+    assert_eq!(
+        db.get_child_clocks(namespace, "score")?
+            .into_iter()
+            .map(|clock| clock.name)
+            .collect::<Vec<_>>(),
+        vec![String::from("bribe")]
+    );
+    db.unlink_clock(namespace, "bribe", ClockLinkKind::Child)?;
+    assert!(db.get_child_clocks(namespace, "score")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_scheduled_ticks() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    db.save_clock(
+        &ProgressClock {
+            namespace: namespace.to_owned(),
+            name: String::from("crackdown"),
+            segments: 8,
+            segments_filled: 0,
+            ephemeral: false,
+            color: None,
+        },
+        "gm",
+    )?;
+    db.set_clock_schedule(
+        namespace,
+        "crackdown",
+        &ClockSchedule {
+            every_hours: Some(6),
+            count: 1,
+            on_session_start: true,
+            next_tick_at: None,
+        },
+    )?;
+    assert!(db.run_scheduled_ticks()?.is_empty());
+
+    // the bot was down for the last three ticks.
+    db.connection.execute(
+        "UPDATE progress_clock SET next_tick_at = datetime('now', '-13 hours');",
+        [],
+    )?;
+    let ticked = db.run_scheduled_ticks()?;
+    assert_eq!(ticked[0].segments_filled, 3);
+    let next_tick_at = db.get_clock_schedule(namespace, "crackdown")?.next_tick_at;
+    assert!(next_tick_at > Some(0));
+    assert!(db.run_scheduled_ticks()?.is_empty());
+
+    let ticked = db.start_session(namespace, "gm")?;
+    assert_eq!(ticked[0].segments_filled, 4);
+    // changing how much it ticks keeps the timer going.
+    db.set_clock_schedule(
+        namespace,
+        "crackdown",
+        &ClockSchedule {
+            every_hours: Some(6),
+            count: 2,
+            on_session_start: true,
+            next_tick_at: None,
+        },
+    )?;
+    assert_eq!(
+        db.get_clock_schedule(namespace, "crackdown")?.next_tick_at,
+        next_tick_at
+    );
+
+    // a timed tick doesn't take away what could be redone.
+    db.undo_clock_change(&namespace.to_owned(), true)?;
+    db.connection.execute(
+        "UPDATE progress_clock SET next_tick_at = datetime('now', '-1 hours');",
+        [],
+    )?;
+    assert_eq!(db.run_scheduled_ticks()?.len(), 1);
+    assert_eq!(
+        db.redo_clock_change(&namespace.to_owned(), true)?.action,
+        "bump"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_hidden_clocks() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    for name in ["heist", "cult's plan"] {
+        db.save_clock(
+            &ProgressClock {
+                namespace: namespace.to_owned(),
+                name: String::from(name),
+                segments: 6,
+                segments_filled: 0,
+                ephemeral: false,
+                color: None,
+            },
+            "gm",
+        )?;
     }
+    db.set_clock_access(namespace, "cult's plan", ClockAccess::GmOnly)?;
+    assert_eq!(
+        db.get_clock_access(namespace, "heist")?,
+        ClockAccess::Everyone
+    );
+    let public = db.get_public_clocks(namespace)?;
+    assert_eq!(public.len(), 1);
+    assert_eq!(public[0].name, "heist");
+
+    db.bump_clock(namespace, "heist", 1, "player")?;
+    db.bump_clock(namespace, "cult's plan", 1, "gm")?;
+    // a player's undo passes over the hidden clock, rather than giving it away.
+    let event = db.undo_clock_change(&String::from(namespace), false)?;
+    assert_eq!(event.name(), "heist");
+    let event = db.undo_clock_change(&String::from(namespace), true)?;
+    assert_eq!(event.name(), "cult's plan");
+
+    db.set_gm_role(1, Some(2))?;
+    assert_eq!(db.get_gm_role(1)?, Some(2));
+    db.set_gm_role(1, None)?;
+    assert_eq!(db.get_gm_role(1)?, None);
+    Ok(())
+}
+
+#[test]
+fn test_listing_clocks_by_label() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    for (name, segments_filled) in [("lampblacks", 1), ("red sashes", 5), ("heist", 3)] {
+        db.save_clock(
+            &ProgressClock {
+                namespace: namespace.to_owned(),
+                name: String::from(name),
+                segments: 6,
+                segments_filled,
+                ephemeral: false,
+                color: None,
+            },
+            "gm",
+        )?;
+    }
+    for name in ["lampblacks", "red sashes"] {
+        db.set_clock_labels(
+            namespace,
+            name,
+            &ClockLabels {
+                category: Some(ClockCategory::Faction),
+                tags: ClockLabels::parse_tags(&format!("Crows, {name}")),
+            },
+        )?;
+    }
+    assert_eq!(
+        db.get_clock_labels(namespace, "lampblacks")?.tags,
+        ["crows", "lampblacks"]
+    );
+    assert_eq!(
+        db.get_tags(namespace, false)?,
+        ["crows", "lampblacks", "red sashes"]
+    );
+
+    let names = |filter: &ClockFilter, order| -> Result<Vec<String>, Error> {
+        Ok(db
+            .list_clocks(namespace, filter, order)?
+            .into_iter()
+            .map(|(clock, _)| clock.name)
+            .collect())
+    };
+    let factions = ClockFilter {
+        category: Some(ClockCategory::Faction),
+        ..Default::default()
+    };
+    assert_eq!(
+        names(&factions, ClockOrder::MostComplete)?,
+        ["red sashes", "lampblacks"]
+    );
+    // a tag only matches whole, so "lamp" finds nothing.
+    for (tag, expected) in [("crows", 2), ("LAMPBLACKS", 1), ("lamp", 0)] {
+        let filter = ClockFilter {
+            tag: Some(String::from(tag)),
+            ..Default::default()
+        };
+        assert_eq!(names(&filter, ClockOrder::Name)?.len(), expected);
+    }
+    assert_eq!(
+        names(&ClockFilter::default(), ClockOrder::LeastComplete)?,
+        ["lampblacks", "heist", "red sashes"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_clock_templates() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let namespace = "guild:1";
+    let mut template = ClockTemplate {
+        namespace: namespace.to_owned(),
+        name: String::from("Heist Alarm"),
+        segments: 4,
+        color: Some(String::from("red")),
+        ephemeral: true,
+        labels: ClockLabels {
+            category: Some(ClockCategory::Threat),
+            tags: vec![String::from("heist")],
+        },
+    };
+    db.save_clock_template(&template)?;
+    assert_eq!(
+        db.get_clock_template(namespace, "heist alarm")?,
+        Some(template.clone())
+    );
+    assert_eq!(db.get_clock_template("guild:2", "heist alarm")?, None);
+
+    // saving it again replaces it, whatever the case of its name.
+    template.segments = 6;
+    template.name = String::from("heist alarm");
+    db.save_clock_template(&template)?;
+    assert_eq!(db.get_clock_templates(namespace, "")?, [template]);
+
+    db.remove_clock_template(namespace, "Heist Alarm")?;
+    assert!(db.get_clock_templates(namespace, "")?.is_empty());
+    assert!(db.remove_clock_template(namespace, "Heist Alarm").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_legacy_namespaces_move_once() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    connection.execute_batch(
+        "
+        CREATE TABLE progress_clock(
+            namespace TEXT,
+            name TEXT,
+            segments INTEGER,
+            segments_filled INTEGER,
+            creation_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+            ephemeral BOOL,
+            PRIMARY KEY(namespace, name)
+        );
+        INSERT INTO progress_clock (namespace, name, segments, segments_filled, ephemeral)
+        VALUES ('The Crew', 'heat', 6, 2, 0);
+        ",
+    )?;
+    migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let legacy_name = String::from("The Crew");
+    let namespace = String::from("guild:1");
+    // a server made after the switch to IDs can't take clocks by sharing a name with an old one.
+    let made_since = i64::MAX;
+    assert!(!db.is_legacy_namespace(&legacy_name, made_since)?);
+    assert_eq!(
+        db.migrate_legacy_namespace(&legacy_name, &namespace, made_since)?,
+        0
+    );
+
+    assert_eq!(db.migrate_legacy_namespace(&legacy_name, &namespace, 0)?, 1);
+    assert_eq!(db.get_clock(&namespace, "heat")?.segments_filled, 2);
+    assert!(!db.is_legacy_namespace(&legacy_name, 0)?);
+    assert_eq!(
+        db.migrate_legacy_namespace(&legacy_name, &String::from("user:2"), 0)?,
+        0
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_tags() {
+    assert_eq!(
+        ClockLabels::parse_tags(" Crows, docks,, crows ,"),
+        ["crows", "docks"]
+    );
+    let long_tag = "a".repeat(100);
+    assert_eq!(
+        ClockLabels::parse_tags(&long_tag)[0].chars().count(),
+        MAX_TAG_CHARS
+    );
+    let many_tags: Vec<String> = (0..20).map(|n| format!("tag {n}")).collect();
+    assert_eq!(
+        ClockLabels::parse_tags(&many_tags.join(",")).len(),
+        MAX_CLOCK_TAGS
+    );
 }
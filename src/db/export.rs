@@ -0,0 +1,328 @@
+//! Clocks written out to a file and read back in, to keep backups and to move a campaign to another server.
+//!
+//! An export holds each clock with its category, tags, access and history. Triggers, schedules and links
+//! refer to channels, roles and other clocks of the server they were made in, so they're left behind.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CLOCK_EVENT_COLUMNS, ClockAccess, ClockCategory, ClockEvent, ClockLabels, ClockStorage, DB,
+    Error, ProgressClock,
+};
+
+/// the version of the file `DB::export_clocks` makes. Bumped when older versions couldn't read it.
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ClockExport {
+    pub version: u32,
+    pub clocks: Vec<ExportedClock>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedClock {
+    #[serde(flatten)]
+    pub clock: ProgressClock,
+    #[serde(default)]
+    pub category: Option<ClockCategory>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub access: ClockAccess,
+    /// oldest change first.
+    #[serde(default)]
+    pub history: Vec<ClockEvent>,
+}
+
+/// how an export is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Yaml,
+}
+
+impl ExportFormat {
+    /// Guesses the format of a file from its name, taking anything that isn't YAML to be JSON.
+    pub fn from_file_name(file_name: &str) -> Self {
+        let file_name = file_name.to_ascii_lowercase();
+        match file_name.ends_with(".yaml") || file_name.ends_with(".yml") {
+            true => Self::Yaml,
+            false => Self::Json,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+        }
+    }
+}
+
+impl ClockExport {
+    pub fn to_bytes(&self, format: ExportFormat) -> Result<Vec<u8>, Error> {
+        Ok(match format {
+            ExportFormat::Json => serde_json::to_vec_pretty(self)?,
+            ExportFormat::Yaml => serde_norway::to_string(self)?.into_bytes(),
+        })
+    }
+
+    /// Reads an export back in. Fails on files that aren't exports, or that come from a newer version.
+    pub fn from_bytes(data: &[u8], format: ExportFormat) -> Result<Self, Error> {
+        let export: Self = match format {
+            ExportFormat::Json => serde_json::from_slice(data)
+                .map_err(|e| format!("That isn't a clock export: {e}"))?,
+            ExportFormat::Yaml => serde_norway::from_slice(data)
+                .map_err(|e| format!("That isn't a clock export: {e}"))?,
+        };
+        if export.version > EXPORT_VERSION {
+            return Err("That export comes from a newer version of the bot.".into());
+        }
+        Ok(export)
+    }
+}
+
+/// what to do with an imported clock that has the same name as one already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportConflict {
+    /// keep the clock that's there, and leave the imported one out.
+    Skip,
+    /// replace the clock that's there with the imported one.
+    Overwrite,
+    /// import the clock under a name that isn't taken, like `heist (2)`.
+    Rename,
+}
+
+impl DB {
+    /// Gathers every clock in `namespace`, along with how it's filed and its history, for writing to a file.
+    pub fn export_clocks(&self, namespace: &str) -> Result<ClockExport, Error> {
+        let accesses = self.get_clock_accesses(namespace)?;
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {CLOCK_EVENT_COLUMNS}
+            FROM progress_clock_event
+            WHERE namespace = ?1 AND undone = 0 AND ?2 IN (before_name, after_name)
+            ORDER BY id ASC;"
+        ))?;
+
+        let mut clocks = Vec::new();
+        for clock in self.get_available_clocks(namespace, "")? {
+            let labels = self.get_clock_labels(namespace, &clock.name)?;
+            let mut history = Vec::new();
+            for event in statement.query_map(rusqlite::params![namespace, &clock.name], |row| {
+                ClockEvent::from_row(namespace, row)
+            })? {
+                history.push(event?);
+            }
+
+            clocks.push(ExportedClock {
+                access: accesses
+                    .get(&clock.name)
+                    .copied()
+                    .unwrap_or(ClockAccess::Everyone),
+                clock,
+                category: labels.category,
+                tags: labels.tags,
+                history,
+            });
+        }
+
+        Ok(ClockExport {
+            version: EXPORT_VERSION,
+            clocks,
+        })
+    }
+
+    /// Adds an exported clock to `namespace`, settling a clash of names with `conflict`.
+    /// Returns the name the clock was imported under, or `None` if it was skipped.
+    ///
+    /// The import is one change that `/clock undo` can take back. The clock's earlier history is kept to read,
+    /// but can't be undone.
+    pub fn import_clock(
+        &self,
+        namespace: &str,
+        exported: &ExportedClock,
+        conflict: ImportConflict,
+        actor: &str,
+    ) -> Result<Option<String>, Error> {
+        let original_name = &exported.clock.name;
+        let taken = |name: &str| self.get_clock(namespace, name).is_ok();
+        let (name, overwrite) = match conflict {
+            _ if !taken(original_name) => (original_name.clone(), false),
+            ImportConflict::Skip => return Ok(None),
+            ImportConflict::Overwrite => (original_name.clone(), true),
+            ImportConflict::Rename => {
+                let name = (2..)
+                    .map(|n| format!("{original_name} ({n})"))
+                    .find(|name| !taken(name))
+                    .expect("there's always a free name");
+                (name, false)
+            }
+        };
+        let clock = ProgressClock {
+            namespace: namespace.to_owned(),
+            name: name.clone(),
+            ..exported.clock.clone()
+        };
+
+        // the clock, its history and its labels are imported together or not at all.
+        self.atomically(|| {
+            if !overwrite {
+                self.forget_removed_clock(namespace, &name)?;
+            }
+            for event in &exported.history {
+                self.insert_imported_event(namespace, event, original_name, &name)?;
+            }
+            self.record_clock_change(namespace, &name, "import", actor, || {
+                let statement = if overwrite {
+                    "UPDATE progress_clock
+                    SET segments = ?3, segments_filled = ?4, ephemeral = ?5, color = ?6,
+                        expires_at = CASE WHEN ?5 = 1 THEN COALESCE(expires_at, datetime('now', ?7)) END
+                    WHERE namespace = ?1 AND name = ?2 AND deleted_at IS NULL;"
+                } else {
+                    "INSERT INTO progress_clock
+                    (namespace, name, segments, segments_filled, ephemeral, color, expires_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, CASE WHEN ?5 = 1 THEN datetime('now', ?7) END);"
+                };
+                self.connection.execute(
+                    statement,
+                    rusqlite::params![
+                        namespace,
+                        &clock.name,
+                        clock.segments,
+                        clock.segments_filled,
+                        clock.ephemeral,
+                        &clock.color,
+                        super::DEFAULT_CLOCK_LIFETIME
+                    ],
+                )?;
+                Ok(clock.name.clone())
+            })?;
+
+            self.set_clock_labels(
+                namespace,
+                &name,
+                &ClockLabels {
+                    category: exported.category,
+                    // tags from a file are tidied like typed ones.
+                    tags: ClockLabels::parse_tags(&exported.tags.join(",")),
+                },
+            )?;
+            self.set_clock_access(namespace, &name, exported.access)?;
+            Ok(Some(name))
+        })
+    }
+
+    /// Adds a change from an imported clock's history, naming the clock `name` where it was `original_name`.
+    fn insert_imported_event(
+        &self,
+        namespace: &str,
+        event: &ClockEvent,
+        original_name: &str,
+        name: &str,
+    ) -> Result<(), Error> {
+        let renamed = |clock: &Option<ProgressClock>| {
+            clock
+                .as_ref()
+                .map(|clock| match clock.name == original_name {
+                    true => name.to_owned(),
+                    false => clock.name.clone(),
+                })
+        };
+        self.connection.execute(
+            "INSERT INTO progress_clock_event
+            (namespace, action, actor, time, imported,
+                before_name, before_segments, before_segments_filled, before_ephemeral, before_color,
+                after_name, after_segments, after_segments_filled, after_ephemeral, after_color)
+            VALUES (?1, ?2, ?3, datetime(?4, 'unixepoch'), 1, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);",
+            rusqlite::params![
+                namespace,
+                &event.action,
+                &event.actor,
+                event.time,
+                renamed(&event.before),
+                event.before.as_ref().map(|clock| clock.segments),
+                event.before.as_ref().map(|clock| clock.segments_filled),
+                event.before.as_ref().map(|clock| clock.ephemeral),
+                event.before.as_ref().and_then(|clock| clock.color.as_ref()),
+                renamed(&event.after),
+                event.after.as_ref().map(|clock| clock.segments),
+                event.after.as_ref().map(|clock| clock.segments_filled),
+                event.after.as_ref().map(|clock| clock.ephemeral),
+                event.after.as_ref().and_then(|clock| clock.color.as_ref()),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_exported_clocks_import_elsewhere() -> Result<(), Error> {
+    let connection = rusqlite::Connection::open_in_memory()?;
+    super::migrations::migrate(&connection)?;
+    let db = DB { connection };
+
+    let (from, to) = ("guild:1", "guild:2");
+    for namespace in [from, to] {
+        db.save_clock(
+            &ProgressClock {
+                namespace: namespace.to_owned(),
+                name: String::from("heist"),
+                segments: 6,
+                segments_filled: 0,
+                ephemeral: false,
+                color: Some(String::from("crimson")),
+            },
+            "gm",
+        )?;
+    }
+    db.bump_clock(from, "heist", 2, "player")?;
+    db.set_clock_labels(
+        from,
+        "heist",
+        &ClockLabels {
+            category: Some(ClockCategory::Project),
+            tags: vec![String::from("docks")],
+        },
+    )?;
+    db.set_clock_access(from, "heist", ClockAccess::ViewOnly)?;
+
+    for format in [ExportFormat::Json, ExportFormat::Yaml] {
+        let data = db.export_clocks(from)?.to_bytes(format)?;
+        let export = ClockExport::from_bytes(&data, format)?;
+        assert_eq!(export.clocks[0].history.len(), 2);
+        assert_eq!(
+            db.import_clock(to, &export.clocks[0], ImportConflict::Skip, "gm")?,
+            None
+        );
+    }
+
+    let data = db.export_clocks(from)?.to_bytes(ExportFormat::Yaml)?;
+    let mut export = ClockExport::from_bytes(&data, ExportFormat::Yaml)?;
+    // a hand-edited file's tags are tidied on the way in.
+    export.clocks[0].tags = vec![String::from(" Docks "), String::new()];
+    let renamed = db.import_clock(to, &export.clocks[0], ImportConflict::Rename, "gm")?;
+    assert_eq!(renamed.as_deref(), Some("heist (2)"));
+    let clock = db.get_clock(to, "heist (2)")?;
+    assert_eq!(clock.segments_filled, 2);
+    assert_eq!(clock.color.as_deref(), Some("crimson"));
+    assert_eq!(db.get_clock_labels(to, "heist (2)")?.tags, ["docks"]);
+    assert_eq!(db.get_clock_access(to, "heist (2)")?, ClockAccess::ViewOnly);
+    // the old history reads under the new name, but only the import itself can be undone.
+    let history = db.get_clock_history(&String::from(to), &String::from("heist (2)"))?;
+    assert_eq!(history.len(), 3);
+    let undone = db.undo_clock_change(&String::from(to), true)?;
+    assert_eq!(undone.action, "import");
+    assert!(db.get_clock(to, "heist (2)").is_err());
+    let undone = db.undo_clock_change(&String::from(to), true)?;
+    assert_eq!(
+        (undone.action.as_str(), undone.name().as_str()),
+        ("create", "heist")
+    );
+
+    db.import_clock(to, &export.clocks[0], ImportConflict::Overwrite, "gm")?;
+    assert_eq!(db.get_clock(to, "heist")?.segments_filled, 2);
+    assert!(
+        ClockExport::from_bytes(b"{\"version\": 99, \"clocks\": []}", ExportFormat::Json).is_err()
+    );
+    Ok(())
+}
@@ -0,0 +1,344 @@
+//! Versioned changes to the database schema.
+//!
+//! Every step runs once, in order, and the version it brings the database to is recorded in `schema_version`.
+//! A step's version is its position in `MIGRATIONS`, counting from 1, so new steps only ever go at the end.
+//!
+//! Databases from before versions were recorded start at version 0. Their tables may already exist, so every
+//! step is written to be harmless when its change is already there.
+
+use rusqlite::Connection;
+
+use super::{
+    ClockCompletion, ClockEvent, ClockLink, ClockTemplate, ClockTheme, DiceMacro, Error, GmRole,
+    InitiativeTracker, InlineRollChannel, LegacyNamespace, NamespaceScope, ORM, PinnedMessage,
+    ProgressClock, RandomTable, RollMessage,
+};
+
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create progress clocks",
+        apply: create_table::<ProgressClock>,
+    },
+    Migration {
+        description: "add a colour to progress clocks",
+        apply: |connection| add_column(connection, "progress_clock", "color", "TEXT"),
+    },
+    Migration {
+        description: "create inline roll channels",
+        apply: create_table::<InlineRollChannel>,
+    },
+    Migration {
+        description: "create roll messages",
+        apply: create_table::<RollMessage>,
+    },
+    Migration {
+        description: "create dice macros",
+        apply: create_table::<DiceMacro>,
+    },
+    Migration {
+        description: "create initiative trackers",
+        apply: create_table::<InitiativeTracker>,
+    },
+    Migration {
+        description: "create random tables",
+        apply: create_table::<RandomTable>,
+    },
+    Migration {
+        description: "let removed progress clocks be restored",
+        apply: |connection| add_column(connection, "progress_clock", "deleted_at", "DATETIME"),
+    },
+    Migration {
+        description: "create progress clock history",
+        apply: create_table::<ClockEvent>,
+    },
+    Migration {
+        description: "create namespace scopes",
+        apply: create_table::<NamespaceScope>,
+    },
+    Migration {
+        description: "let ephemeral progress clocks last any length of time",
+        apply: |connection| {
+            add_column(connection, "progress_clock", "expires_at", "DATETIME")?;
+            add_column(
+                connection,
+                "progress_clock",
+                "expires_with_session",
+                "BOOL DEFAULT 0",
+            )?;
+            add_column(
+                connection,
+                "progress_clock",
+                "announce_channel_id",
+                "INTEGER",
+            )?;
+            // ephemeral clocks used to last a day from when they were made.
+            connection.execute_batch(
+                "UPDATE progress_clock SET expires_at = datetime(creation_time, '+1 day')
+                WHERE ephemeral = 1 AND expires_at IS NULL;",
+            )
+        },
+    },
+    Migration {
+        description: "create pinned clock messages",
+        apply: create_table::<PinnedMessage>,
+    },
+    Migration {
+        description: "let progress clocks do something when they fill up",
+        apply: |connection| {
+            for (column, definition) in [
+                ("complete_channel_id", "INTEGER"),
+                ("complete_message", "TEXT"),
+                ("complete_role_id", "INTEGER"),
+                ("complete_clock", "TEXT"),
+                ("complete_count", "INTEGER"),
+                ("complete_sound", "TEXT"),
+            ] {
+                add_column(connection, "progress_clock", column, definition)?;
+            }
+            create_table::<ClockCompletion>(connection)
+        },
+    },
+    Migration {
+        description: "create progress clock links",
+        apply: create_table::<ClockLink>,
+    },
+    Migration {
+        description: "let progress clocks tick on a schedule",
+        apply: |connection| {
+            for (column, definition) in [
+                ("tick_every_hours", "INTEGER"),
+                ("tick_count", "INTEGER DEFAULT 1"),
+                ("tick_on_session_start", "BOOL DEFAULT 0"),
+                ("next_tick_at", "DATETIME"),
+            ] {
+                add_column(connection, "progress_clock", column, definition)?;
+            }
+            Ok(())
+        },
+    },
+    Migration {
+        description: "let progress clocks be hidden from players",
+        apply: |connection| {
+            add_column(
+                connection,
+                "progress_clock",
+                "access",
+                "TEXT DEFAULT 'everyone'",
+            )?;
+            create_table::<GmRole>(connection)
+        },
+    },
+    Migration {
+        description: "file progress clocks under categories and tags",
+        apply: |connection| {
+            add_column(connection, "progress_clock", "category", "TEXT")?;
+            add_column(connection, "progress_clock", "tags", "TEXT")
+        },
+    },
+    Migration {
+        description: "keep the history of imported progress clocks out of undo",
+        apply: |connection| {
+            add_column(
+                connection,
+                "progress_clock_event",
+                "imported",
+                "BOOL DEFAULT 0",
+            )
+        },
+    },
+    Migration {
+        description: "create progress clock templates",
+        apply: create_table::<ClockTemplate>,
+    },
+    Migration {
+        description: "create progress clock themes",
+        apply: create_table::<ClockTheme>,
+    },
+    Migration {
+        description: "let progress clock themes set the shape, size and shading of clocks",
+        apply: |connection| {
+            add_column(connection, "clock_theme", "style", "TEXT DEFAULT 'pie'")?;
+            add_column(connection, "clock_theme", "size", "INTEGER DEFAULT 200")?;
+            add_column(
+                connection,
+                "clock_theme",
+                "shade_opacity",
+                "REAL DEFAULT 0.4",
+            )
+        },
+    },
+    Migration {
+        description: "remember which namespaces are still stored under a guild or user's name",
+        apply: |connection| {
+            create_table::<LegacyNamespace>(connection)?;
+            connection.execute_batch(
+                "
+                INSERT OR IGNORE INTO legacy_namespace (name)
+                SELECT namespace FROM progress_clock
+                UNION SELECT namespace FROM dice_macro
+                UNION SELECT namespace FROM random_table;
+                DELETE FROM legacy_namespace
+                WHERE name IS NULL OR name LIKE 'guild:%' OR name LIKE 'user:%';
+                ",
+            )
+        },
+    },
+    Migration {
+        description: "ignore case in the names of progress clock templates",
+        apply: |connection| {
+            // SQLite can't change a column's collation, so the table is copied into one that ignores case.
+            // Of templates whose names differ only in case, the last one saved is kept.
+            connection.execute_batch(
+                "
+                CREATE TABLE clock_template_nocase(
+                    namespace TEXT,
+                    name TEXT COLLATE NOCASE,
+                    segments INTEGER,
+                    color TEXT,
+                    ephemeral BOOL,
+                    category TEXT,
+                    tags TEXT,
+                    PRIMARY KEY(namespace, name)
+                );
+                INSERT OR REPLACE INTO clock_template_nocase
+                SELECT namespace, name, segments, color, ephemeral, category, tags
+                FROM clock_template ORDER BY rowid;
+                DROP TABLE clock_template;
+                ALTER TABLE clock_template_nocase RENAME TO clock_template;
+                ",
+            )
+        },
+    },
+];
+
+fn create_table<T: ORM>(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(T::schema())
+}
+
+/// Adds a column to `table`, unless the table already has it.
+fn add_column(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if connection
+        .prepare(&format!("SELECT {column} FROM {table} LIMIT 0;"))
+        .is_ok()
+    {
+        return Ok(());
+    }
+    connection.execute_batch(&format!(
+        "ALTER TABLE {table} ADD COLUMN {column} {definition};"
+    ))
+}
+
+/// Returns the version the database's schema is at.
+pub fn schema_version(connection: &Connection) -> Result<usize, Error> {
+    let version: Option<i64> =
+        connection.query_row("SELECT MAX(version) FROM schema_version;", [], |row| {
+            row.get(0)
+        })?;
+    Ok(version.unwrap_or(0) as usize)
+}
+
+/// Brings the database up to the latest schema version.
+/// Refuses to touch a database whose schema is newer than this build knows about.
+pub fn migrate(connection: &Connection) -> Result<(), Error> {
+    connection.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS schema_version(
+            version INTEGER PRIMARY KEY,
+            description TEXT,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        ",
+    )?;
+
+    let version = schema_version(connection)?;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "the database is at schema version {version}, but this build only knows up to version {}. Use a newer build of troller.",
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.unchecked_transaction()?;
+        (migration.apply)(connection)?;
+        transaction.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, ?2);",
+            rusqlite::params![(idx + 1) as i64, migration.description],
+        )?;
+        transaction.commit()?;
+        println!(
+            "migrated the database to version {}: {}",
+            idx + 1,
+            migration.description
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_new_database() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    migrate(&connection)?;
+    assert_eq!(schema_version(&connection)?, MIGRATIONS.len());
+
+    // migrating again changes nothing.
+    migrate(&connection)?;
+    assert_eq!(schema_version(&connection)?, MIGRATIONS.len());
+
+    connection.execute(
+        "INSERT INTO schema_version (version, description) VALUES (?1, 'from the future');",
+        rusqlite::params![(MIGRATIONS.len() + 1) as i64],
+    )?;
+    assert!(migrate(&connection).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_migrate_unversioned_database() -> Result<(), Error> {
+    let connection = Connection::open_in_memory()?;
+    connection.execute_batch(
+        "
+        CREATE TABLE progress_clock(
+            namespace TEXT,
+            name TEXT,
+            segments INTEGER,
+            segments_filled INTEGER,
+            creation_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+            ephemeral BOOL,
+            PRIMARY KEY(namespace, name)
+        );
+        INSERT INTO progress_clock (namespace, name, segments, segments_filled, ephemeral)
+        VALUES ('guild', 'ritual', 6, 2, 0);
+        ",
+    )?;
+
+    migrate(&connection)?;
+    let (segments_filled, color, deleted_at): (u8, Option<String>, Option<String>) = connection
+        .query_row(
+            "SELECT segments_filled, color, deleted_at FROM progress_clock WHERE name = 'ritual';",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+    assert_eq!((segments_filled, color, deleted_at), (2, None, None));
+
+    // the clock wasn't ephemeral, so it never expires.
+    let expires_at: Option<String> = connection.query_row(
+        "SELECT expires_at FROM progress_clock WHERE name = 'ritual';",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(expires_at, None);
+    Ok(())
+}
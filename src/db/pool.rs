@@ -0,0 +1,135 @@
+//! Shares the database between async commands without blocking the runtime.
+//!
+//! Writes go through a single connection, one at a time. Reads use their own read-only connections, which
+//! SQLite's write-ahead log lets run alongside a write, so autocomplete stays responsive while a command saves.
+//! Every query runs on tokio's blocking thread pool.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{DB, Error};
+
+/// Read-only connections kept open between reads. More are opened when needed and closed afterwards.
+const MAX_IDLE_READERS: usize = 4;
+
+/// Cloning a pool shares its connections.
+#[derive(Clone)]
+pub struct DbPool {
+    path: PathBuf,
+    writer: Arc<Mutex<DB>>,
+    readers: Arc<Mutex<Vec<DB>>>,
+}
+
+impl DbPool {
+    /// Opens the database at `path`, bringing its schema up to date.
+    pub fn open(path: PathBuf) -> Result<Self, Error> {
+        let writer = DB::open(&path)?;
+        Ok(DbPool {
+            path,
+            writer: Arc::new(Mutex::new(writer)),
+            readers: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    /// Runs `query` against the write connection on a blocking thread. Writes run one at a time.
+    pub async fn write<F, T>(&self, query: F) -> Result<T, Error>
+    where
+        F: FnOnce(&DB) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let writer = self.writer.clone();
+        tokio::task::spawn_blocking(move || {
+            let db = match writer.lock() {
+                Ok(db) => db,
+                Err(poisoned) => {
+                    // a panic mid-query can leave a transaction open, which would swallow every later write.
+                    writer.clear_poison();
+                    let db = poisoned.into_inner();
+                    if !db.connection.is_autocommit() {
+                        db.connection.execute_batch("ROLLBACK;")?;
+                    }
+                    db
+                }
+            };
+            query(&db)
+        })
+        .await?
+    }
+
+    /// Runs `query` against a read-only connection on a blocking thread. Reads don't wait for writes.
+    pub async fn read<F, T>(&self, query: F) -> Result<T, Error>
+    where
+        F: FnOnce(&DB) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let path = self.path.clone();
+        let readers = self.readers.clone();
+        tokio::task::spawn_blocking(move || {
+            let idle = readers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .pop();
+            let db = match idle {
+                Some(db) => db,
+                None => DB::open_read_only(&path)?,
+            };
+
+            let result = query(&db);
+
+            let mut readers = readers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if readers.len() < MAX_IDLE_READERS {
+                readers.push(db);
+            }
+            result
+        })
+        .await?
+    }
+}
+
+#[test]
+fn test_pool_reads_see_writes() -> Result<(), Error> {
+    use super::DiceMacro;
+
+    let path = std::env::temp_dir().join(format!("troller-pool-{}.sqlite", std::process::id()));
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(async {
+        let pool = DbPool::open(path.clone())?;
+        pool.write(|db| {
+            db.save_macro(&DiceMacro {
+                namespace: String::from("guild:1"),
+                name: String::from("init"),
+                dice_string: String::from("1d20 + 3"),
+            })
+        })
+        .await?;
+
+        let dice_string = pool
+            .read(|db| db.get_macro(&String::from("guild:1"), &String::from("init")))
+            .await?
+            .dice_string;
+        assert_eq!(dice_string, "1d20 + 3");
+
+        // read-only connections refuse to write.
+        assert!(pool.read(|db| db.set_inline_rolls(1, true)).await.is_err());
+
+        // a write that panics inside a transaction doesn't leave it open for the next one.
+        let panicked = pool
+            .write(|db| -> Result<(), Error> {
+                db.connection.execute_batch("BEGIN;")?;
+                panic!("mid-transaction");
+            })
+            .await;
+        assert!(panicked.is_err());
+        assert!(pool.write(|db| Ok(db.connection.is_autocommit())).await?);
+        Ok(())
+    });
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+    result
+}
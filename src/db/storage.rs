@@ -0,0 +1,119 @@
+//! The clock operations commands rely on, so they can run against something other than SQLite.
+//! `DB` implements them on top of `troller.sqlite`.
+
+#[cfg(test)]
+use std::{collections::BTreeMap, sync::Mutex};
+
+use super::{Error, ProgressClock};
+
+/// Somewhere progress clocks are kept, keyed by namespace and name.
+pub trait ClockStorage {
+    fn get_clock(&self, namespace: &str, name: &str) -> Result<ProgressClock, Error>;
+
+    /// Returns the clocks in `namespace` whose names contain `partial`.
+    fn get_available_clocks(
+        &self,
+        namespace: &str,
+        partial: &str,
+    ) -> Result<Vec<ProgressClock>, Error>;
+
+    /// Adds a new clock. Fails if the namespace already has a clock with its name.
+    fn save_clock(&self, progress_clock: &ProgressClock, actor: &str) -> Result<(), Error>;
+
+    /// Fills `count` more segments of a clock, stopping when it's full.
+    fn bump_clock(&self, namespace: &str, name: &str, count: u8, actor: &str) -> Result<(), Error>;
+
+    fn remove_clock(&self, namespace: &str, name: &str, actor: &str) -> Result<(), Error>;
+}
+
+/// Keeps clocks in memory, for tests. Nothing is written to disk, and ephemeral clocks never expire.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage {
+    clocks: Mutex<BTreeMap<(String, String), ProgressClock>>,
+}
+
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+impl ClockStorage for MemoryStorage {
+    fn get_clock(&self, namespace: &str, name: &str) -> Result<ProgressClock, Error> {
+        self.clocks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&(namespace.to_owned(), name.to_owned()))
+            .cloned()
+            .ok_or_else(|| format!("There's no clock named {name}.").into())
+    }
+
+    fn get_available_clocks(
+        &self,
+        namespace: &str,
+        partial: &str,
+    ) -> Result<Vec<ProgressClock>, Error> {
+        // LIKE in SQLite ignores ASCII case, so this does too.
+        let partial = partial.to_ascii_lowercase();
+        Ok(self
+            .clocks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .filter(|clock| {
+                clock.namespace == namespace && clock.name.to_ascii_lowercase().contains(&partial)
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn save_clock(&self, progress_clock: &ProgressClock, _actor: &str) -> Result<(), Error> {
+        let mut clocks = self
+            .clocks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = (
+            progress_clock.namespace.clone(),
+            progress_clock.name.clone(),
+        );
+        if clocks.contains_key(&key) {
+            return Err(format!("There's already a clock named {}.", progress_clock.name).into());
+        }
+
+        clocks.insert(key, progress_clock.clone());
+        Ok(())
+    }
+
+    fn bump_clock(
+        &self,
+        namespace: &str,
+        name: &str,
+        count: u8,
+        _actor: &str,
+    ) -> Result<(), Error> {
+        let mut clocks = self
+            .clocks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let clock = clocks
+            .get_mut(&(namespace.to_owned(), name.to_owned()))
+            .ok_or_else(|| format!("There's no clock named {name}."))?;
+        clock.segments_filled = clock
+            .segments_filled
+            .saturating_add(count)
+            .min(clock.segments);
+        Ok(())
+    }
+
+    fn remove_clock(&self, namespace: &str, name: &str, _actor: &str) -> Result<(), Error> {
+        self.clocks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&(namespace.to_owned(), name.to_owned()))
+            .map(|_| ())
+            .ok_or_else(|| format!("There's no clock named {name}.").into())
+    }
+}
@@ -18,7 +18,7 @@ impl Compile for &Dice {
                 let mut rolls = Vec::new();
                 let mut rng = rand::rng();
                 let between =
-                    Uniform::try_from(1..die + 1).expect("Could not create random distribution.");
+                    Uniform::try_from(1..=die).expect("Could not create random distribution.");
                 for _ in 0..self.count {
                     rolls.push(between.sample(&mut rng));
                 }
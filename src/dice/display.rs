@@ -131,12 +131,11 @@ impl Display for Roll {
                 None => {
                     f.write_fmt(format_args!("{}", roll))?;
                 }
-                Some(die) => {
-                    if *roll == 1 || *roll == die {
-                        f.write_fmt(format_args!("**{}**", roll))?;
-                    } else {
-                        f.write_fmt(format_args!("{}", roll))?;
-                    }
+                Some(die) if *roll == 1 || *roll == die => {
+                    f.write_fmt(format_args!("**{}**", roll))?;
+                }
+                Some(_) => {
+                    f.write_fmt(format_args!("{}", roll))?;
                 }
             }
         }
@@ -1,6 +1,8 @@
 use crate::dice::{Eval, parser::*};
 use std::rc::Rc;
 
+static TOO_LARGE: &str = "The roll is too large to add up.";
+
 pub enum Op {
     Plus,
     Minus,
@@ -53,44 +55,43 @@ pub struct RollNode {
 }
 
 impl Eval for Roll {
-    fn eval(&self) -> u32 {
-        let mut total = 0u32;
-        match self.limit {
-            None => {
-                for i in self.rolls.iter() {
-                    total += i;
-                }
-            }
-            Some(limit) => {
-                for i in self.rolls.iter().take(limit as usize) {
-                    total += i;
-                }
-            }
+    fn eval(&self) -> Result<u32, &'static str> {
+        let taken = match self.limit {
+            None => self.rolls.len(),
+            Some(limit) => limit as usize,
         };
-        total
+        self.rolls
+            .iter()
+            .take(taken)
+            .try_fold(0u32, |total, roll| total.checked_add(*roll))
+            .ok_or(TOO_LARGE)
     }
 }
 
 impl Eval for RollNode {
-    fn eval(&self) -> u32 {
+    fn eval(&self) -> Result<u32, &'static str> {
         let left_eval = match self.left.as_ref() {
-            RollHand::Roll(roll) => roll.eval(),
-            RollHand::RollNode(roll_node) => roll_node.eval(),
+            RollHand::Roll(roll) => roll.eval()?,
+            RollHand::RollNode(roll_node) => roll_node.eval()?,
         };
 
         match self.right.as_ref() {
-            None => return left_eval,
+            None => Ok(left_eval),
             Some((op, right)) => {
                 let right_eval = match right.as_ref() {
-                    RollHand::Roll(roll) => roll.eval(),
-                    RollHand::RollNode(roll_node) => roll_node.eval(),
+                    RollHand::Roll(roll) => roll.eval()?,
+                    RollHand::RollNode(roll_node) => roll_node.eval()?,
                 };
 
                 match op {
-                    Op::Plus => left_eval + right_eval,
-                    Op::Minus => left_eval - right_eval,
-                    Op::Multiply => left_eval * right_eval,
-                    Op::Divide => left_eval / right_eval,
+                    Op::Plus => left_eval.checked_add(right_eval).ok_or(TOO_LARGE),
+                    Op::Minus => left_eval
+                        .checked_sub(right_eval)
+                        .ok_or("The roll went below zero."),
+                    Op::Multiply => left_eval.checked_mul(right_eval).ok_or(TOO_LARGE),
+                    Op::Divide => left_eval
+                        .checked_div(right_eval)
+                        .ok_or("Can't divide by zero."),
                 }
             }
         }
@@ -98,7 +99,7 @@ impl Eval for RollNode {
 }
 
 impl Eval for RollHand {
-    fn eval(&self) -> u32 {
+    fn eval(&self) -> Result<u32, &'static str> {
         match self {
             RollHand::Roll(roll) => roll.eval(),
             RollHand::RollNode(roll_node) => roll_node.eval(),
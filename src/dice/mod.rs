@@ -1,7 +1,9 @@
 pub mod compile;
 pub mod display;
 pub mod eval;
+pub mod modify;
 pub mod parser;
+pub mod stats;
 
 use nom::IResult;
 use parser::{NamedList, TakeAdd};
@@ -17,8 +19,8 @@ pub trait Compile {
 
 /// This trait is implemented by trees that can return a summed up roll.
 pub trait Eval {
-    /// Returns the summed roll of a node.
-    fn eval(&self) -> u32;
+    /// Returns the summed roll of a node, or why it has none, like a division by zero.
+    fn eval(&self) -> Result<u32, &'static str>;
 }
 
 /// This trait is implemented by structs that can parse a version of themselves out from a string.
@@ -30,6 +32,7 @@ pub trait Parse<NodeType = Self> {
 pub struct RollResult {
     pub name: String,
     pub value: String,
+    pub total: u32,
 }
 
 pub fn handle_dice_string(
@@ -43,6 +46,7 @@ pub fn handle_dice_string(
 
     for (idx, item) in list.expressions.iter().enumerate() {
         let compiled_expr = item.expression.as_ref().compile();
+        let total = compiled_expr.eval()?;
         if idx > 0 {
             out += ", ";
         }
@@ -51,13 +55,15 @@ pub fn handle_dice_string(
             Some(name) => {
                 roll_results.push(RollResult {
                     name: name.clone(),
-                    value: format!("{compiled_expr} => {}", compiled_expr.eval()),
+                    value: format!("{compiled_expr} => {total}"),
+                    total,
                 });
             }
             None => {
                 roll_results.push(RollResult {
                     name: format!("Roll {}", idx + 1),
-                    value: format!("{compiled_expr} => {}", compiled_expr.eval()),
+                    value: format!("{compiled_expr} => {total}"),
+                    total,
                 });
             }
         };
@@ -66,6 +72,56 @@ pub fn handle_dice_string(
     Ok(roll_results)
 }
 
+/// Returns the dice strings wrapped in `[[` and `]]` inside a block of text, in order of appearance.
+pub fn find_inline_rolls(text: &str) -> Vec<&str> {
+    let mut inline_rolls = Vec::new();
+    let mut remaining = text;
+
+    while let Some(start) = remaining.find("[[") {
+        remaining = &remaining[start + 2..];
+        match remaining.find("]]") {
+            Some(end) => {
+                let dice_string = remaining[..end].trim();
+                if !dice_string.is_empty() {
+                    inline_rolls.push(dice_string);
+                }
+                remaining = &remaining[end + 2..];
+            }
+            None => break,
+        }
+    }
+
+    inline_rolls
+}
+
+/// Replaces every dice expression in `text`, such as the `2d4` in `2d4 goblins`, with its rolled total.
+/// Plain numbers are left alone.
+pub fn roll_dice_in_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut remaining = text;
+    let mut at_word_start = true;
+
+    while let Some(ch) = remaining.chars().next() {
+        if at_word_start
+            && ch.is_ascii_digit()
+            && let Ok((rest, expression)) = TakeAdd::parse(remaining)
+            && expression.to_string().contains('d')
+            && let Ok(total) = (&expression).compile().eval()
+        {
+            out += &total.to_string();
+            remaining = rest;
+            at_word_start = false;
+            continue;
+        }
+
+        out.push(ch);
+        at_word_start = !ch.is_alphanumeric();
+        remaining = &remaining[ch.len_utf8()..];
+    }
+
+    out
+}
+
 #[allow(dead_code)]
 fn test_roll_performance(
     unnamed_expression: &'static str,
@@ -79,7 +135,7 @@ fn test_roll_performance(
     let start = Instant::now();
 
     for _ in range.clone() {
-        rolls.push(compiled_node.eval());
+        rolls.push(compiled_node.eval()?);
     }
 
     let time_taken_ms = start.elapsed().as_millis();
@@ -89,7 +145,7 @@ fn test_roll_performance(
 #[test]
 fn test_roll_performance_simple() -> Result<(), Box<dyn std::error::Error>> {
     let expression = "4d6 + 3";
-    let (rolls, time_taken_ms) = test_roll_performance(expression, 0..1_00_000_000)?;
+    let (rolls, time_taken_ms) = test_roll_performance(expression, 0..100_000_000)?;
     println!(
         "took {}ms to evaluate {} rolls on {}",
         time_taken_ms,
@@ -102,7 +158,7 @@ fn test_roll_performance_simple() -> Result<(), Box<dyn std::error::Error>> {
 #[test]
 fn test_roll_performance_take_higher() -> Result<(), Box<dyn std::error::Error>> {
     let expression = "3d6h1 + 9 + 2";
-    let (rolls, time_taken_ms) = test_roll_performance(expression, 0..1_00_000_000)?;
+    let (rolls, time_taken_ms) = test_roll_performance(expression, 0..100_000_000)?;
     println!(
         "took {}ms to evaluate {} rolls on {}",
         time_taken_ms,
@@ -111,3 +167,69 @@ fn test_roll_performance_take_higher() -> Result<(), Box<dyn std::error::Error>>
     );
     Ok(())
 }
+
+#[test]
+fn test_find_inline_rolls() {
+    let text = "I swing at the goblin [[hit: 1d20 + 5]] and hope for [[ 1d8+3 ]]. [[]] [[2d6";
+    assert_eq!(find_inline_rolls(text), vec!["hit: 1d20 + 5", "1d8+3"]);
+    assert!(find_inline_rolls("no rolls here").is_empty());
+}
+
+#[test]
+fn test_modify_dice_string() -> Result<(), Box<dyn std::error::Error>> {
+    use modify::{RollModifier, modify_dice_string};
+
+    assert_eq!(
+        modify_dice_string("hit: 1d20 + 5, damage: 1d8", RollModifier::Advantage)?,
+        "hit: 2d20h1 + 5, damage: 1d8"
+    );
+    assert_eq!(
+        modify_dice_string("1d20", RollModifier::Bonus(2))?,
+        "1d20 + 2"
+    );
+    assert_eq!(
+        modify_dice_string("1d20 - 1", RollModifier::Bonus(2))?,
+        "(1d20 - 1) + 2"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_roll_statistics() -> Result<(), Box<dyn std::error::Error>> {
+    let statistics = stats::roll_statistics("check: 2d6, 5", 10_000)?;
+    assert_eq!(statistics.len(), 2);
+    assert_eq!(statistics[0].name, "check");
+    assert!(statistics[0].min >= 2 && statistics[0].max <= 12);
+    assert!((statistics[0].mean - 7f64).abs() < 0.25);
+    assert_eq!(statistics[0].chance_at_least(2), 1f64);
+    assert_eq!((statistics[1].min, statistics[1].max), (5, 5));
+    Ok(())
+}
+
+#[test]
+fn test_roll_dice_in_text() {
+    assert_eq!(
+        roll_dice_in_text("3 wolves and 1d1 + 1 bears"),
+        "3 wolves and 2 bears"
+    );
+    assert_eq!(roll_dice_in_text("2d1 goblins"), "2 goblins");
+    assert_eq!(
+        roll_dice_in_text("room b2d4 is empty"),
+        "room b2d4 is empty"
+    );
+}
+
+#[test]
+fn test_impossible_rolls() {
+    for dice_string in [
+        "1d0",
+        "1d4294967295 - 4294967295 - 1",
+        "1d6 / 0",
+        "1d4 - 10",
+        "5000d6",
+        "4000000000 * 2",
+    ] {
+        assert!(handle_dice_string(dice_string.to_owned()).is_err());
+    }
+    assert_eq!(roll_dice_in_text("2d4 - 9 goblins"), "2d4 - 9 goblins");
+}
@@ -0,0 +1,137 @@
+use crate::dice::{Parse, parser::*};
+use std::rc::Rc;
+
+/// A change applied to a dice string before it is rolled again.
+#[derive(Debug, Clone, Copy)]
+pub enum RollModifier {
+    /// Every lone, unfiltered d20 is rolled twice and the higher die is kept.
+    Advantage,
+    /// A flat bonus is added to every expression in the list.
+    Bonus(u32),
+}
+
+impl Take {
+    fn with_advantage(&self) -> Take {
+        match (self.dice.count, self.dice.die, &self.filter) {
+            (1, Some(20), None) => Take {
+                dice: Rc::new(Dice {
+                    count: 2,
+                    die: Some(20),
+                }),
+                filter: Some((1, FilterType::Higher)),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+impl TakeRecursive {
+    fn with_advantage(&self) -> TakeRecursive {
+        match self {
+            TakeRecursive::Take(take) => TakeRecursive::Take(take.with_advantage()),
+            TakeRecursive::TakeAdd(take_add) => TakeRecursive::TakeAdd(take_add.with_advantage()),
+        }
+    }
+}
+
+impl TakeFactorRight {
+    fn with_advantage(&self) -> TakeFactorRight {
+        match self {
+            TakeFactorRight::Take(take) => TakeFactorRight::Take(take.with_advantage()),
+            TakeFactorRight::TakeFactor(take_factor) => {
+                TakeFactorRight::TakeFactor(take_factor.with_advantage())
+            }
+        }
+    }
+}
+
+impl TakeFactor {
+    fn with_advantage(&self) -> TakeFactor {
+        TakeFactor {
+            left: Rc::new(self.left.with_advantage()),
+            right: self
+                .right
+                .as_ref()
+                .map(|(op, right)| (op.clone(), Rc::new(right.with_advantage()))),
+        }
+    }
+}
+
+impl TakeAddRight {
+    fn with_advantage(&self) -> TakeAddRight {
+        match self {
+            TakeAddRight::TakeFactor(take_factor) => {
+                TakeAddRight::TakeFactor(take_factor.with_advantage())
+            }
+            TakeAddRight::TakeAdd(take_add) => TakeAddRight::TakeAdd(take_add.with_advantage()),
+        }
+    }
+}
+
+impl TakeAdd {
+    fn with_advantage(&self) -> TakeAdd {
+        TakeAdd {
+            left: Rc::new(self.left.with_advantage()),
+            right: self
+                .right
+                .as_ref()
+                .map(|(op, right)| (op.clone(), Rc::new(right.with_advantage()))),
+        }
+    }
+
+    fn with_bonus(&self, bonus: u32) -> TakeAdd {
+        let constant = |count: u32| TakeFactor {
+            left: Rc::new(TakeRecursive::Take(Take {
+                dice: Rc::new(Dice { count, die: None }),
+                filter: None,
+            })),
+            right: None,
+        };
+
+        // the grammar is right-recursive, so anything with an operator is wrapped in brackets
+        // to keep `1d20 - 1` from turning into `1d20 - (1 + bonus)`.
+        let left = match self.right {
+            None => self.left.clone(),
+            Some(_) => Rc::new(TakeFactor {
+                left: Rc::new(TakeRecursive::TakeAdd(self.clone())),
+                right: None,
+            }),
+        };
+
+        TakeAdd {
+            left,
+            right: Some((
+                OpAdd::Plus,
+                Rc::new(TakeAddRight::TakeFactor(constant(bonus))),
+            )),
+        }
+    }
+}
+
+impl NamedList {
+    /// Returns a copy of this list with `modifier` applied to every expression.
+    pub fn modify(&self, modifier: RollModifier) -> NamedList {
+        NamedList {
+            expressions: self
+                .expressions
+                .iter()
+                .map(|named_expr| NamedTakeAdd {
+                    name: named_expr.name.clone(),
+                    expression: Rc::new(match modifier {
+                        RollModifier::Advantage => named_expr.expression.with_advantage(),
+                        RollModifier::Bonus(bonus) => named_expr.expression.with_bonus(bonus),
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses `dice_string`, applies `modifier` and returns the rewritten dice string.
+pub fn modify_dice_string(
+    dice_string: &str,
+    modifier: RollModifier,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (_remaining, list) = NamedList::parse(dice_string).map_err(|err| err.to_string())?;
+    Ok(list.modify(modifier).to_string())
+}
@@ -7,7 +7,7 @@
 //! TakeFactor := TakeRecursive (_ [+ | -] _ TakeFactor | TakeRecursive)
 //! TakeRecursive := Take | _ '(' _ TakeAdd _ ')'
 //! Take := Dice ([hHlL]\d+)?
-//! Dice := [\d+] 'd' [\d+]
+//! Dice := [\d+] 'd' [\d+]    (at most MAX_DICE dice, with at least one side)
 //! _ := [ \n\r]*
 
 use nom::{
@@ -16,79 +16,83 @@ use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, char, digit1, space0, space1},
     combinator::{map_res, opt},
+    error::ErrorKind,
     multi::many0,
 };
 
 use crate::dice::Parse;
 use std::rc::Rc;
 
-#[derive(Debug)]
+/// Most dice rolled by a single `Dice`, so a typo can't roll billions of them.
+const MAX_DICE: u32 = 1000;
+
+#[derive(Debug, Clone)]
 pub enum OpAdd {
     Plus,
     Minus,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum OpFactor {
     Multiply,
     Divide,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Dice {
     pub count: u32,
     pub die: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum FilterType {
     Higher,
     Lower,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Take {
     pub dice: Rc<Dice>,
     pub filter: Option<(u32, FilterType)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TakeRecursive {
     Take(Take),
     TakeAdd(TakeAdd),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TakeFactorRight {
     TakeFactor(TakeFactor),
     Take(TakeRecursive),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TakeFactor {
     pub left: Rc<TakeRecursive>,
     pub right: Option<(OpFactor, Rc<TakeFactorRight>)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TakeAddRight {
     TakeFactor(TakeFactor),
     TakeAdd(TakeAdd),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TakeAdd {
     pub left: Rc<TakeFactor>,
     pub right: Option<(OpAdd, Rc<TakeAddRight>)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NamedTakeAdd {
     pub name: Option<String>,
     pub expression: Rc<TakeAdd>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NamedList {
     pub expressions: Vec<NamedTakeAdd>,
 }
@@ -121,6 +125,13 @@ impl Parse for Dice {
 
         let (input, optional_die): (&str, Option<(char, u32)>) =
             opt((char('d'), map_res(digit1, str::parse))).parse(input)?;
+        // a failure, rather than an error, so the `d` isn't left over for whatever parses next.
+        if optional_die.is_some_and(|(_, die)| die == 0 || count > MAX_DICE) {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                ErrorKind::Verify,
+            )));
+        }
 
         Ok((
             input,
@@ -165,7 +176,7 @@ impl Parse for TakeRecursive {
         alt((
             (space0, char('('), space0, TakeAdd::parse, space0, char(')'))
                 .map(|(_, _, _, take_add, _, _)| TakeRecursive::TakeAdd(take_add)),
-            Take::parse.map(|take| TakeRecursive::Take(take)),
+            Take::parse.map(TakeRecursive::Take),
         ))
         .parse(input)
     }
@@ -179,8 +190,8 @@ impl Parse for TakeFactor {
             OpFactor::parse,
             space0,
             alt((
-                TakeRecursive::parse.map(|take_recursive| TakeFactorRight::Take(take_recursive)),
-                TakeFactor::parse.map(|take_factor| TakeFactorRight::TakeFactor(take_factor)),
+                TakeRecursive::parse.map(TakeFactorRight::Take),
+                TakeFactor::parse.map(TakeFactorRight::TakeFactor),
             )),
         ))
         .parse(input)?;
@@ -203,8 +214,8 @@ impl Parse for TakeAdd {
             OpAdd::parse,
             space0,
             alt((
-                TakeAdd::parse.map(|take_add| TakeAddRight::TakeAdd(take_add)),
-                TakeFactor::parse.map(|take_factor| TakeAddRight::TakeFactor(take_factor)),
+                TakeAdd::parse.map(TakeAddRight::TakeAdd),
+                TakeFactor::parse.map(TakeAddRight::TakeFactor),
             )),
         ))
         .parse(input)?;
@@ -0,0 +1,93 @@
+use crate::dice::{Compile, Eval, Parse, parser::NamedList};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// Distributions with more distinct outcomes than this are summarised without a histogram.
+const MAX_HISTOGRAM_ROWS: usize = 40;
+/// Width of the longest bar in a histogram.
+const HISTOGRAM_WIDTH: f64 = 40f64;
+
+/// Estimated outcome distribution of a single dice expression.
+pub struct RollStatistics {
+    pub name: String,
+    pub samples: usize,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// how often each total came up.
+    pub distribution: BTreeMap<u32, usize>,
+}
+
+impl RollStatistics {
+    /// Returns the estimated chance of rolling at least `target`.
+    pub fn chance_at_least(&self, target: u32) -> f64 {
+        let hits: usize = self.distribution.range(target..).map(|(_, n)| n).sum();
+        hits as f64 / self.samples as f64
+    }
+}
+
+/// Estimates the distribution of every expression in `dice_string` by rolling it `samples` times.
+pub fn roll_statistics(
+    dice_string: &str,
+    samples: usize,
+) -> Result<Vec<RollStatistics>, Box<dyn std::error::Error>> {
+    let (_remaining, list) = NamedList::parse(dice_string).map_err(|err| err.to_string())?;
+
+    let mut statistics = Vec::new();
+    for (idx, item) in list.expressions.iter().enumerate() {
+        let mut distribution: BTreeMap<u32, usize> = BTreeMap::new();
+        let mut sum = 0f64;
+        let mut sum_squares = 0f64;
+
+        for _ in 0..samples {
+            let total = item.expression.as_ref().compile().eval()?;
+            *distribution.entry(total).or_default() += 1;
+            sum += f64::from(total);
+            sum_squares += f64::from(total) * f64::from(total);
+        }
+
+        let mean = sum / samples as f64;
+        statistics.push(RollStatistics {
+            name: item.name.clone().unwrap_or(format!("Roll {}", idx + 1)),
+            samples,
+            min: distribution.keys().next().copied().unwrap_or_default(),
+            max: distribution.keys().last().copied().unwrap_or_default(),
+            mean,
+            std_dev: (sum_squares / samples as f64 - mean * mean)
+                .max(0f64)
+                .sqrt(),
+            distribution,
+        });
+    }
+
+    Ok(statistics)
+}
+
+impl Display for RollStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: min {}, max {}, mean {:.2}, std dev {:.2} ({} samples)",
+            self.name, self.min, self.max, self.mean, self.std_dev, self.samples
+        )?;
+
+        if self.distribution.len() > MAX_HISTOGRAM_ROWS {
+            return Ok(());
+        }
+
+        let most_common = self.distribution.values().max().copied().unwrap_or(1) as f64;
+        for (total, count) in self.distribution.iter() {
+            let bar_length = (*count as f64 / most_common * HISTOGRAM_WIDTH).round() as usize;
+            writeln!(
+                f,
+                "{:>6} | {:>6.2}% | {:>6.2}% or more | {}",
+                total,
+                *count as f64 / self.samples as f64 * 100f64,
+                self.chance_at_least(*total) * 100f64,
+                "#".repeat(bar_length)
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, CreateAllowedMentions, CreateMessage, Mentionable, RoleId,
+};
+
+use crate::{
+    commands::{
+        Data, EMBED_FIELD_LIMIT, Error, create_quick_success_embed, initiative, inline_rolls,
+        pins::refresh_pinned_messages, roll_buttons, shorten,
+    },
+    db::{ClockAccess, ClockCompletion, DbPool},
+};
+
+/// How often expired clocks are looked for and scheduled ticks made.
+const CLOCK_SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Handles gateway events that aren't slash command invocations.
+pub async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    _framework: poise::FrameworkContext<'_, Data, Error>,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        serenity::FullEvent::Message { new_message } => {
+            inline_rolls::handle_message(ctx, new_message, data).await?;
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(component),
+        } if component
+            .data
+            .custom_id
+            .starts_with(roll_buttons::ROLL_BUTTON_PREFIX) =>
+        {
+            roll_buttons::handle_interaction(ctx, component, data).await?;
+        }
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(component),
+        } if component
+            .data
+            .custom_id
+            .starts_with(initiative::INITIATIVE_BUTTON_PREFIX) =>
+        {
+            initiative::handle_interaction(ctx, component, data).await?;
+        }
+        serenity::FullEvent::GuildCreate { guild, .. } => {
+            let legacy_name = guild.name.clone();
+            let namespace = format!("guild:{}", guild.id);
+            // a server the bot joined since can't have had clocks under its name.
+            let owner_since = guild.joined_at.unix_timestamp();
+            let checked_name = legacy_name.clone();
+            if !data
+                .db
+                .read(move |db| db.is_legacy_namespace(&checked_name, owner_since))
+                .await?
+            {
+                return Ok(());
+            }
+            let moved = data
+                .db
+                .write(move |db| db.migrate_legacy_namespace(&legacy_name, &namespace, owner_since))
+                .await?;
+            if moved > 0 {
+                println!(
+                    "moved {moved} items from {} to guild:{}",
+                    guild.name, guild.id
+                );
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Deletes expired clocks, and removed clocks past their grace period.
+/// Expired clocks that asked for it get their expiry announced in their channel, and their pinned messages are updated.
+pub async fn purge_clocks(http: &serenity::Http, db: &DbPool) -> Result<(), Error> {
+    let expired = db
+        .write(|db| {
+            db.purge_removed_clocks()?;
+            db.purge_expired_clocks()
+        })
+        .await?;
+
+    for clock in expired {
+        refresh_pinned_messages(http, db, &clock.namespace, Some(&clock.name)).await;
+
+        let Some(channel_id) = clock.announce_channel_id else {
+            continue;
+        };
+        let message = CreateMessage::new().embed(create_quick_success_embed(
+            "clock expired",
+            &format!("{} has expired.", clock.name),
+        ));
+        // the channel may have been deleted, or the bot locked out of it.
+        if let Err(e) = ChannelId::new(channel_id).send_message(http, message).await {
+            println!("{}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Ticks the clocks whose scheduled ticks are due, and updates their pinned messages.
+pub async fn tick_scheduled_clocks(http: &serenity::Http, db: &DbPool) -> Result<(), Error> {
+    let ticked = db.write(|db| db.run_scheduled_ticks()).await?;
+    for clock in ticked {
+        refresh_pinned_messages(http, db, &clock.namespace, Some(&clock.name)).await;
+    }
+    Ok(())
+}
+
+/// Every `CLOCK_SCHEDULER_INTERVAL` for as long as the bot runs, purges clocks, makes scheduled ticks, and acts
+/// on the clocks those ticks fill. Ticks are saved in the database, so any missed while the bot was down are
+/// made on its first run. Old `/roll` replies are forgotten on the way.
+pub async fn run_clock_scheduler(ctx: serenity::Context, db: DbPool, music_dir: PathBuf) {
+    let mut interval = tokio::time::interval(CLOCK_SCHEDULER_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_clocks(&ctx.http, &db).await {
+            println!("{}", e);
+        }
+        if let Err(e) = db.write(|db| db.purge_roll_messages()).await {
+            println!("{}", e);
+        }
+        if let Err(e) = tick_scheduled_clocks(&ctx.http, &db).await {
+            println!("{}", e);
+        }
+        if let Err(e) = fire_clock_completions(&ctx, &db, &music_dir, None).await {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Acts on the clocks that filled up since this was last called: posts their messages, pings their roles,
+/// and plays their sounds. Their linked and parent clocks have already been bumped, so only their pinned
+/// messages are updated.
+/// `here` is the namespace and channel of a command that just changed a clock. Only its completions are taken,
+/// and they're announced in the channel their trigger was set in, or else in that channel.
+/// Without `here`, only completions with a channel of their own are taken. The rest wait for a command.
+/// GM-only clocks fill up without a word, and are left out of other clocks' announcements.
+pub async fn fire_clock_completions(
+    ctx: &serenity::Context,
+    db: &DbPool,
+    music_dir: &Path,
+    here: Option<(&str, ChannelId)>,
+) -> Result<(), Error> {
+    let namespace = here.map(|(namespace, _)| namespace.to_owned());
+    let (completions, accesses) = db
+        .write(move |db| {
+            let completions = db.take_clock_completions(namespace.as_deref())?;
+            let mut accesses = HashMap::new();
+            for completion in &completions {
+                if !accesses.contains_key(&completion.namespace) {
+                    let namespace_accesses = db.get_clock_accesses(&completion.namespace)?;
+                    accesses.insert(completion.namespace.clone(), namespace_accesses);
+                }
+            }
+            Ok((completions, accesses))
+        })
+        .await?;
+
+    for completion in completions {
+        for bumped in [&completion.linked, &completion.parent]
+            .into_iter()
+            .flatten()
+        {
+            refresh_pinned_messages(&ctx.http, db, &bumped.namespace, Some(&bumped.name)).await;
+        }
+
+        let accesses = &accesses[&completion.namespace];
+        if is_gm_only(accesses, &completion.name) {
+            continue;
+        }
+        let Some(channel_id) = completion
+            .trigger
+            .channel_id
+            .map(ChannelId::new)
+            .or(here.map(|(_, channel_id)| channel_id))
+        else {
+            continue;
+        };
+        // the channel may have been deleted, or the bot locked out of it.
+        if let Err(e) = announce_completion(ctx, channel_id, &completion, accesses).await {
+            println!("{}", e);
+        }
+        if let Some(sound) = &completion.trigger.sound
+            && let Err(e) = play_completion_sound(ctx, music_dir, channel_id, sound).await
+        {
+            println!("{}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_gm_only(accesses: &HashMap<String, ClockAccess>, name: &str) -> bool {
+    accesses.get(name) == Some(&ClockAccess::GmOnly)
+}
+
+async fn announce_completion(
+    ctx: &serenity::Context,
+    channel_id: ChannelId,
+    completion: &ClockCompletion,
+    accesses: &HashMap<String, ClockAccess>,
+) -> Result<(), Error> {
+    let trigger = &completion.trigger;
+    let mut description = trigger
+        .message
+        .clone()
+        .unwrap_or_else(|| format!("{} is complete.", completion.name));
+    match (&trigger.linked_clock, &completion.linked) {
+        (Some(linked_clock), _) if is_gm_only(accesses, linked_clock) => {}
+        (Some(_), Some(linked)) => description.push_str(&format!(
+            "\n{} is now at {}/{}.",
+            linked.name, linked.segments_filled, linked.segments
+        )),
+        (Some(linked_clock), None) => description.push_str(&format!(
+            "\nThere's no clock named {linked_clock} to advance."
+        )),
+        (None, _) => {}
+    }
+    if let Some(parent) = &completion.parent
+        && !is_gm_only(accesses, &parent.name)
+    {
+        description.push_str(&format!(
+            "\n{} ticked {}, which is now at {}/{}.",
+            completion.name, parent.name, parent.segments_filled, parent.segments
+        ));
+    }
+    match &completion.rival {
+        Some(rival) if is_gm_only(accesses, &rival.name) => {}
+        Some(rival) if rival.segments_filled < rival.segments => description.push_str(&format!(
+            "\n{} won the race against {}.",
+            completion.name, rival.name
+        )),
+        Some(rival) => description.push_str(&format!("\n{} filled up first.", rival.name)),
+        None => {}
+    }
+
+    let mut message = CreateMessage::new().embed(create_quick_success_embed(
+        &format!("{} filled up", completion.name),
+        &shorten(&description, EMBED_FIELD_LIMIT),
+    ));
+    if let Some(role_id) = trigger.role_id.map(RoleId::new) {
+        message = message
+            .content(role_id.mention().to_string())
+            .allowed_mentions(CreateAllowedMentions::new().roles(vec![role_id]));
+    }
+    channel_id.send_message(&ctx.http, message).await?;
+    Ok(())
+}
+
+/// Returns the path of the sound file called `sound`, as long as it's inside the music directory.
+pub fn find_sound(music_dir: &Path, sound: &str) -> Option<PathBuf> {
+    let music_dir = music_dir.canonicalize().ok()?;
+    let path = music_dir.join(sound).canonicalize().ok()?;
+    (path.starts_with(&music_dir) && path.is_file()).then_some(path)
+}
+
+/// Plays `sound` from the music directory over whatever's playing, if the bot is in a voice channel
+/// in the same server as `channel_id`.
+async fn play_completion_sound(
+    ctx: &serenity::Context,
+    music_dir: &Path,
+    channel_id: ChannelId,
+    sound: &str,
+) -> Result<(), Error> {
+    let Some(guild_id) = channel_id
+        .to_channel(ctx)
+        .await?
+        .guild()
+        .map(|channel| channel.guild_id)
+    else {
+        return Ok(());
+    };
+    let Some(manager) = songbird::get(ctx).await else {
+        return Ok(());
+    };
+    let Some(path) = find_sound(music_dir, sound) else {
+        return Err(format!("There's no sound called {sound}.").into());
+    };
+    if let Some(call) = manager.get(guild_id) {
+        let input = songbird::input::File::new(path);
+        call.lock().await.play_input(input.into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_find_sound() -> Result<(), Error> {
+    let root = std::env::temp_dir().join(format!("troller-sounds-{}", std::process::id()));
+    let music_dir = root.join("music");
+    std::fs::create_dir_all(&music_dir)?;
+    std::fs::write(music_dir.join("rumble.mp3"), b"")?;
+    std::fs::write(root.join("secret.txt"), b"")?;
+
+    assert!(find_sound(&music_dir, "rumble.mp3").is_some());
+    assert!(find_sound(&music_dir, "../secret.txt").is_none());
+    assert!(find_sound(&music_dir, root.join("secret.txt").to_str().unwrap()).is_none());
+    assert!(find_sound(&music_dir, "missing.mp3").is_none());
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
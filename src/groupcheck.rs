@@ -0,0 +1,173 @@
+//! Rules for combining everyone's rolls in a group check.
+//!
+//! Every participant rolls the same expression, and a roll succeeds if its total meets the DC.
+//! Once everyone has rolled, or time runs out, the check's rule decides the group's outcome.
+//! Participants who never rolled count as failing.
+
+/// How the participants' rolls combine into the group's outcome.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum GroupRule {
+    /// The group succeeds if at least half of the participants meet the DC.
+    #[name = "Half succeed"]
+    HalfSucceed,
+    /// The group uses its best roll.
+    #[name = "Best of"]
+    BestOf,
+    /// The group adds all of its rolls together.
+    #[name = "Sum"]
+    Sum,
+}
+
+/// A participant's roll, once they have made it.
+pub struct GroupRoll {
+    pub user_id: u64,
+    pub total: Option<u32>,
+    pub value: Option<String>,
+}
+
+pub struct GroupCheck {
+    pub expression: String,
+    pub dc: Option<u32>,
+    pub rule: GroupRule,
+    pub rolls: Vec<GroupRoll>,
+}
+
+/// The result of a closed group check. `success` is only known when the check has a DC.
+pub struct GroupOutcome {
+    pub success: Option<bool>,
+    pub summary: String,
+}
+
+impl GroupCheck {
+    pub fn new(
+        expression: String,
+        dc: Option<u32>,
+        rule: GroupRule,
+        mut participants: Vec<u64>,
+    ) -> Result<Self, String> {
+        if rule == GroupRule::HalfSucceed && dc.is_none() {
+            return Err(String::from("A half succeed check needs a DC."));
+        }
+
+        participants.sort();
+        participants.dedup();
+        if participants.is_empty() {
+            return Err(String::from("Nobody was asked to roll."));
+        }
+
+        Ok(GroupCheck {
+            expression,
+            dc,
+            rule,
+            rolls: participants
+                .into_iter()
+                .map(|user_id| GroupRoll {
+                    user_id,
+                    total: None,
+                    value: None,
+                })
+                .collect(),
+        })
+    }
+
+    /// Records a participant's roll. Each participant may only roll once.
+    pub fn record(&mut self, user_id: u64, total: u32, value: String) -> Result<(), String> {
+        let roll = self
+            .rolls
+            .iter_mut()
+            .find(|roll| roll.user_id == user_id)
+            .ok_or(String::from("You're not part of this group check."))?;
+
+        if roll.total.is_some() {
+            return Err(String::from("You've already rolled for this group check."));
+        }
+
+        roll.total = Some(total);
+        roll.value = Some(value);
+        Ok(())
+    }
+
+    /// Returns whether a roll meets the DC, if the check has one.
+    pub fn meets_dc(&self, total: u32) -> Option<bool> {
+        self.dc.map(|dc| total >= dc)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.rolls.iter().all(|roll| roll.total.is_some())
+    }
+
+    /// Applies the check's rule to the rolls made so far.
+    pub fn outcome(&self) -> GroupOutcome {
+        let totals: Vec<u32> = self.rolls.iter().filter_map(|roll| roll.total).collect();
+
+        match self.rule {
+            GroupRule::HalfSucceed => {
+                let successes = totals
+                    .iter()
+                    .filter(|total| self.meets_dc(**total) == Some(true))
+                    .count();
+                GroupOutcome {
+                    success: Some(successes * 2 >= self.rolls.len()),
+                    summary: format!(
+                        "{successes} of {} met DC {}.",
+                        self.rolls.len(),
+                        self.dc.unwrap_or_default()
+                    ),
+                }
+            }
+            GroupRule::BestOf => match totals.iter().max() {
+                Some(best) => GroupOutcome {
+                    success: self.meets_dc(*best),
+                    summary: format!("The best roll was {best}."),
+                },
+                None => GroupOutcome {
+                    success: self.dc.map(|_| false),
+                    summary: String::from("Nobody rolled."),
+                },
+            },
+            GroupRule::Sum => {
+                let sum: u32 = totals.iter().sum();
+                GroupOutcome {
+                    success: self.meets_dc(sum),
+                    summary: format!("The rolls add up to {sum}."),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_group_check_rules() -> Result<(), String> {
+    assert!(GroupCheck::new(String::from("1d20"), None, GroupRule::HalfSucceed, vec![1]).is_err());
+    assert!(GroupCheck::new(String::from("1d20"), None, GroupRule::Sum, vec![]).is_err());
+
+    let mut check = GroupCheck::new(
+        String::from("1d20"),
+        Some(10),
+        GroupRule::HalfSucceed,
+        vec![1, 2, 3, 2],
+    )?;
+    assert_eq!(check.rolls.len(), 3);
+
+    check.record(1, 15, String::from("15"))?;
+    assert!(check.record(1, 3, String::from("3")).is_err());
+    assert!(check.record(4, 3, String::from("3")).is_err());
+    assert!(!check.is_complete());
+    // the two participants who haven't rolled count as failing.
+    assert_eq!(check.outcome().success, Some(false));
+
+    check.record(2, 10, String::from("10"))?;
+    check.record(3, 2, String::from("2"))?;
+    assert!(check.is_complete());
+    assert_eq!(check.outcome().success, Some(true));
+
+    check.rule = GroupRule::BestOf;
+    assert_eq!(check.outcome().summary, "The best roll was 15.");
+
+    check.rule = GroupRule::Sum;
+    check.dc = Some(30);
+    assert_eq!(check.outcome().success, Some(false));
+    check.dc = None;
+    assert_eq!(check.outcome().success, None);
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! Turn order rules for initiative trackers.
+//!
+//! Combatants act from the highest initiative to the lowest. Ties go to players before NPCs,
+//! and then to the higher tiebreaker rolled when the combatant joined.
+
+use crate::db::{Combatant, InitiativeTracker};
+
+impl Combatant {
+    /// Combatants with a greater key act earlier.
+    fn sort_key(&self) -> (u32, bool, u32) {
+        (self.initiative, !self.npc, self.tiebreaker)
+    }
+}
+
+impl InitiativeTracker {
+    pub fn new(channel_id: u64) -> Self {
+        InitiativeTracker {
+            channel_id,
+            round: 1,
+            turn: 0,
+            message_id: None,
+            combatants: vec![],
+        }
+    }
+
+    /// Returns the combatant whose turn it is.
+    pub fn current(&self) -> Option<&Combatant> {
+        self.combatants.get(self.turn)
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.combatants
+            .iter()
+            .position(|combatant| combatant.name == name)
+            .ok_or(format!("{name} is not in the initiative order."))
+    }
+
+    fn has_active_combatants(&self) -> bool {
+        self.combatants.iter().any(|combatant| !combatant.delayed)
+    }
+
+    /// Inserts a combatant into the turn order without changing whose turn it is.
+    /// Before the first turn has passed, the highest initiative always goes first.
+    pub fn add(&mut self, combatant: Combatant) -> Result<(), String> {
+        if self.position(&combatant.name).is_ok() {
+            return Err(format!(
+                "{} is already in the initiative order.",
+                combatant.name
+            ));
+        }
+
+        let idx = self
+            .combatants
+            .iter()
+            .position(|other| other.sort_key() < combatant.sort_key())
+            .unwrap_or(self.combatants.len());
+
+        if idx <= self.turn && (self.round, self.turn) != (1, 0) {
+            self.turn += 1;
+        }
+        self.combatants.insert(idx, combatant);
+        Ok(())
+    }
+
+    /// Moves to the next combatant that isn't delayed, starting a new round after the last one.
+    pub fn next(&mut self) {
+        if !self.has_active_combatants() {
+            return;
+        }
+
+        loop {
+            self.turn += 1;
+            if self.turn >= self.combatants.len() {
+                self.turn = 0;
+                self.round += 1;
+            }
+            if !self.combatants[self.turn].delayed {
+                break;
+            }
+        }
+    }
+
+    /// Moves back to the previous combatant that isn't delayed.
+    /// Does nothing on the first turn of the first round.
+    pub fn previous(&mut self) {
+        if !self.has_active_combatants() {
+            return;
+        }
+
+        let (round, turn) = (self.round, self.turn);
+        loop {
+            if self.turn == 0 {
+                if self.round == 1 {
+                    (self.round, self.turn) = (round, turn);
+                    return;
+                }
+                self.turn = self.combatants.len() - 1;
+                self.round -= 1;
+            } else {
+                self.turn -= 1;
+            }
+            if !self.combatants[self.turn].delayed {
+                break;
+            }
+        }
+    }
+
+    /// Takes a combatant out of the turn order until they resume.
+    pub fn delay(&mut self, name: &str) -> Result<(), String> {
+        let idx = self.position(name)?;
+        if self.combatants[idx].delayed {
+            return Err(format!("{name} is already delaying."));
+        }
+
+        self.combatants[idx].delayed = true;
+        if idx == self.turn {
+            self.next();
+        }
+        Ok(())
+    }
+
+    /// Brings a delayed combatant back in. They act right away, ahead of the current combatant.
+    pub fn resume(&mut self, name: &str) -> Result<(), String> {
+        let idx = self.position(name)?;
+        if !self.combatants[idx].delayed {
+            return Err(format!("{name} is not delaying."));
+        }
+
+        let mut combatant = self.combatants.remove(idx);
+        combatant.delayed = false;
+        if idx < self.turn {
+            self.turn -= 1;
+        }
+        // a delayed combatant might have been the only one left, leaving `turn` past the end.
+        self.turn = self.turn.min(self.combatants.len());
+        self.combatants.insert(self.turn, combatant);
+        Ok(())
+    }
+
+    /// Removes a combatant. If it was their turn, the next combatant goes.
+    pub fn remove(&mut self, name: &str) -> Result<Combatant, String> {
+        let idx = self.position(name)?;
+        let combatant = self.combatants.remove(idx);
+
+        if idx < self.turn {
+            self.turn -= 1;
+        } else if idx == self.turn && !self.combatants.is_empty() {
+            if self.turn >= self.combatants.len() {
+                self.turn = 0;
+                self.round += 1;
+            }
+            if self.combatants[self.turn].delayed {
+                self.next();
+            }
+        }
+        if self.combatants.is_empty() {
+            self.turn = 0;
+        }
+        Ok(combatant)
+    }
+}
+
+#[allow(dead_code)]
+fn test_combatant(name: &str, initiative: u32, npc: bool) -> Combatant {
+    Combatant {
+        name: name.to_owned(),
+        initiative,
+        tiebreaker: 0,
+        npc,
+        delayed: false,
+    }
+}
+
+#[allow(dead_code)]
+fn turn_order(tracker: &InitiativeTracker) -> Vec<&str> {
+    tracker
+        .combatants
+        .iter()
+        .map(|combatant| combatant.name.as_str())
+        .collect()
+}
+
+#[test]
+fn test_initiative_order_and_rounds() -> Result<(), String> {
+    let mut tracker = InitiativeTracker::new(0);
+    tracker.add(test_combatant("goblin", 12, true))?;
+    tracker.add(test_combatant("fighter", 12, false))?;
+    tracker.add(test_combatant("wizard", 18, false))?;
+    assert_eq!(turn_order(&tracker), vec!["wizard", "fighter", "goblin"]);
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("wizard"));
+    assert!(tracker.add(test_combatant("wizard", 3, false)).is_err());
+
+    tracker.previous();
+    assert_eq!((tracker.round, tracker.turn), (1, 0));
+
+    tracker.next();
+    tracker.next();
+    tracker.next();
+    assert_eq!((tracker.round, tracker.turn), (2, 0));
+
+    tracker.previous();
+    assert_eq!((tracker.round, tracker.turn), (1, 2));
+
+    // joining mid-round keeps the current combatant's turn.
+    tracker.add(test_combatant("rogue", 20, false))?;
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("goblin"));
+    Ok(())
+}
+
+#[test]
+fn test_initiative_delay_resume_remove() -> Result<(), String> {
+    let mut tracker = InitiativeTracker::new(0);
+    tracker.add(test_combatant("wizard", 18, false))?;
+    tracker.add(test_combatant("fighter", 12, false))?;
+    tracker.add(test_combatant("goblin", 8, true))?;
+
+    tracker.delay("wizard")?;
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("fighter"));
+    tracker.next();
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("goblin"));
+
+    tracker.resume("wizard")?;
+    assert_eq!(turn_order(&tracker), vec!["fighter", "wizard", "goblin"]);
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("wizard"));
+
+    tracker.remove("wizard")?;
+    assert_eq!(tracker.current().map(|c| c.name.as_str()), Some("goblin"));
+    tracker.remove("goblin")?;
+    assert_eq!((tracker.round, tracker.turn), (2, 0));
+    assert!(tracker.remove("goblin").is_err());
+    Ok(())
+}
@@ -1,76 +1,39 @@
+mod cli;
+mod clock;
 mod commands;
 mod db;
 mod dice;
+mod events;
+mod groupcheck;
+mod initiative;
 mod svg;
+mod tables;
 
-use crate::db::ProgressClock;
-use crate::{db::DB, dice::handle_dice_string, svg::render_progress_clock};
+use crate::cli::Cli;
+use crate::db::DbPool;
+use clap::Parser;
 use commands::*;
-use futures::lock::Mutex;
-use poise::serenity_prelude::futures::{self};
 use poise::serenity_prelude::{self as serenity};
 use songbird::SerenityInit;
-use std::env::args;
-use std::sync::Arc;
 
-#[allow(unused)]
-fn cli() {
-    let mut arg_iter = args().skip(1);
-    match arg_iter.next() {
-        Some(command_name) => {
-            match command_name.as_str() {
-                "roll" => {
-                    let dice_string: String =
-                        arg_iter.next().expect("USAGE: troller roll [dice_string]");
-
-                    match handle_dice_string(dice_string) {
-                        Err(err) => println!("{err}"),
-                        Ok(_) => {}
-                    };
-                }
-                "clock" => {
-                    static HELP_STRING: &str =
-                        "USAGE: troller clock segments:[1-255] segments-filled:[1-255]";
-                    let segments: u8 = arg_iter
-                        .next()
-                        .expect(HELP_STRING)
-                        .parse()
-                        .expect(HELP_STRING);
-                    let segments_filled: u8 = arg_iter
-                        .next()
-                        .expect(HELP_STRING)
-                        .parse()
-                        .expect("HELP_STRING");
-                    render_progress_clock(&ProgressClock {
-                        namespace: "".to_owned(),
-                        name: "".to_owned(),
-                        segments,
-                        segments_filled,
-                        ephemeral: false,
-                        color: None,
-                    })
-                    .expect("Could not render progress clock.");
-                    println!("wrote progress clock to out.png.");
-                }
-                _ => {
-                    println!("Invalid Command.");
-                }
-            };
-        }
-        None => {
-            println!("USAGE: troller [roll | clock]");
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        if let Err(err) = cli::run(command, &cli.namespace, &cli.database) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
         }
+        return;
     }
-}
 
-#[tokio::main]
-async fn main() {
     let token =
         std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN environment variable.");
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::GUILD_VOICE_STATES;
+    let intents = serenity::GatewayIntents::non_privileged()
+        | serenity::GatewayIntents::GUILD_VOICE_STATES
+        | serenity::GatewayIntents::MESSAGE_CONTENT;
 
-    let database = Mutex::new(DB::new().expect("Could not initialize database."));
+    let database = DbPool::open(cli.database).expect("Could not initialize database.");
 
     let music_dir = std::env::var_os("MUSIC_DIR")
         .expect("no MUSIC_DIR variable set in the environment.")
@@ -89,18 +52,35 @@ async fn main() {
                 remove_progress_clock(),
                 bump_progress_clock(),
                 help(),
+                inline_rolls::inline_rolls(),
+                commands::clock::clock(),
+                commands::clocks::clocks(),
+                commands::gm_role::gm_role(),
+                commands::groupcheck::groupcheck(),
+                commands::initiative::initiative(),
+                commands::scope::scope(),
+                commands::tables::table(),
+                commands::templates::clock_template(),
                 play_music::music(),
                 play_music::leave(),
             ],
+            pre_command: |ctx| Box::pin(commands::migrate_user_namespace(ctx)),
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(events::event_handler(ctx, event, framework, data))
+            },
             ..Default::default()
         })
         .setup(|ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                tokio::spawn(events::run_clock_scheduler(
+                    ctx.clone(),
+                    database.clone(),
+                    music_dir.clone(),
+                ));
                 Ok(Data {
                     db: database,
                     music_dir,
-                    track_list: Arc::new(Mutex::new(vec![])),
                 })
             })
         })
@@ -0,0 +1,231 @@
+//! Colours people can give clocks and themes: CSS colour names and hex codes.
+//!
+//! Colours end up in the SVG a clock is drawn from, so anything else is turned away rather than drawn.
+
+/// Every colour name CSS knows, apart from `transparent`, which would hide whatever it colours.
+static COLOR_NAMES: &[&str] = &[
+    "aliceblue",
+    "antiquewhite",
+    "aqua",
+    "aquamarine",
+    "azure",
+    "beige",
+    "bisque",
+    "black",
+    "blanchedalmond",
+    "blue",
+    "blueviolet",
+    "brown",
+    "burlywood",
+    "cadetblue",
+    "chartreuse",
+    "chocolate",
+    "coral",
+    "cornflowerblue",
+    "cornsilk",
+    "crimson",
+    "cyan",
+    "darkblue",
+    "darkcyan",
+    "darkgoldenrod",
+    "darkgray",
+    "darkgreen",
+    "darkgrey",
+    "darkkhaki",
+    "darkmagenta",
+    "darkolivegreen",
+    "darkorange",
+    "darkorchid",
+    "darkred",
+    "darksalmon",
+    "darkseagreen",
+    "darkslateblue",
+    "darkslategray",
+    "darkslategrey",
+    "darkturquoise",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dimgray",
+    "dimgrey",
+    "dodgerblue",
+    "firebrick",
+    "floralwhite",
+    "forestgreen",
+    "fuchsia",
+    "gainsboro",
+    "ghostwhite",
+    "gold",
+    "goldenrod",
+    "gray",
+    "green",
+    "greenyellow",
+    "grey",
+    "honeydew",
+    "hotpink",
+    "indianred",
+    "indigo",
+    "ivory",
+    "khaki",
+    "lavender",
+    "lavenderblush",
+    "lawngreen",
+    "lemonchiffon",
+    "lightblue",
+    "lightcoral",
+    "lightcyan",
+    "lightgoldenrodyellow",
+    "lightgray",
+    "lightgreen",
+    "lightgrey",
+    "lightpink",
+    "lightsalmon",
+    "lightseagreen",
+    "lightskyblue",
+    "lightslategray",
+    "lightslategrey",
+    "lightsteelblue",
+    "lightyellow",
+    "lime",
+    "limegreen",
+    "linen",
+    "magenta",
+    "maroon",
+    "mediumaquamarine",
+    "mediumblue",
+    "mediumorchid",
+    "mediumpurple",
+    "mediumseagreen",
+    "mediumslateblue",
+    "mediumspringgreen",
+    "mediumturquoise",
+    "mediumvioletred",
+    "midnightblue",
+    "mintcream",
+    "mistyrose",
+    "moccasin",
+    "navajowhite",
+    "navy",
+    "oldlace",
+    "olive",
+    "olivedrab",
+    "orange",
+    "orangered",
+    "orchid",
+    "palegoldenrod",
+    "palegreen",
+    "paleturquoise",
+    "palevioletred",
+    "papayawhip",
+    "peachpuff",
+    "peru",
+    "pink",
+    "plum",
+    "powderblue",
+    "purple",
+    "rebeccapurple",
+    "red",
+    "rosybrown",
+    "royalblue",
+    "saddlebrown",
+    "salmon",
+    "sandybrown",
+    "seagreen",
+    "seashell",
+    "sienna",
+    "silver",
+    "skyblue",
+    "slateblue",
+    "slategray",
+    "slategrey",
+    "snow",
+    "springgreen",
+    "steelblue",
+    "tan",
+    "teal",
+    "thistle",
+    "tomato",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "whitesmoke",
+    "yellow",
+    "yellowgreen",
+];
+
+/// A typo this many edits or fewer from a colour name is suggested as that name.
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// Reads a colour name like `Crimson` or a hex code like `#FF5733`, `#f53` or `ff5733`.
+/// Returns the colour in lower case, with hex codes starting with `#`, or an error explaining what went wrong.
+pub fn parse_color(input: &str) -> Result<String, String> {
+    let color = input.trim().to_ascii_lowercase();
+    let hex = color.strip_prefix('#').unwrap_or(&color);
+    if matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(format!("#{hex}"));
+    }
+    let name: String = color.chars().filter(|c| !c.is_whitespace()).collect();
+    if COLOR_NAMES.contains(&name.as_str()) {
+        return Ok(name);
+    }
+
+    let hint = "Use a CSS colour name like `crimson` or a hex code like `#FF5733`.";
+    if color.starts_with('#') {
+        return Err(format!(
+            "`{}` is not a hex code. Hex codes have 3 or 6 digits from 0-9 and A-F. {hint}",
+            input.trim()
+        ));
+    }
+    match closest_color_name(&name) {
+        Some(suggestion) => Err(format!(
+            "`{}` is not a colour. Did you mean `{suggestion}`? {hint}",
+            input.trim()
+        )),
+        None => Err(format!("`{}` is not a colour. {hint}", input.trim())),
+    }
+}
+
+/// The colour name nearest to `name`, if it's close enough to be a typo.
+fn closest_color_name(name: &str) -> Option<&'static str> {
+    COLOR_NAMES
+        .iter()
+        .map(|color| (edit_distance(name, color), *color))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE.min(name.len() / 2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, color)| color)
+}
+
+/// How many letters have to be added, removed or swapped for another to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color(" Crimson ").as_deref(), Ok("crimson"));
+    assert_eq!(
+        parse_color("dark slate blue").as_deref(),
+        Ok("darkslateblue")
+    );
+    assert_eq!(parse_color("#FF5733").as_deref(), Ok("#ff5733"));
+    assert_eq!(parse_color("a0f").as_deref(), Ok("#a0f"));
+    assert!(parse_color("#12345").is_err());
+    assert!(parse_color("transparent").is_err());
+    assert!(parse_color("red\" onload=\"alert(1)").is_err());
+
+    let typo = parse_color("crimsom").unwrap_err();
+    assert!(typo.contains("Did you mean `crimson`?"), "{typo}");
+    let nonsense = parse_color("xyzzy").unwrap_err();
+    assert!(!nonsense.contains("Did you mean"), "{nonsense}");
+}
@@ -1,12 +1,63 @@
 use handlebars::Handlebars;
-use resvg::usvg::Options;
-use resvg::{render, tiny_skia::Pixmap, usvg::Tree};
+use resvg::usvg::{Options, fontdb};
+use resvg::{
+    render,
+    tiny_skia::Pixmap,
+    usvg::{Transform, Tree},
+};
 use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::sync::{Arc, LazyLock};
 
-use crate::db::ProgressClock;
+use crate::db::{ClockStyle, ClockTheme, ProgressClock};
+
+mod color;
+
+pub use color::parse_color;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
 
 static SVG_FILE: &str = include_str!("./source.svg");
+static BOARD_FILE: &str = include_str!("./board.svg");
+static GROUP_FILE: &str = include_str!("./group.svg");
+static SEGMENTS_FILE: &str = include_str!("./segments.svg");
+
+/// Width of a clock in the units images are laid out in. Images are scaled from these to the theme's size.
+const CLOCK_WIDTH: i32 = 200;
+/// Radius of a pie or ring clock.
+const CLOCK_RADIUS: f32 = 90.0;
+/// Radius of the empty middle of a ring clock.
+const RING_INNER_RADIUS: f32 = 58.0;
+/// Height of a bar clock.
+const BAR_HEIGHT: i32 = 56;
+/// Space around the segments of a bar clock.
+const BAR_PADDING: i32 = 8;
+/// Smallest and largest widths a theme can draw a clock at, in pixels.
+const MIN_CLOCK_WIDTH: u32 = 100;
+const MAX_CLOCK_WIDTH: u32 = 400;
+/// Clocks in each row of a board.
+const BOARD_COLUMNS: usize = 4;
+/// Room under each clock on a board for its labels.
+const LABEL_HEIGHT: i32 = 56;
+/// Names longer than this are cut short on a board, so they don't run into the next clock's.
+const MAX_LABEL_CHARS: usize = 18;
+/// Child clocks are drawn at this fraction of a clock's size, under their parent.
+const CHILD_SCALE: f32 = 0.5;
+/// Child clocks in each row under their parent.
+const CHILD_COLUMNS: usize = 6;
+/// Room under each child clock for its labels.
+const CHILD_LABEL_HEIGHT: i32 = 40;
+/// Child clock names longer than this are cut short.
+const MAX_CHILD_LABEL_CHARS: usize = 12;
+/// Space between two racing clocks, for the "vs" between them.
+const RACE_GAP: i32 = 80;
+
+/// Fonts for labels. Loading them is slow, so it only happens once.
+static FONTS: LazyLock<Arc<fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
 
 #[derive(Debug)]
 enum RenderDataTypes {
@@ -14,6 +65,7 @@ enum RenderDataTypes {
     Float(f32),
     FloatList(Vec<f32>),
     String(String),
+    List(Vec<HashMap<&'static str, RenderDataTypes>>),
 }
 
 impl serde::Serialize for RenderDataTypes {
@@ -26,71 +78,705 @@ impl serde::Serialize for RenderDataTypes {
             Self::Int(int) => int.serialize(serializer),
             Self::Float(float) => float.serialize(serializer),
             Self::String(string) => string.serialize(serializer),
+            Self::List(items) => items.serialize(serializer),
         }
     }
 }
 
-pub fn render_progress_clock(
-    clock: &ProgressClock,
-) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
-    if clock.segments_filled > clock.segments {
-        return Err(String::from("segments filled must be lesser than existing segments.").into());
+/// The colour `clock` is filled with. Clocks without a colour, or with one that was never valid, take one from
+/// the theme's palette, picked by name so a clock keeps its colour from one render to the next.
+fn shade_color(clock: &ProgressClock, theme: &ClockTheme) -> String {
+    if let Some(color) = clock
+        .color
+        .as_deref()
+        .and_then(|color| parse_color(color).ok())
+    {
+        return color;
     }
+    let hash = clock.name.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte.into())
+    });
+    theme
+        .palette
+        .get(hash % theme.palette.len().max(1))
+        .cloned()
+        .unwrap_or(String::from("green"))
+}
 
-    let mut handlebars = Handlebars::new();
+/// The colour the other side of a tug of war fills `clock` with, kept apart from `clock`'s own colour.
+fn opposed_color(clock: &ProgressClock, opposed: &ProgressClock, theme: &ClockTheme) -> String {
+    let (color, opposed_color) = (shade_color(clock, theme), shade_color(opposed, theme));
+    if opposed_color != color || opposed.color.is_some() {
+        return opposed_color;
+    }
+    theme
+        .palette
+        .iter()
+        .find(|other| **other != color)
+        .cloned()
+        .unwrap_or(String::from("crimson"))
+}
 
-    handlebars
-        .register_template_string("progress_clock", SVG_FILE)
-        .map_err(|e| e.to_string())?;
+/// Fills in the SVG for a single clock. `id` keeps its definitions apart from other clocks' in the same document.
+fn progress_clock_svg(
+    clock: &ProgressClock,
+    id: &str,
+    theme: &ClockTheme,
+) -> Result<String, Error> {
+    clock_svg(clock, None, id, theme, None)
+}
 
-    let angle_segment: f32 = 360f32 / f32::from(clock.segments);
-    let mut spoke_angles: Vec<f32> = vec![angle_segment];
-    let mut shade_angles: Vec<f32> = vec![];
-    for i in 0..clock.segments {
-        spoke_angles.push(angle_segment * f32::from(i));
-        if i < clock.segments_filled {
-            // default angle of a shade object is 90deg. Need to use that as an offset.
-            shade_angles.push(angle_segment * f32::from(i) - 90f32);
-        }
+/// Fills in the SVG for a clock, with `opposed` filling it anticlockwise from the top in its own colour, for a
+/// tug of war. `background` is only given for a clock drawn on its own, as boards and groups draw their own.
+fn clock_svg(
+    clock: &ProgressClock,
+    opposed: Option<&ProgressClock>,
+    id: &str,
+    theme: &ClockTheme,
+    background: Option<&str>,
+) -> Result<String, Error> {
+    let opposed_filled = opposed.map_or(0, |opposed| opposed.segments_filled);
+    if clock.segments_filled > clock.segments
+        || opposed_filled > clock.segments - clock.segments_filled
+    {
+        return Err(String::from("segments filled must be lesser than existing segments.").into());
     }
 
     let mut render_data = HashMap::new();
-    let radius = 90f32;
-    let width = 200;
-    let height = 200;
-    render_data.insert("spoke_angle", RenderDataTypes::FloatList(spoke_angles));
-    render_data.insert("shade_angle", RenderDataTypes::FloatList(shade_angles));
+    let width = CLOCK_WIDTH;
+    let height = clock_height(theme.style);
+    render_data.insert("id", RenderDataTypes::String(id.to_owned()));
+    let shade_color = shade_color(clock, theme);
+    let opposed_color = opposed.map_or(String::from("none"), |opposed| {
+        opposed_color(clock, opposed, theme)
+    });
     render_data.insert(
-        "shade_color",
-        RenderDataTypes::String(clock.color.clone().unwrap_or(String::from("green"))),
+        "spoke_color",
+        RenderDataTypes::String(theme.spoke_color.clone()),
     );
+    render_data.insert(
+        "shade_opacity",
+        RenderDataTypes::Float(theme.shade_opacity.clamp(0.0, 1.0)),
+    );
+    if let Some(background) = background {
+        render_data.insert("background", RenderDataTypes::String(background.to_owned()));
+    }
     render_data.insert("height", RenderDataTypes::Int(height));
     render_data.insert("width", RenderDataTypes::Int(width));
-    render_data.insert("cx", RenderDataTypes::Int(width / 2));
-    render_data.insert("cy", RenderDataTypes::Int(height / 2));
-    render_data.insert("radius", RenderDataTypes::Float(radius));
-    render_data.insert(
-        "rcostheta",
-        RenderDataTypes::Float(radius * (angle_segment * PI / 180f32).cos()),
+
+    let template = match theme.style {
+        ClockStyle::Pie => {
+            let angle_segment: f32 = 360f32 / f32::from(clock.segments);
+            let mut spoke_angles: Vec<f32> = vec![angle_segment];
+            let mut shade_angles: Vec<f32> = vec![];
+            let mut opposed_shade_angles: Vec<f32> = vec![];
+            for i in 0..clock.segments {
+                spoke_angles.push(angle_segment * f32::from(i));
+                if i < clock.segments_filled {
+                    // default angle of a shade object is 90deg. Need to use that as an offset.
+                    shade_angles.push(angle_segment * f32::from(i) - 90f32);
+                }
+                if i >= clock.segments - opposed_filled {
+                    opposed_shade_angles.push(angle_segment * f32::from(i) - 90f32);
+                }
+            }
+
+            let radius = CLOCK_RADIUS;
+            render_data.insert("spoke_angle", RenderDataTypes::FloatList(spoke_angles));
+            render_data.insert("shade_angle", RenderDataTypes::FloatList(shade_angles));
+            render_data.insert(
+                "opposed_shade_angle",
+                RenderDataTypes::FloatList(opposed_shade_angles),
+            );
+            render_data.insert("opposed_color", RenderDataTypes::String(opposed_color));
+            render_data.insert("shade_color", RenderDataTypes::String(shade_color));
+            render_data.insert("cx", RenderDataTypes::Int(width / 2));
+            render_data.insert("cy", RenderDataTypes::Int(height / 2));
+            render_data.insert("radius", RenderDataTypes::Float(radius));
+            render_data.insert(
+                "rcostheta",
+                RenderDataTypes::Float(radius * (angle_segment * PI / 180f32).cos()),
+            );
+            render_data.insert(
+                "rsintheta",
+                RenderDataTypes::Float(radius * (angle_segment * PI / 180f32).sin()),
+            );
+            SVG_FILE
+        }
+        ClockStyle::Ring | ClockStyle::Bar => {
+            let segments = (0..clock.segments)
+                .map(|i| {
+                    let fill = if i < clock.segments_filled {
+                        shade_color.clone()
+                    } else if i >= clock.segments - opposed_filled {
+                        opposed_color.clone()
+                    } else {
+                        String::from("none")
+                    };
+                    HashMap::from([
+                        (
+                            "d",
+                            RenderDataTypes::String(segment_outline(
+                                theme.style,
+                                i,
+                                clock.segments,
+                            )),
+                        ),
+                        ("fill", RenderDataTypes::String(fill)),
+                    ])
+                })
+                .collect();
+            render_data.insert("segments", RenderDataTypes::List(segments));
+            SEGMENTS_FILE
+        }
+    };
+
+    let mut handlebars = Handlebars::new();
+    handlebars
+        .register_template_string("progress_clock", template)
+        .map_err(|e| e.to_string())?;
+    handlebars
+        .render("progress_clock", &render_data)
+        .map_err(|e| e.to_string().into())
+}
+
+/// How tall a clock drawn in `style` is, in layout units.
+fn clock_height(style: ClockStyle) -> i32 {
+    match style {
+        ClockStyle::Pie | ClockStyle::Ring => CLOCK_WIDTH,
+        ClockStyle::Bar => BAR_HEIGHT,
+    }
+}
+
+/// The outline of segment `i` of a ring or bar clock with `segments` segments, as SVG path data.
+/// Ring segments go clockwise from the top, and bar segments from left to right.
+fn segment_outline(style: ClockStyle, i: u8, segments: u8) -> String {
+    if style == ClockStyle::Bar {
+        let width = (CLOCK_WIDTH - 2 * BAR_PADDING) as f32 / f32::from(segments);
+        let x = BAR_PADDING as f32 + width * f32::from(i);
+        let height = BAR_HEIGHT - 2 * BAR_PADDING;
+        return format!("M {x} {BAR_PADDING} h {width} v {height} h -{width} Z");
+    }
+
+    let center = (CLOCK_WIDTH / 2) as f32;
+    let point = |radius: f32, degrees: f32| {
+        let radians = degrees.to_radians();
+        format!(
+            "{:.2} {:.2}",
+            center + radius * radians.cos(),
+            center + radius * radians.sin()
+        )
+    };
+    let angle = 360f32 / f32::from(segments);
+    let start = angle * f32::from(i) - 90f32;
+    let (middle, end) = (start + angle / 2f32, start + angle);
+    // each edge is drawn as two arcs, so a clock with one segment still has a whole ring to draw.
+    let (outer, inner) = (CLOCK_RADIUS, RING_INNER_RADIUS);
+    format!(
+        "M {} A {outer} {outer} 0 0 1 {} A {outer} {outer} 0 0 1 {} \
+        L {} A {inner} {inner} 0 0 0 {} A {inner} {inner} 0 0 0 {} Z",
+        point(outer, start),
+        point(outer, middle),
+        point(outer, end),
+        point(inner, end),
+        point(inner, middle),
+        point(inner, start),
+    )
+}
+
+/// Draws an SVG document laid out `width` by `height` units into a PNG image, scaled up or down so each clock
+/// is as wide as the theme asks.
+fn rasterize(
+    svg_source: String,
+    width: i32,
+    height: i32,
+    theme: &ClockTheme,
+) -> Result<Vec<u8>, Error> {
+    let options = Options {
+        fontdb: FONTS.clone(),
+        ..Options::default()
+    };
+    let tree = Tree::from_data(&svg_source.into_bytes(), &options)?;
+
+    let scale = theme.size.clamp(MIN_CLOCK_WIDTH, MAX_CLOCK_WIDTH) as f32 / CLOCK_WIDTH as f32;
+    let mut pixmap = Pixmap::new(
+        (width as f32 * scale).round() as u32,
+        (height as f32 * scale).round() as u32,
+    )
+    .ok_or("Could not get mutable pixmap.")?;
+    render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
     );
+
+    pixmap.encode_png().map_err(|err| err.into())
+}
+
+pub fn render_progress_clock(clock: &ProgressClock, theme: &ClockTheme) -> Result<Vec<u8>, Error> {
+    rasterize(
+        clock_svg(clock, None, "clock", theme, theme.background.as_deref())?,
+        CLOCK_WIDTH,
+        clock_height(theme.style),
+        theme,
+    )
+}
+
+/// Shortens `name` to fit under a clock on a board.
+fn board_label(name: &str) -> String {
+    shorten_label(name, MAX_LABEL_CHARS)
+}
+
+/// Shortens `name` to at most `max_chars` characters, marking where it was cut.
+fn shorten_label(name: &str, max_chars: usize) -> String {
+    if name.chars().count() <= max_chars {
+        return name.to_owned();
+    }
+    let mut label: String = name.chars().take(max_chars - 1).collect();
+    label.push('…');
+    label
+}
+
+/// Adds the label colour and background of a board or group to what its template is filled in with.
+fn insert_theme(render_data: &mut HashMap<&'static str, RenderDataTypes>, theme: &ClockTheme) {
     render_data.insert(
-        "rsintheta",
-        RenderDataTypes::Float(radius * (angle_segment * PI / 180f32).sin()),
+        "label_color",
+        RenderDataTypes::String(theme.spoke_color.clone()),
     );
+    if let Some(background) = &theme.background {
+        render_data.insert("background", RenderDataTypes::String(background.clone()));
+    }
+}
+
+/// Draws `clocks` in a grid in one image, each with its name and progress underneath.
+pub fn render_clock_board(clocks: &[ProgressClock], theme: &ClockTheme) -> Result<Vec<u8>, Error> {
+    if clocks.is_empty() {
+        return Err(String::from("a board needs at least one clock.").into());
+    }
+
+    let mut handlebars = Handlebars::new();
+    handlebars
+        .register_template_string("board", BOARD_FILE)
+        .map_err(|e| e.to_string())?;
+
+    let clock_height = clock_height(theme.style);
+    let mut cells = vec![];
+    for (idx, clock) in clocks.iter().enumerate() {
+        let mut cell = HashMap::new();
+        cell.insert(
+            "x",
+            RenderDataTypes::Int((idx % BOARD_COLUMNS) as i32 * CLOCK_WIDTH),
+        );
+        cell.insert(
+            "y",
+            RenderDataTypes::Int((idx / BOARD_COLUMNS) as i32 * (clock_height + LABEL_HEIGHT)),
+        );
+        cell.insert(
+            "clock",
+            RenderDataTypes::String(progress_clock_svg(clock, &format!("clock{idx}"), theme)?),
+        );
+        cell.insert("name", RenderDataTypes::String(board_label(&clock.name)));
+        cell.insert(
+            "progress",
+            RenderDataTypes::String(format!("{}/{}", clock.segments_filled, clock.segments)),
+        );
+        cells.push(cell);
+    }
+
+    let columns = clocks.len().min(BOARD_COLUMNS) as i32;
+    let rows = clocks.len().div_ceil(BOARD_COLUMNS) as i32;
+    let width = columns * CLOCK_WIDTH;
+    let height = rows * (clock_height + LABEL_HEIGHT);
+
+    let mut render_data = HashMap::new();
+    render_data.insert("width", RenderDataTypes::Int(width));
+    render_data.insert("height", RenderDataTypes::Int(height));
+    render_data.insert("label_x", RenderDataTypes::Int(CLOCK_WIDTH / 2));
+    render_data.insert("name_y", RenderDataTypes::Int(clock_height + 20));
+    render_data.insert("progress_y", RenderDataTypes::Int(clock_height + 44));
+    render_data.insert("cells", RenderDataTypes::List(cells));
+    insert_theme(&mut render_data, theme);
 
     let svg_source = handlebars
-        .render("progress_clock", &render_data)
+        .render("board", &render_data)
         .map_err(|e| e.to_string())?;
 
-    dbg!("{}", &svg_source);
+    rasterize(svg_source, width, height, theme)
+}
 
-    let mut pixmap = Pixmap::new(200, 200).expect("Could not get mutable pixmap.");
-    render(
-        &Tree::from_data(&svg_source.into_bytes(), &Options::default())
-            .expect("Could not build tree from SVG source"),
-        resvg::usvg::Transform::default(),
-        &mut pixmap.as_mut(),
+/// Clocks drawn together in one image because they're linked.
+pub enum ClockGroup<'a> {
+    /// two clocks side by side, racing to fill first.
+    Race(&'a ProgressClock, &'a ProgressClock),
+    /// one clock, filled clockwise by the first side and anticlockwise by the second.
+    TugOfWar(&'a ProgressClock, &'a ProgressClock),
+    /// a parent clock, with its children smaller underneath.
+    Family(&'a ProgressClock, &'a [ProgressClock]),
+}
+
+fn group_cell(x: i32, y: i32, scale: f32, clock: String) -> HashMap<&'static str, RenderDataTypes> {
+    HashMap::from([
+        ("x", RenderDataTypes::Int(x)),
+        ("y", RenderDataTypes::Int(y)),
+        ("scale", RenderDataTypes::Float(scale)),
+        ("clock", RenderDataTypes::String(clock)),
+    ])
+}
+
+fn group_label(
+    x: i32,
+    y: i32,
+    size: i32,
+    bold: bool,
+    color: &str,
+    text: String,
+) -> HashMap<&'static str, RenderDataTypes> {
+    HashMap::from([
+        ("x", RenderDataTypes::Int(x)),
+        ("y", RenderDataTypes::Int(y)),
+        ("size", RenderDataTypes::Int(size)),
+        (
+            "weight",
+            RenderDataTypes::String(String::from(if bold { "bold" } else { "normal" })),
+        ),
+        ("color", RenderDataTypes::String(color.to_owned())),
+        ("text", RenderDataTypes::String(text)),
+    ])
+}
+
+/// The name and progress labels under a full size clock centred on `x`, whose bottom is at `y`.
+fn clock_labels(
+    x: i32,
+    y: i32,
+    clock: &ProgressClock,
+    color: &str,
+) -> [HashMap<&'static str, RenderDataTypes>; 2] {
+    [
+        group_label(x, y + 20, 20, true, color, board_label(&clock.name)),
+        group_label(
+            x,
+            y + 44,
+            16,
+            false,
+            color,
+            format!("{}/{}", clock.segments_filled, clock.segments),
+        ),
+    ]
+}
+
+/// Draws linked clocks together in one image, each with its name and progress underneath.
+pub fn render_clock_group(group: &ClockGroup, theme: &ClockTheme) -> Result<Vec<u8>, Error> {
+    let mut handlebars = Handlebars::new();
+    handlebars
+        .register_template_string("group", GROUP_FILE)
+        .map_err(|e| e.to_string())?;
+
+    let mut cells = vec![];
+    let mut labels = vec![];
+    let label_color = theme.spoke_color.as_str();
+    let clock_height = clock_height(theme.style);
+    let (width, height) = match group {
+        ClockGroup::Race(clock, rival) => {
+            let rival_x = CLOCK_WIDTH + RACE_GAP;
+            cells.push(group_cell(
+                0,
+                0,
+                1.0,
+                progress_clock_svg(clock, "clock", theme)?,
+            ));
+            cells.push(group_cell(
+                rival_x,
+                0,
+                1.0,
+                progress_clock_svg(rival, "rival", theme)?,
+            ));
+            labels.extend(clock_labels(
+                CLOCK_WIDTH / 2,
+                clock_height,
+                clock,
+                label_color,
+            ));
+            labels.extend(clock_labels(
+                rival_x + CLOCK_WIDTH / 2,
+                clock_height,
+                rival,
+                label_color,
+            ));
+            labels.push(group_label(
+                CLOCK_WIDTH + RACE_GAP / 2,
+                clock_height / 2 + 10,
+                28,
+                true,
+                label_color,
+                String::from("vs"),
+            ));
+            (2 * CLOCK_WIDTH + RACE_GAP, clock_height + LABEL_HEIGHT)
+        }
+        ClockGroup::TugOfWar(clock, opponent) => {
+            cells.push(group_cell(
+                CLOCK_WIDTH / 2,
+                0,
+                1.0,
+                clock_svg(clock, Some(opponent), "clock", theme, None)?,
+            ));
+            // each side is labelled in its own colour, so it's clear which shade is whose.
+            let color = shade_color(clock, theme);
+            let opponent_color = opposed_color(clock, opponent, theme);
+            labels.extend(clock_labels(CLOCK_WIDTH / 2, clock_height, clock, &color));
+            labels.extend(clock_labels(
+                3 * CLOCK_WIDTH / 2,
+                clock_height,
+                opponent,
+                &opponent_color,
+            ));
+            (2 * CLOCK_WIDTH, clock_height + LABEL_HEIGHT)
+        }
+        ClockGroup::Family(parent, children) => {
+            let child_width = (CLOCK_WIDTH as f32 * CHILD_SCALE) as i32;
+            let child_height = (clock_height as f32 * CHILD_SCALE) as i32;
+            let columns = children.len().min(CHILD_COLUMNS) as i32;
+            let rows = children.len().div_ceil(CHILD_COLUMNS) as i32;
+            let width = CLOCK_WIDTH.max(columns * child_width);
+
+            cells.push(group_cell(
+                (width - CLOCK_WIDTH) / 2,
+                0,
+                1.0,
+                progress_clock_svg(parent, "parent", theme)?,
+            ));
+            labels.extend(clock_labels(width / 2, clock_height, parent, label_color));
+
+            let left = (width - columns * child_width) / 2;
+            for (idx, child) in children.iter().enumerate() {
+                let x = left + (idx % CHILD_COLUMNS) as i32 * child_width;
+                let y = clock_height
+                    + LABEL_HEIGHT
+                    + (idx / CHILD_COLUMNS) as i32 * (child_height + CHILD_LABEL_HEIGHT);
+                cells.push(group_cell(
+                    x,
+                    y,
+                    CHILD_SCALE,
+                    progress_clock_svg(child, &format!("child{idx}"), theme)?,
+                ));
+                labels.push(group_label(
+                    x + child_width / 2,
+                    y + child_height + 16,
+                    13,
+                    true,
+                    label_color,
+                    shorten_label(&child.name, MAX_CHILD_LABEL_CHARS),
+                ));
+                labels.push(group_label(
+                    x + child_width / 2,
+                    y + child_height + 32,
+                    11,
+                    false,
+                    label_color,
+                    format!("{}/{}", child.segments_filled, child.segments),
+                ));
+            }
+            (
+                width,
+                clock_height + LABEL_HEIGHT + rows * (child_height + CHILD_LABEL_HEIGHT),
+            )
+        }
+    };
+
+    let mut render_data = HashMap::new();
+    render_data.insert("width", RenderDataTypes::Int(width));
+    render_data.insert("height", RenderDataTypes::Int(height));
+    render_data.insert("cells", RenderDataTypes::List(cells));
+    render_data.insert("labels", RenderDataTypes::List(labels));
+    insert_theme(&mut render_data, theme);
+
+    let svg_source = handlebars
+        .render("group", &render_data)
+        .map_err(|e| e.to_string())?;
+
+    rasterize(svg_source, width, height, theme)
+}
+
+#[test]
+fn test_render_clock_board() -> Result<(), Error> {
+    let clock = |name: &str, segments_filled: u8| ProgressClock {
+        namespace: String::from("guild"),
+        name: name.to_owned(),
+        segments: 4,
+        segments_filled,
+        ephemeral: false,
+        color: Some(String::from("crimson")),
+    };
+    let clocks: Vec<ProgressClock> = (0..5)
+        .map(|idx| clock(&format!("clock <{idx}> & co"), idx % 5))
+        .collect();
+
+    let png = render_clock_board(&clocks, &ClockTheme::default())?;
+    let board = Pixmap::decode_png(&png)?;
+    assert_eq!(
+        (board.width(), board.height()),
+        (
+            4 * CLOCK_WIDTH as u32,
+            2 * (CLOCK_WIDTH + LABEL_HEIGHT) as u32
+        )
+    );
+    assert!(render_clock_board(&[], &ClockTheme::default()).is_err());
+    assert_eq!(
+        board_label("a very long clock name indeed"),
+        "a very long clock…"
     );
+    Ok(())
+}
 
-    pixmap.encode_png().map_err(|err| err.into())
+#[test]
+fn test_render_clock_group() -> Result<(), Error> {
+    let clock = |name: &str, segments_filled: u8| ProgressClock {
+        namespace: String::from("guild"),
+        name: name.to_owned(),
+        segments: 6,
+        segments_filled,
+        ephemeral: false,
+        color: None,
+    };
+    let theme = ClockTheme::default();
+    let size = |png: Vec<u8>| -> Result<(u32, u32), Error> {
+        let image = Pixmap::decode_png(&png)?;
+        Ok((image.width(), image.height()))
+    };
+
+    let (alarm, escape) = (clock("alarm", 4), clock("escape", 2));
+    assert_eq!(
+        size(render_clock_group(
+            &ClockGroup::TugOfWar(&alarm, &escape),
+            &theme
+        )?)?,
+        (2 * CLOCK_WIDTH as u32, (CLOCK_WIDTH + LABEL_HEIGHT) as u32)
+    );
+    // the two sides of a tug of war can't fill more than the clock.
+    let overfilled = clock("escape", 3);
+    assert!(render_clock_group(&ClockGroup::TugOfWar(&alarm, &overfilled), &theme).is_err());
+
+    let children: Vec<ProgressClock> = (0..7)
+        .map(|idx| clock(&format!("step {idx}"), idx % 6))
+        .collect();
+    assert_eq!(
+        size(render_clock_group(
+            &ClockGroup::Family(&alarm, &children),
+            &theme
+        )?)?,
+        (
+            600,
+            (CLOCK_WIDTH + LABEL_HEIGHT + 2 * (100 + CHILD_LABEL_HEIGHT)) as u32
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_render_with_theme() -> Result<(), Error> {
+    let clock = |name: &str, color: Option<&str>| ProgressClock {
+        namespace: String::from("guild"),
+        name: name.to_owned(),
+        segments: 4,
+        segments_filled: 2,
+        ephemeral: false,
+        color: color.map(str::to_owned),
+    };
+    let theme = ClockTheme {
+        palette: vec![String::from("#b3202a"), String::from("#c9a227")],
+        spoke_color: String::from("#e8e0cc"),
+        background: Some(String::from("#16161a")),
+        ..ClockTheme::default()
+    };
+
+    // clocks without a colour, or with one that isn't, take the same palette colour every time.
+    let uncoloured = shade_color(&clock("heist", None), &theme);
+    assert!(theme.palette.contains(&uncoloured));
+    assert_eq!(
+        shade_color(&clock("heist", Some("grene")), &theme),
+        uncoloured
+    );
+    assert_eq!(
+        shade_color(&clock("heist", Some("Crimson")), &theme),
+        "crimson"
+    );
+    // the two sides of a tug of war never share a colour.
+    let (first, second) = (clock("heist", None), clock("heist", None));
+    assert_ne!(opposed_color(&first, &second, &theme), uncoloured);
+
+    let corner = |png: Vec<u8>| -> Result<u8, Error> {
+        let image = Pixmap::decode_png(&png)?;
+        Ok(image.pixel(0, 0).ok_or("no pixel")?.alpha())
+    };
+    assert_eq!(corner(render_progress_clock(&first, &theme)?)?, 255);
+    assert_eq!(
+        corner(render_progress_clock(&first, &ClockTheme::default())?)?,
+        0
+    );
+    assert_eq!(corner(render_clock_board(&[first], &theme)?)?, 255);
+    Ok(())
+}
+
+#[test]
+fn test_render_clock_styles() -> Result<(), Error> {
+    let clock = |name: &str, segments: u8, segments_filled: u8| ProgressClock {
+        namespace: String::from("guild"),
+        name: name.to_owned(),
+        segments,
+        segments_filled,
+        ephemeral: false,
+        color: None,
+    };
+    let size = |png: Vec<u8>| -> Result<(u32, u32), Error> {
+        let image = Pixmap::decode_png(&png)?;
+        Ok((image.width(), image.height()))
+    };
+    let theme = |style: ClockStyle, size: u32| ClockTheme {
+        style,
+        size,
+        ..ClockTheme::default()
+    };
+
+    let heist = clock("heist", 6, 2);
+    assert_eq!(
+        size(render_progress_clock(
+            &heist,
+            &theme(ClockStyle::Ring, 100)
+        )?)?,
+        (100, 100)
+    );
+    assert_eq!(
+        size(render_progress_clock(&heist, &theme(ClockStyle::Bar, 300))?)?,
+        (300, 84)
+    );
+    // sizes are kept to what Discord shows well.
+    assert_eq!(
+        size(render_progress_clock(
+            &heist,
+            &theme(ClockStyle::Pie, 5000)
+        )?)?,
+        (400, 400)
+    );
+    assert_eq!(
+        size(render_clock_board(
+            &[heist.clone(), clock("escape", 4, 1)],
+            &theme(ClockStyle::Bar, 200)
+        )?)?,
+        (400, (BAR_HEIGHT + LABEL_HEIGHT) as u32)
+    );
+
+    // a ring with a single segment still draws its whole circle.
+    assert!(segment_outline(ClockStyle::Ring, 0, 1).contains("A 90 90 0 0 1 100.00 190.00"));
+    for style in [ClockStyle::Ring, ClockStyle::Bar] {
+        let (alarm, escape) = (clock("alarm", 6, 4), clock("escape", 6, 2));
+        render_clock_group(&ClockGroup::TugOfWar(&alarm, &escape), &theme(style, 200))?;
+        let overfilled = clock("escape", 6, 3);
+        assert!(
+            render_clock_group(
+                &ClockGroup::TugOfWar(&alarm, &overfilled),
+                &theme(style, 200)
+            )
+            .is_err()
+        );
+    }
+    Ok(())
 }
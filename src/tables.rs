@@ -0,0 +1,231 @@
+//! Parsing and rolling random tables.
+//!
+//! A table is uploaded as CSV or as a markdown table, one entry per line:
+//!
+//! ```text
+//! | d6  | Encounter                  |
+//! |-----|----------------------------|
+//! | 1-3 | 2d4 goblins                |
+//! | 4-5 | A wandering merchant       |
+//! | 6   | A dragon with {treasure}   |
+//! ```
+//!
+//! Lines that don't start with a number or a range (headers, separators) are skipped.
+//! Dice expressions in an entry are rolled, and `{name}` rolls on the table called `name`.
+
+use crate::{
+    db::{RandomTable, TableEntry},
+    dice::{handle_dice_string, roll_dice_in_text},
+};
+
+/// How deep linked tables may nest before rolling stops, so that tables linking to each other terminate.
+const MAX_TABLE_DEPTH: usize = 8;
+/// How many rolls one roll on a table may lead to, so that entries linking several times don't multiply out of hand.
+const MAX_TABLE_ROLLS: usize = 100;
+
+/// One roll made while rolling on a table, including rolls on linked tables.
+pub struct TableRoll {
+    pub table: String,
+    pub roll: u32,
+    pub text: String,
+}
+
+/// Parses `1-3`, `1–3` or `6` into an inclusive range.
+fn parse_range(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.trim();
+    match cell.split_once(['-', '–']) {
+        Some((low, high)) => Some((low.trim().parse().ok()?, high.trim().parse().ok()?)),
+        None => {
+            let value = cell.parse().ok()?;
+            Some((value, value))
+        }
+    }
+}
+
+/// Splits a CSV or markdown line into its range cell and its entry text.
+fn split_line(line: &str) -> Option<(&str, String)> {
+    let line = line.trim().trim_start_matches('|').trim_end_matches('|');
+    let (range, text) = if line.contains('|') {
+        line.split_once('|')?
+    } else {
+        line.split_once([',', '\t'])?
+    };
+
+    let text = text.trim().trim_matches('"').replace("\"\"", "\"");
+    Some((range, text))
+}
+
+/// Builds a table out of an uploaded CSV or markdown file.
+/// The die defaults to `1dN`, where N is the end of the highest range.
+pub fn parse_table(
+    namespace: &str,
+    name: &str,
+    source: &str,
+    die: Option<String>,
+) -> Result<RandomTable, String> {
+    let mut entries: Vec<TableEntry> = source
+        .lines()
+        .filter_map(split_line)
+        .filter_map(|(range, text)| {
+            parse_range(range).map(|(low, high)| TableEntry {
+                low: low.min(high),
+                high: low.max(high),
+                text,
+            })
+        })
+        .collect();
+
+    if entries.is_empty() {
+        return Err(String::from(
+            "The table has no entries. Each line should look like `1-3 | Goblins` or `1-3,Goblins`.",
+        ));
+    }
+
+    entries.sort_by_key(|entry| entry.low);
+    for pair in entries.windows(2) {
+        if pair[1].low <= pair[0].high {
+            return Err(format!(
+                "The ranges {}-{} and {}-{} overlap.",
+                pair[0].low, pair[0].high, pair[1].low, pair[1].high
+            ));
+        }
+    }
+
+    let die = match die {
+        Some(die) => {
+            handle_dice_string(die.clone()).map_err(|_| format!("`{die}` is not a valid die."))?;
+            die
+        }
+        None => format!(
+            "1d{}",
+            entries.iter().map(|entry| entry.high).max().unwrap_or(1)
+        ),
+    };
+
+    Ok(RandomTable {
+        namespace: namespace.to_owned(),
+        name: name.to_owned(),
+        die,
+        entries,
+    })
+}
+
+/// Rolls `text`'s dice expressions and replaces every `{name}` with a roll on that table.
+fn expand_entry<F>(text: &str, lookup: &F, depth: usize, rolls: &mut Vec<TableRoll>) -> String
+where
+    F: Fn(&str) -> Option<RandomTable>,
+{
+    let mut out = String::new();
+    let mut remaining = text;
+
+    while let Some(start) = remaining.find('{') {
+        out += &roll_dice_in_text(&remaining[..start]);
+        let Some(end) = remaining[start..].find('}') else {
+            remaining = &remaining[start..];
+            break;
+        };
+
+        let link = remaining[start + 1..start + end].trim();
+        match lookup(link) {
+            Some(_) if depth >= MAX_TABLE_DEPTH => {
+                out += &format!("*({link}: too many nested tables)*")
+            }
+            Some(_) if rolls.len() >= MAX_TABLE_ROLLS => {
+                out += &format!("*({link}: too many linked rolls)*")
+            }
+            Some(table) => match roll_on(&table, lookup, depth + 1, rolls) {
+                Ok(text) => out += &text,
+                Err(message) => out += &format!("*({message})*"),
+            },
+            None => out += &format!("*(no table named {link})*"),
+        }
+        remaining = &remaining[start + end + 1..];
+    }
+
+    out += &roll_dice_in_text(remaining);
+    out
+}
+
+fn roll_on<F>(
+    table: &RandomTable,
+    lookup: &F,
+    depth: usize,
+    rolls: &mut Vec<TableRoll>,
+) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<RandomTable>,
+{
+    let roll = handle_dice_string(table.die.clone())
+        .map_err(|err| err.to_string())?
+        .first()
+        .map(|result| result.total)
+        .ok_or(format!("{} has no die to roll.", table.name))?;
+
+    let entry = table
+        .entries
+        .iter()
+        .find(|entry| entry.low <= roll && roll <= entry.high)
+        .ok_or(format!("{} has no entry for a roll of {roll}.", table.name))?;
+
+    // reserve this roll's slot so that rolls are listed in the order they were made.
+    let idx = rolls.len();
+    rolls.push(TableRoll {
+        table: table.name.clone(),
+        roll,
+        text: String::new(),
+    });
+    let text = expand_entry(&entry.text, lookup, depth, rolls);
+    rolls[idx].text = text.clone();
+    Ok(text)
+}
+
+/// Rolls on `table`, following links to other tables through `lookup`.
+/// Returns every roll made, starting with the roll on `table` itself.
+pub fn roll_table<F>(table: &RandomTable, lookup: F) -> Result<Vec<TableRoll>, String>
+where
+    F: Fn(&str) -> Option<RandomTable>,
+{
+    let mut rolls = Vec::new();
+    roll_on(table, &lookup, 0, &mut rolls)?;
+    Ok(rolls)
+}
+
+#[test]
+fn test_parse_table() -> Result<(), String> {
+    let markdown = "| d6 | Encounter |\n|----|----|\n| 1-3 | 2d4 goblins |\n| 4–5 | A merchant |\n| 6 | A dragon |";
+    let table = parse_table("guild", "encounters", markdown, None)?;
+    assert_eq!(table.die, "1d6");
+    assert_eq!(table.entries.len(), 3);
+    assert_eq!((table.entries[1].low, table.entries[1].high), (4, 5));
+
+    let csv = "roll,result\n1,\"A \"\"lucky\"\" coin\"\n2-4,Nothing";
+    let table = parse_table("guild", "loot", csv, Some(String::from("1d4")))?;
+    assert_eq!(table.entries[0].text, "A \"lucky\" coin");
+
+    assert!(parse_table("guild", "broken", "1-3,a\n3-4,b", None).is_err());
+    assert!(parse_table("guild", "empty", "no entries here", None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_roll_table_links() -> Result<(), String> {
+    let encounters = parse_table("guild", "encounters", "1,2d1 goblins with {loot}", None)?;
+    let rolls = roll_table(&encounters, |name| match name {
+        "loot" => parse_table("guild", "loot", "1,a shiny {loot}", None).ok(),
+        _ => None,
+    })?;
+
+    assert_eq!(rolls.len(), MAX_TABLE_DEPTH + 1);
+    assert!(rolls[0].text.starts_with("2 goblins with a shiny a shiny"));
+    assert_eq!(rolls[1].table, "loot");
+
+    // a table that links to itself several times stops before it multiplies out of hand.
+    let source = "1,{swarm} {swarm} {swarm} {swarm} {swarm}";
+    let swarm = parse_table("guild", "swarm", source, None)?;
+    let rolls = roll_table(&swarm, |name| match name {
+        "swarm" => parse_table("guild", "swarm", source, None).ok(),
+        _ => None,
+    })?;
+    assert_eq!(rolls.len(), MAX_TABLE_ROLLS);
+    Ok(())
+}